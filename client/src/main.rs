@@ -1,6 +1,17 @@
 mod manager;
-use crate::manager::{deploy_deployment, deploy_single_image, get_node_by_id};
+use crate::manager::{
+    deploy_deployment, deploy_single_image, get_node_by_id, plan_deployment, plan_single_image,
+};
 use clap::{App, Arg, ArgMatches};
+use serde_json::{json, Map, Value};
+use structs::audit_entry::AuditFilter;
+use structs::node_definition::NodeDefinition;
+use structs::rerun_request::{parse_override, RerunRequest};
+use structs::reservation_request::{
+    add_reservation_subcommand, parse_end_argument, parse_reservation_arguments,
+};
+use structs::state::STATES;
+use structs::utils::parse_local_datetime;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
@@ -13,8 +24,15 @@ async fn main() -> std::io::Result<()> {
         .subcommand(add_service_subcommand())
         .subcommand(add_deployment_subcommand())
         .subcommand(add_deploy_subcommand())
+        .subcommand(add_reservation_subcommand())
+        .subcommand(add_audit_subcommand())
+        .subcommand(add_config_subcommand())
         .get_matches();
 
+    if !manager::has_token() {
+        eprintln!("Please set CLUSTER_TOKEN to your token, the server refuses requests without it");
+        return Ok(());
+    }
     handle_subcommands(&matches).await;
     Ok(())
 }
@@ -35,6 +53,15 @@ async fn handle_subcommands(matches: &ArgMatches<'_>) {
     if let Some(matches) = matches.subcommand_matches("deploy") {
         handle_deploy_subcommand(&matches.clone()).await;
     }
+    if let Some(matches) = matches.subcommand_matches("reservation") {
+        handle_reservation_subcommand(&matches.clone()).await;
+    }
+    if let Some(matches) = matches.subcommand_matches("audit") {
+        handle_audit_subcommand(&matches.clone()).await;
+    }
+    if let Some(matches) = matches.subcommand_matches("config") {
+        handle_config_subcommand(&matches.clone()).await;
+    }
 }
 
 fn add_service_subcommand() -> App<'static, 'static> {
@@ -56,6 +83,13 @@ fn add_service_subcommand() -> App<'static, 'static> {
                         .long("group")
                         .help("group services")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .long("state")
+                        .help("only show services in this state")
+                        .possible_values(&STATES)
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -79,21 +113,27 @@ fn add_service_subcommand() -> App<'static, 'static> {
 
 async fn handle_service_subcommand(matches: &ArgMatches<'_>) {
     if let Some(submatches) = matches.subcommand_matches("list") {
-        self::manager::list_services(submatches.is_present("all"), submatches.is_present("group"))
-            .await
-            .unwrap();
+        self::manager::list_services(
+            submatches.is_present("all"),
+            submatches.is_present("group"),
+            submatches.value_of("state"),
+        )
+        .await
+        .unwrap();
     }
-    /*if let Some(matches) = matches.subcommand_matches("stop") {
+    if let Some(matches) = matches.subcommand_matches("stop") {
         if let Some(param) = matches.value_of("id") {
             if let Ok(id) = param.parse::<i64>() {
-                self::manager::stop_service(id, matches.is_present("prune"));
+                self::manager::stop_service(id, matches.is_present("prune"))
+                    .await
+                    .unwrap();
             } else {
                 eprintln!("Please provide a valid id")
             }
         } else {
             eprintln!("Please provide an id")
         }
-    }*/
+    }
 }
 
 async fn handle_node_subcommand(matches: &ArgMatches<'_>) {
@@ -102,15 +142,59 @@ async fn handle_node_subcommand(matches: &ArgMatches<'_>) {
             .await
             .unwrap();
     }
+    if let Some(submatches) = matches.subcommand_matches("history") {
+        self::manager::list_node_state_transitions(submatches.value_of("id").unwrap())
+            .await
+            .unwrap();
+    }
+    if let Some(submatches) = matches.subcommand_matches("add") {
+        let id = submatches.value_of("id").unwrap();
+        match get_node_fields(submatches).and_then(|fields| {
+            serde_json::from_value::<NodeDefinition>(fields).map_err(|e| e.to_string())
+        }) {
+            Ok(definition) => {
+                self::manager::add_node(id, &definition).await.unwrap();
+            }
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    if let Some(submatches) = matches.subcommand_matches("edit") {
+        let id = submatches.value_of("id").unwrap();
+        match get_node_fields(submatches) {
+            Ok(patch) => {
+                self::manager::edit_node(id, &patch).await.unwrap();
+            }
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    if let Some(submatches) = matches.subcommand_matches("del") {
+        self::manager::delete_node(submatches.value_of("id").unwrap())
+            .await
+            .unwrap();
+    }
 }
 
 async fn handle_deployment_subcommand(matches: &ArgMatches<'_>) {
     if let Some(submatches) = matches.subcommand_matches("list") {
-        self::manager::list_deployments(submatches.is_present("all"))
+        self::manager::list_deployments(submatches.is_present("all"), submatches.value_of("state"))
             .await
             .unwrap();
     }
 
+    if let Some(submatches) = matches.subcommand_matches("stop") {
+        if let Some(param) = submatches.value_of("id") {
+            if let Ok(id) = param.parse::<i64>() {
+                self::manager::stop_deployment(id, submatches.is_present("prune"))
+                    .await
+                    .unwrap();
+            } else {
+                eprintln!("Please provide a valid id")
+            }
+        } else {
+            eprintln!("Please provide an id")
+        }
+    }
+
     if let Some(submatches) = matches.subcommand_matches("logs") {
         if let Some(param) = submatches.value_of("id") {
             if let Ok(id) = param.parse::<i64>() {
@@ -122,6 +206,46 @@ async fn handle_deployment_subcommand(matches: &ArgMatches<'_>) {
             eprintln!("Please provide an id")
         }
     }
+
+    if let Some(submatches) = matches.subcommand_matches("rerun") {
+        if let Ok(id) = submatches.value_of("id").unwrap().parse::<i64>() {
+            match get_rerun_request(submatches) {
+                Ok(request) => {
+                    println!(
+                        "{}",
+                        self::manager::rerun_deployment(id, &request).await.unwrap()
+                    );
+                }
+                Err(message) => eprintln!("{}", message),
+            }
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+
+    if let Some(submatches) = matches.subcommand_matches("history") {
+        if let Ok(id) = submatches.value_of("id").unwrap().parse::<i64>() {
+            self::manager::list_state_transitions(id).await.unwrap();
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+}
+
+fn get_rerun_request(matches: &ArgMatches) -> Result<RerunRequest, String> {
+    let mut request = RerunRequest {
+        name: matches.value_of("name").map(String::from),
+        ..RerunRequest::default()
+    };
+    for assignment in matches.values_of("image").into_iter().flatten() {
+        let (service, image) = parse_override(assignment)?;
+        request.images.insert(service, image);
+    }
+    for assignment in matches.values_of("node").into_iter().flatten() {
+        let (service, node) = parse_override(assignment)?;
+        request.nodes.insert(service, node);
+    }
+    Ok(request)
 }
 
 fn add_node_subcommand() -> App<'static, 'static> {
@@ -136,6 +260,157 @@ fn add_node_subcommand() -> App<'static, 'static> {
                     .takes_value(false),
             ),
         )
+        .subcommand(
+            App::new("history")
+                .about("show the state transitions of a node")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the node")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(add_node_definition_args(
+            App::new("add").about("add a new node to the cluster"),
+        ))
+        .subcommand(add_node_definition_args(App::new("edit").about(
+            "change the given fields of a node which was added with the client",
+        )))
+        .subcommand(
+            App::new("del")
+                .about("remove a node which was added with the client")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the node")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+}
+
+/// Fields of a node which are passed as they are, named like their keys in config.yml.
+const NODE_FIELDS: [&str; 9] = [
+    "name",
+    "tftp-prefix",
+    "mac-address",
+    "ipv4-address",
+    "serial-number",
+    "architecture",
+    "default-os",
+    "default-user",
+    "storage-device",
+];
+
+fn add_node_definition_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    let app = app.arg(
+        Arg::with_name("id")
+            .long("id")
+            .help("id of the node")
+            .required(true)
+            .takes_value(true),
+    );
+    NODE_FIELDS
+        .iter()
+        .fold(app, |app, field| {
+            app.arg(Arg::with_name(field).long(field).takes_value(true))
+        })
+        .arg(
+            Arg::with_name("pxe")
+                .long("pxe")
+                .help("whether the node boots over the network")
+                .possible_values(&["true", "false"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("label")
+                .long("label")
+                .help("label of the node as key=value")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-host")
+                .long("log-host")
+                .help("log file of the node which is collected")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-serial")
+                .long("log-serial")
+                .help("serial device the node logs to")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("power-on")
+                .long("power-on")
+                .help("command which powers the node on")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("power-off")
+                .long("power-off")
+                .help("command which powers the node off")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("power-reboot")
+                .long("power-reboot")
+                .help("command which reboots the node")
+                .takes_value(true),
+        )
+}
+
+/// Collects the given fields of a node, in the shape the server expects.
+fn get_node_fields(matches: &ArgMatches) -> Result<Value, String> {
+    let mut fields = Map::new();
+    for field in &NODE_FIELDS {
+        if let Some(value) = matches.value_of(field) {
+            fields.insert(String::from(*field), json!(value));
+        }
+    }
+    if let Some(pxe) = matches.value_of("pxe") {
+        fields.insert(String::from("pxe"), json!(pxe == "true"));
+    }
+    if let Some(labels) = matches.values_of("label") {
+        let mut map = Map::new();
+        for label in labels {
+            match label.split_once('=') {
+                Some((key, value)) => map.insert(String::from(key), json!(value)),
+                None => return Err(format!("{} is not formatted as key=value", label)),
+            };
+        }
+        fields.insert(String::from("labels"), Value::Object(map));
+    }
+    let mut log_inputs = Map::new();
+    for (arg, key) in &[("log-host", "hosts"), ("log-serial", "serial")] {
+        if let Some(values) = matches.values_of(arg) {
+            log_inputs.insert(String::from(*key), json!(values.collect::<Vec<&str>>()));
+        }
+    }
+    if !log_inputs.is_empty() {
+        fields.insert(String::from("log-inputs"), Value::Object(log_inputs));
+    }
+    let mut power = Map::new();
+    for (arg, key) in &[
+        ("power-on", "on"),
+        ("power-off", "off"),
+        ("power-reboot", "reboot"),
+    ] {
+        if let Some(command) = matches.value_of(arg) {
+            power.insert(String::from(*key), json!(command));
+        }
+    }
+    if !power.is_empty() {
+        fields.insert(String::from("power"), Value::Object(power));
+    }
+    Ok(Value::Object(fields))
 }
 
 fn add_deployment_subcommand() -> App<'static, 'static> {
@@ -150,6 +425,30 @@ fn add_deployment_subcommand() -> App<'static, 'static> {
                         .long("all")
                         .help("show all deployments, stopped included")
                         .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .long("state")
+                        .help("only show deployments in this state")
+                        .possible_values(&STATES)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("stop")
+                .about("stop deployment with the given id")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id for which deployment should be stopped")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("prune")
+                        .long("prune")
+                        .help("prune sd_card of nodes")
+                        .takes_value(false),
                 ),
         )
         .subcommand(
@@ -161,6 +460,50 @@ fn add_deployment_subcommand() -> App<'static, 'static> {
                     .takes_value(true),
             ),
         )
+        .subcommand(
+            App::new("rerun")
+                .about("deploy the deployment with the given id again")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the deployment which should be deployed again")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .help("name of the new deployment, defaults to the original one")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("image")
+                        .long("image")
+                        .help("use another image for a service, e.g. web=debian-11")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("node")
+                        .long("node")
+                        .help("prefer another node for a service, e.g. web=pi-04")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("history")
+                .about("show the state transitions of a deployment")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the deployment")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
 }
 
 async fn handle_image_subcommand(matches: &ArgMatches<'_>) {
@@ -186,9 +529,17 @@ async fn handle_deploy_subcommand(matches: &ArgMatches<'_>) {
                 return;
             }
         }
-        println!("{}", deploy_single_image(image, node).await.unwrap());
+        if matches.is_present("dry-run") {
+            plan_single_image(image, node).await.unwrap();
+        } else {
+            println!("{}", deploy_single_image(image, node).await.unwrap());
+        }
     } else if let Some(file) = matches.value_of("file") {
-        println!("{}", deploy_deployment(file).await.unwrap());
+        if matches.is_present("dry-run") {
+            plan_deployment(file).await.unwrap();
+        } else {
+            println!("{}", deploy_deployment(file).await.unwrap());
+        }
     }
 }
 
@@ -216,4 +567,115 @@ fn add_deploy_subcommand() -> App<'static, 'static> {
                 .help("yaml file to deploy several nodes at once")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("only show where the deployment would land, without touching any node")
+                .takes_value(false),
+        )
+}
+
+async fn handle_reservation_subcommand(matches: &ArgMatches<'_>) {
+    if let Some(submatches) = matches.subcommand_matches("list") {
+        self::manager::list_reservations(submatches.is_present("all"))
+            .await
+            .unwrap();
+    }
+    if let Some(submatches) = matches.subcommand_matches("add") {
+        match parse_reservation_arguments(submatches) {
+            Ok(request) => {
+                self::manager::create_reservation(&request).await.unwrap();
+            }
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    if let Some(submatches) = matches.subcommand_matches("extend") {
+        if let Ok(id) = submatches.value_of("id").unwrap().parse::<i64>() {
+            let end = match self::manager::get_reservation_end(id).await {
+                Ok(Some(end)) => end,
+                _ => {
+                    eprintln!("No such reservation found");
+                    return;
+                }
+            };
+            match parse_end_argument(submatches, &end) {
+                Ok(end) => {
+                    self::manager::extend_reservation(id, &end).await.unwrap();
+                }
+                Err(message) => eprintln!("{}", message),
+            }
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+    if let Some(submatches) = matches.subcommand_matches("release") {
+        if let Ok(id) = submatches.value_of("id").unwrap().parse::<i64>() {
+            self::manager::release_reservation(id).await.unwrap();
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+}
+
+fn add_config_subcommand() -> App<'static, 'static> {
+    App::new("config")
+        .about("manage the configuration of the server")
+        .subcommand(App::new("reload").about("make the server read config.yml again"))
+}
+
+async fn handle_config_subcommand(matches: &ArgMatches<'_>) {
+    if matches.subcommand_matches("reload").is_some() {
+        self::manager::reload_config().await.unwrap();
+    }
+}
+
+fn add_audit_subcommand() -> App<'static, 'static> {
+    App::new("audit")
+        .about("show who changed what on the testbed")
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .help("only show actions of this user")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("node")
+                .long("node")
+                .help("only show actions on this node")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .help("only show actions since this time (YYYY-MM-DD HH:MM)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .help("only show actions until this time (YYYY-MM-DD HH:MM)")
+                .takes_value(true),
+        )
+}
+
+async fn handle_audit_subcommand(matches: &ArgMatches<'_>) {
+    match get_audit_filter(matches) {
+        Ok(filter) => self::manager::list_audit_entries(&filter).await.unwrap(),
+        Err(message) => eprintln!("{}", message),
+    }
+}
+
+fn get_audit_filter(matches: &ArgMatches) -> Result<AuditFilter, String> {
+    Ok(AuditFilter {
+        user: matches.value_of("user").map(String::from),
+        node: matches.value_of("node").map(String::from),
+        since: matches
+            .value_of("since")
+            .map(parse_local_datetime)
+            .transpose()?,
+        until: matches
+            .value_of("until")
+            .map(parse_local_datetime)
+            .transpose()?,
+    })
 }
@@ -1,16 +1,31 @@
+use chrono::NaiveDateTime;
 use colored::Colorize;
 use prettytable::format;
 use prettytable::{Cell, Row, Table};
-use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use std::io::Write;
 use std::path::Path;
 use std::{env, fs};
+use structs::audit_entry::AuditFilter;
+use structs::audit_row::AuditRow;
 use structs::deployment::Deployment;
+use structs::deployment_plan::DeploymentPlan;
 use structs::deployment_row::DeploymentRow;
 use structs::image_row::ImageRow;
+use structs::job::Job;
 use structs::node::Node;
+use structs::node_definition::NodeDefinition;
 use structs::node_row::NodeRow;
+use structs::node_state_transition_row::NodeStateTransitionRow;
+use structs::rerun_request::RerunRequest;
+use structs::reservation::Reservation;
+use structs::reservation_request::ReservationRequest;
+use structs::reservation_row::ReservationRow;
 use structs::service_row::ServiceRow;
+use structs::state_transition_row::StateTransitionRow;
+use structs::utils::{get_current_user, print_information, print_message, vec_to_string};
+use tokio::time::{sleep, Duration};
 use yaml_rust::YamlLoader;
 
 fn get_server_address() -> String {
@@ -20,7 +35,29 @@ fn get_server_address() -> String {
     )
 }
 
-pub async fn list_services(all: bool, group: bool) -> std::io::Result<()> {
+/// The server refuses every request which does not carry a token.
+pub fn has_token() -> bool {
+    env::var("CLUSTER_TOKEN").is_ok()
+}
+
+fn get_client() -> Client {
+    let mut headers = HeaderMap::new();
+    if let Ok(token) = env::var("CLUSTER_TOKEN") {
+        if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", token)) {
+            headers.insert(AUTHORIZATION, value);
+        }
+    }
+    Client::builder().default_headers(headers).build().unwrap()
+}
+
+fn with_state_filter(url: String, state: Option<&str>) -> String {
+    match state {
+        Some(state) => format!("{}?state={}", url, state),
+        None => url,
+    }
+}
+
+pub async fn list_services(all: bool, group: bool, state: Option<&str>) -> std::io::Result<()> {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     table.set_titles(Row::new(
@@ -35,27 +72,36 @@ pub async fn list_services(all: bool, group: bool) -> std::io::Result<()> {
             Cell::new("started"),
             Cell::new("ended"),
             Cell::new("replicas"),
+            Cell::new("state"),
         ]
         .to_vec(),
     ));
 
-    for row in get_service_rows(all, group).await.unwrap_or_default() {
+    for row in get_service_rows(all, group, state)
+        .await
+        .unwrap_or_default()
+    {
         table.add_row(Row::new(row.get_cells()));
     }
     table.printstd();
     Ok(())
 }
 
-pub async fn get_service_rows(all: bool, group: bool) -> Result<Vec<ServiceRow>, reqwest::Error> {
-    let resp = reqwest::get(format!(
-        "{}/service/list/{}/{}",
-        get_server_address(),
-        all,
-        group
-    ))
-    .await?
-    .json::<Vec<ServiceRow>>()
-    .await?;
+pub async fn get_service_rows(
+    all: bool,
+    group: bool,
+    state: Option<&str>,
+) -> Result<Vec<ServiceRow>, reqwest::Error> {
+    let resp = get_client()
+        .get(with_state_filter(
+            format!("{}/service/list/{}/{}", get_server_address(), all, group),
+            state,
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<ServiceRow>>()
+        .await?;
     Ok(resp)
 }
 
@@ -72,7 +118,10 @@ pub async fn list_nodes(all: bool) -> std::io::Result<()> {
             Cell::new("status"),
             Cell::new("hostname"),
             Cell::new("IPv4-address"),
-            Cell::new("usable"),
+            Cell::new("labels"),
+            Cell::new("state"),
+            Cell::new("reason"),
+            Cell::new("expected return"),
         ]
         .to_vec(),
     ));
@@ -90,15 +139,58 @@ pub async fn list_nodes(all: bool) -> std::io::Result<()> {
     Ok(())
 }
 
+pub async fn list_node_state_transitions(id: &str) -> std::io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("timestamp"),
+            Cell::new("from"),
+            Cell::new("to"),
+            Cell::new("reason"),
+        ]
+        .to_vec(),
+    ));
+
+    match get_node_state_transition_rows(id).await {
+        Ok(rows) => {
+            for row in rows {
+                table.add_row(Row::new(row.get_cells()));
+            }
+        }
+        Err(err) => {
+            eprintln!("{:?}", err)
+        }
+    }
+    table.printstd();
+    Ok(())
+}
+
+pub async fn get_node_state_transition_rows(
+    id: &str,
+) -> Result<Vec<NodeStateTransitionRow>, reqwest::Error> {
+    let resp = get_client()
+        .get(format!("{}/node/history/{}", get_server_address(), id))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<NodeStateTransitionRow>>()
+        .await?;
+    Ok(resp)
+}
+
 pub async fn get_nodes_rows(all: bool) -> Result<Vec<NodeRow>, reqwest::Error> {
-    let resp = reqwest::get(format!("{}/node/list/{}", get_server_address(), all))
+    let resp = get_client()
+        .get(format!("{}/node/list/{}", get_server_address(), all))
+        .send()
         .await?
+        .error_for_status()?
         .json::<Vec<NodeRow>>()
         .await?;
     Ok(resp)
 }
 
-pub async fn list_deployments(all: bool) -> std::io::Result<()> {
+pub async fn list_deployments(all: bool, state: Option<&str>) -> std::io::Result<()> {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     table.set_titles(Row::new(
@@ -109,12 +201,22 @@ pub async fn list_deployments(all: bool) -> std::io::Result<()> {
             Cell::new("end"),
             Cell::new("owner"),
             Cell::new("#services"),
+            Cell::new("state"),
+            Cell::new("failure"),
+            Cell::new("rerun of"),
         ]
         .to_vec(),
     ));
 
-    for row in get_deployment_rows(all).await.unwrap() {
-        table.add_row(Row::new(row.get_cells()));
+    match get_deployment_rows(all, state).await {
+        Ok(rows) => {
+            for row in rows {
+                table.add_row(Row::new(row.get_cells()));
+            }
+        }
+        Err(err) => {
+            eprintln!("{:?}", err)
+        }
     }
     table.printstd();
     Ok(())
@@ -132,82 +234,499 @@ pub async fn list_images() -> std::io::Result<()> {
         .to_vec(),
     ));
 
-    for row in get_images_rows().await.unwrap() {
-        table.add_row(Row::new(row.get_cells()));
+    match get_images_rows().await {
+        Ok(rows) => {
+            for row in rows {
+                table.add_row(Row::new(row.get_cells()));
+            }
+        }
+        Err(err) => {
+            eprintln!("{:?}", err)
+        }
     }
 
     table.printstd();
     Ok(())
 }
 
-pub async fn get_deployment_rows(all: bool) -> Result<Vec<DeploymentRow>, reqwest::Error> {
-    let resp = reqwest::get(format!("{}/deployment/list/{}", get_server_address(), all))
+pub async fn get_deployment_rows(
+    all: bool,
+    state: Option<&str>,
+) -> Result<Vec<DeploymentRow>, reqwest::Error> {
+    let resp = get_client()
+        .get(with_state_filter(
+            format!("{}/deployment/list/{}", get_server_address(), all),
+            state,
+        ))
+        .send()
         .await?
+        .error_for_status()?
         .json::<Vec<DeploymentRow>>()
         .await?;
     Ok(resp)
 }
 
+pub async fn list_state_transitions(id: i64) -> std::io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("timestamp"),
+            Cell::new("entity"),
+            Cell::new("from"),
+            Cell::new("to"),
+            Cell::new("reason"),
+        ]
+        .to_vec(),
+    ));
+
+    match get_state_transition_rows(id).await {
+        Ok(rows) => {
+            for row in rows {
+                table.add_row(Row::new(row.get_cells()));
+            }
+        }
+        Err(err) => {
+            eprintln!("{:?}", err)
+        }
+    }
+    table.printstd();
+    Ok(())
+}
+
+pub async fn get_state_transition_rows(id: i64) -> Result<Vec<StateTransitionRow>, reqwest::Error> {
+    let resp = get_client()
+        .get(format!(
+            "{}/deployment/history/{}",
+            get_server_address(),
+            id
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<StateTransitionRow>>()
+        .await?;
+    Ok(resp)
+}
+
 pub async fn get_node_by_id(id: &str, all: bool) -> Result<Node, reqwest::Error> {
-    let resp = reqwest::get(format!("{}/node/get/{}/{}", get_server_address(), id, all))
+    let resp = get_client()
+        .get(format!("{}/node/get/{}/{}", get_server_address(), id, all))
+        .send()
         .await?
+        .error_for_status()?
         .json::<Node>()
         .await?;
     Ok(resp)
 }
 
 pub async fn get_images_rows() -> Result<Vec<ImageRow>, reqwest::Error> {
-    let resp = reqwest::get(format!("{}/image/list", get_server_address()))
+    let resp = get_client()
+        .get(format!("{}/image/list", get_server_address()))
+        .send()
         .await?
+        .error_for_status()?
         .json::<Vec<ImageRow>>()
         .await?;
     Ok(resp)
 }
 
 pub async fn deploy_single_image(image: &str, node: Option<Node>) -> Result<bool, reqwest::Error> {
-    let client = reqwest::Client::new();
+    let client = get_client();
     let resp = client
         .put(format!("{}/deploy/image", get_server_address()))
         .body(serde_json::to_string(&(image, node)).unwrap())
         .send()
         .await?
-        .json::<bool>()
+        .error_for_status()?
+        .json::<i64>()
         .await?;
-    Ok(resp)
+    follow_job(resp).await
 }
 pub async fn deploy_deployment(deployment_yaml: &str) -> Result<bool, reqwest::Error> {
+    match read_deployment(deployment_yaml) {
+        Some(deployment) => deploy(&deployment).await,
+        None => Ok(false),
+    }
+}
+
+fn read_deployment(deployment_yaml: &str) -> Option<Deployment> {
     let path = Path::new(deployment_yaml);
     if !path.exists() {
         println!("{}", "please provide an existing yaml file".red());
-        return Ok(false);
+        return None;
     }
     let content =
         &fs::read_to_string(deployment_yaml).expect("Something went wrong reading the file");
     let yaml = YamlLoader::load_from_str(content).unwrap();
-    let deployment = Deployment::from_yaml(deployment_yaml, &yaml[0]);
-    deploy(&deployment).await
+    match Deployment::from_yaml(deployment_yaml, &get_current_user(), &yaml[0]) {
+        Ok(deployment) => Some(deployment),
+        Err(e) => {
+            println!("{}", e.red());
+            None
+        }
+    }
+}
+
+pub async fn plan_single_image(image: &str, node: Option<Node>) -> Result<bool, reqwest::Error> {
+    let deployment = Deployment::for_image(image, node.map(|n| n.id), &get_current_user());
+    plan(&deployment).await
+}
+
+pub async fn plan_deployment(deployment_yaml: &str) -> Result<bool, reqwest::Error> {
+    match read_deployment(deployment_yaml) {
+        Some(deployment) => plan(&deployment).await,
+        None => Ok(false),
+    }
+}
+
+async fn plan(deployment: &Deployment) -> Result<bool, reqwest::Error> {
+    let plan = get_client()
+        .put(format!("{}/deploy/plan", get_server_address()))
+        .body(serde_json::to_string(deployment).unwrap())
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<DeploymentPlan>()
+        .await?;
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("service"),
+            Cell::new("hostname"),
+            Cell::new("node"),
+            Cell::new("images"),
+            Cell::new("replaced nfsroot"),
+            Cell::new("rationale"),
+        ]
+        .to_vec(),
+    ));
+    for row in &plan.replicas {
+        table.add_row(Row::new(row.get_cells()));
+    }
+    table.printstd();
+    for problem in &plan.problems {
+        eprintln!("{}", problem.red());
+    }
+    print_message(
+        &format!("deployment {} can be deployed", plan.name),
+        plan.is_feasible(),
+    );
+    Ok(plan.is_feasible())
 }
 
 async fn deploy(deployment: &Deployment) -> Result<bool, reqwest::Error> {
-    let client = reqwest::Client::new();
+    let client = get_client();
     let resp = client
         .put(format!("{}/deploy/file", get_server_address()))
         .body(serde_json::to_string(deployment).unwrap())
         .send()
         .await?
-        .json::<bool>()
+        .error_for_status()?
+        .json::<i64>()
         .await?;
-    Ok(resp)
+    follow_job(resp).await
+}
+
+pub async fn rerun_deployment(id: i64, request: &RerunRequest) -> Result<bool, reqwest::Error> {
+    let resp = get_client()
+        .put(format!("{}/deployment/rerun/{}", get_server_address(), id))
+        .body(serde_json::to_string(request).unwrap())
+        .send()
+        .await?;
+    match resp.status() {
+        StatusCode::ACCEPTED => follow_job(resp.json::<i64>().await?).await,
+        StatusCode::FORBIDDEN => {
+            eprintln!("only the owner or an admin may do this");
+            Ok(false)
+        }
+        StatusCode::NOT_FOUND => {
+            eprintln!("Please provide a valid id");
+            Ok(false)
+        }
+        _ => {
+            eprintln!("{}", resp.text().await?.red());
+            Ok(false)
+        }
+    }
+}
+
+/// Prints the steps of the job as the server reports them until the job has finished.
+async fn follow_job(id: i64) -> Result<bool, reqwest::Error> {
+    print_information(&format!("started job {}", id));
+    let mut last_step = 0;
+    loop {
+        let job = get_client()
+            .get(format!(
+                "{}/jobs/{}?after={}",
+                get_server_address(),
+                id,
+                last_step
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Job>()
+            .await?;
+        for step in &job.steps {
+            match step.status {
+                Some(status) => print_message(&step.message, status),
+                None => print_information(&step.message),
+            }
+            last_step = step.id;
+        }
+        if job.is_finished() {
+            return Ok(job.success.unwrap_or(false));
+        }
+        sleep(Duration::from_secs(2)).await;
+    }
+}
+
+pub async fn stop_deployment(id: i64, prune: bool) -> Result<bool, reqwest::Error> {
+    stop(
+        &format!("deployment/stop/{}/{}", id, prune),
+        "stop deployment",
+    )
+    .await
+}
+
+pub async fn stop_service(id: i64, prune: bool) -> Result<bool, reqwest::Error> {
+    stop(&format!("service/stop/{}/{}", id, prune), "stop service").await
+}
+
+async fn stop(path: &str, message: &str) -> Result<bool, reqwest::Error> {
+    let resp = get_client()
+        .put(format!("{}/{}", get_server_address(), path))
+        .send()
+        .await?;
+    let status = match resp.status() {
+        StatusCode::OK => resp.json::<bool>().await?,
+        StatusCode::FORBIDDEN => {
+            eprintln!("only the owner or an admin may do this");
+            false
+        }
+        StatusCode::NOT_FOUND => {
+            eprintln!("Please provide a valid id");
+            false
+        }
+        _ => false,
+    };
+    print_message(message, status);
+    Ok(status)
 }
 
 pub async fn get_deployment_logs(id: i64) -> bool {
-    let client = Client::builder().build().unwrap();
+    let client = get_client();
     if let Ok(response) = client
         .get(format!("{}/deployment/logs/{}", get_server_address(), id))
-        .send().await
+        .send()
+        .await
     {
-        let mut file = std::fs::File::create(format!("./{}.zip", id)).unwrap();
-        return file.write_all(&response.bytes().await.unwrap()).is_ok();
+        match response.status() {
+            StatusCode::OK => {
+                let mut file = std::fs::File::create(format!("./{}.zip", id)).unwrap();
+                return file.write_all(&response.bytes().await.unwrap()).is_ok();
+            }
+            StatusCode::FORBIDDEN => eprintln!("only the owner or an admin may do this"),
+            StatusCode::NOT_FOUND => eprintln!("Please provide a valid id"),
+            _ => eprintln!("{}", response.text().await.unwrap_or_default().red()),
+        }
     }
     false
 }
+
+pub async fn list_reservations(all: bool) -> std::io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("id"),
+            Cell::new("owner"),
+            Cell::new("nodes"),
+            Cell::new("start"),
+            Cell::new("end"),
+            Cell::new("released"),
+        ]
+        .to_vec(),
+    ));
+
+    for row in get_reservation_rows(all).await.unwrap_or_default() {
+        table.add_row(Row::new(row.get_cells()));
+    }
+    table.printstd();
+    Ok(())
+}
+
+pub async fn get_reservation_rows(all: bool) -> Result<Vec<ReservationRow>, reqwest::Error> {
+    let resp = get_client()
+        .get(format!("{}/reservation/list/{}", get_server_address(), all))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<ReservationRow>>()
+        .await?;
+    Ok(resp)
+}
+
+pub async fn create_reservation(request: &ReservationRequest) -> Result<bool, reqwest::Error> {
+    let client = get_client();
+    let resp = client
+        .put(format!("{}/reservation", get_server_address()))
+        .body(serde_json::to_string(request).unwrap())
+        .send()
+        .await?;
+    if resp.status().is_success() {
+        let reservation = resp.json::<Reservation>().await?;
+        print_information(&format!(
+            "reserved {} for {} until {}",
+            vec_to_string(&reservation.nodes, false),
+            reservation.owner,
+            reservation.end.format("%Y-%m-%d %H:%M")
+        ));
+        print_message("add reservation", true);
+        return Ok(true);
+    }
+    eprintln!("{}", resp.text().await?);
+    print_message("add reservation", false);
+    Ok(false)
+}
+
+pub async fn extend_reservation(id: i64, end: &NaiveDateTime) -> Result<bool, reqwest::Error> {
+    let client = get_client();
+    let resp = client
+        .put(format!(
+            "{}/reservation/extend/{}",
+            get_server_address(),
+            id
+        ))
+        .body(serde_json::to_string(end).unwrap())
+        .send()
+        .await?;
+    let status = resp.status().is_success();
+    if !status {
+        eprintln!("{}", resp.text().await?);
+    }
+    print_message("extend reservation", status);
+    Ok(status)
+}
+
+pub async fn release_reservation(id: i64) -> Result<bool, reqwest::Error> {
+    let client = get_client();
+    let resp = client
+        .put(format!(
+            "{}/reservation/release/{}",
+            get_server_address(),
+            id
+        ))
+        .send()
+        .await?;
+    let status = resp.status().is_success();
+    if !status {
+        eprintln!("{}", resp.text().await?);
+    }
+    print_message("release reservation", status);
+    Ok(status)
+}
+
+pub async fn get_reservation_end(id: i64) -> Result<Option<NaiveDateTime>, reqwest::Error> {
+    Ok(get_reservation_rows(true)
+        .await?
+        .into_iter()
+        .find(|r| r.id == id)
+        .and_then(|r| r.end))
+}
+
+/// Prints why the server refused a change, problems of the configuration are listed one by one.
+async fn report_refusal(resp: Response) -> Result<(), reqwest::Error> {
+    match resp.status() {
+        StatusCode::FORBIDDEN => eprintln!("only an admin may do this"),
+        StatusCode::NOT_FOUND => eprintln!("Please provide a valid id"),
+        StatusCode::UNPROCESSABLE_ENTITY => {
+            for problem in resp.json::<Vec<String>>().await? {
+                eprintln!("{}", problem.red());
+            }
+        }
+        _ => eprintln!("{}", resp.text().await?.red()),
+    }
+    Ok(())
+}
+
+pub async fn reload_config() -> Result<bool, reqwest::Error> {
+    let resp = get_client()
+        .post(format!("{}/config/reload", get_server_address()))
+        .send()
+        .await?;
+    let status = resp.status().is_success();
+    if !status {
+        report_refusal(resp).await?;
+    }
+    print_message("reload config.yml", status);
+    Ok(status)
+}
+
+async fn change_node(request: RequestBuilder, message: &str) -> Result<bool, reqwest::Error> {
+    let resp = request.send().await?;
+    let status = if resp.status().is_success() {
+        resp.json::<bool>().await?
+    } else {
+        report_refusal(resp).await?;
+        false
+    };
+    print_message(message, status);
+    Ok(status)
+}
+
+pub async fn add_node(id: &str, definition: &NodeDefinition) -> Result<bool, reqwest::Error> {
+    let request = get_client()
+        .post(format!("{}/node", get_server_address()))
+        .body(serde_json::to_string(&(id, definition)).unwrap());
+    change_node(request, &format!("add {}", id)).await
+}
+
+/// Only the fields in `patch` are changed, the rest of the definition stays as it is.
+pub async fn edit_node(id: &str, patch: &serde_json::Value) -> Result<bool, reqwest::Error> {
+    let request = get_client()
+        .patch(format!("{}/node/{}", get_server_address(), id))
+        .body(patch.to_string());
+    change_node(request, &format!("edit {}", id)).await
+}
+
+pub async fn delete_node(id: &str) -> Result<bool, reqwest::Error> {
+    let request = get_client().delete(format!("{}/node/{}", get_server_address(), id));
+    change_node(request, &format!("remove {}", id)).await
+}
+
+pub async fn list_audit_entries(filter: &AuditFilter) -> std::io::Result<()> {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("timestamp"),
+            Cell::new("actor"),
+            Cell::new("action"),
+            Cell::new("target"),
+            Cell::new("node"),
+            Cell::new("outcome"),
+        ]
+        .to_vec(),
+    ));
+
+    for row in get_audit_rows(filter).await.unwrap_or_default() {
+        table.add_row(Row::new(row.get_cells()));
+    }
+    table.printstd();
+    Ok(())
+}
+
+pub async fn get_audit_rows(filter: &AuditFilter) -> Result<Vec<AuditRow>, reqwest::Error> {
+    let resp = get_client()
+        .get(format!("{}/audit", get_server_address()))
+        .query(filter)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<AuditRow>>()
+        .await?;
+    Ok(resp)
+}
@@ -0,0 +1,87 @@
+use crate::database::{get_audit_entries, insert_audit_entry};
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+use std::cell::RefCell;
+use structs::audit_entry::AuditFilter;
+use structs::audit_row::AuditRow;
+use structs::utils::get_current_user;
+
+thread_local! {
+    static ACTOR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Attributes everything recorded on the current thread to `actor`, `None` falls back to the user
+/// running the cluster-manager.
+pub fn set_actor(actor: Option<String>) {
+    ACTOR.with(|a| *a.borrow_mut() = actor);
+}
+
+/// Runs `work` on the current thread on behalf of `actor`.
+pub fn as_actor<F, R>(actor: &str, work: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let previous = ACTOR.with(|a| a.replace(Some(String::from(actor))));
+    let result = work();
+    set_actor(previous);
+    result
+}
+
+/// Returns who the current thread acts on behalf of.
+pub fn get_actor() -> String {
+    ACTOR
+        .with(|a| a.borrow().clone())
+        .unwrap_or_else(get_current_user)
+}
+
+/// Adds the action to the audit log and returns `success`, so results can be recorded in passing.
+pub fn record(action: &str, target: &str, node: Option<&str>, success: bool) -> bool {
+    if insert_audit_entry(&get_actor(), action, target, node, success).is_err() {
+        eprintln!("Problem with writing the audit log to database");
+    }
+    success
+}
+
+/// Like `record`, but adds one entry for each of the nodes, so the action shows up in the history
+/// of every node it touched.
+pub fn record_for_nodes(action: &str, target: &str, nodes: &[String], success: bool) -> bool {
+    if nodes.is_empty() {
+        return record(action, target, None, success);
+    }
+    for node in nodes {
+        record(action, target, Some(node), success);
+    }
+    success
+}
+
+pub fn list_audit_entries(filter: &AuditFilter) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("timestamp"),
+            Cell::new("actor"),
+            Cell::new("action"),
+            Cell::new("target"),
+            Cell::new("node"),
+            Cell::new("outcome"),
+        ]
+        .to_vec(),
+    ));
+
+    for row in get_audit_rows(filter) {
+        table.add_row(Row::new(row.get_cells()));
+    }
+
+    table.printstd()
+}
+
+pub fn get_audit_rows(filter: &AuditFilter) -> Vec<AuditRow> {
+    match get_audit_entries(filter) {
+        Ok(entries) => entries.into_iter().map(AuditRow::new).collect(),
+        Err(_) => {
+            eprintln!("Problem with reading the audit log from database");
+            Vec::new()
+        }
+    }
+}
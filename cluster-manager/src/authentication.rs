@@ -0,0 +1,36 @@
+use crate::server::blocking;
+use crate::user_manager::authenticate;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorUnauthorized;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{Error, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use structs::user::User;
+
+pub struct AuthenticatedUser(pub User);
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ();
+
+    /// Looks the token up on the blocking thread pool, the database must not stall the executor.
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(String::from);
+        Box::pin(async move {
+            let user = match token {
+                Some(token) => blocking(move || authenticate(&token)).await?,
+                None => None,
+            };
+            match user {
+                Some(user) => Ok(AuthenticatedUser(user)),
+                None => Err(ErrorUnauthorized("missing or invalid token")),
+            }
+        })
+    }
+}
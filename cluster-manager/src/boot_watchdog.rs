@@ -0,0 +1,241 @@
+use crate::config::get_config;
+use crate::deployer::execute_command_over_ssh;
+use crate::installer::{DNSMASQ_LOG_FILE, SYSLOG_FILE};
+use crate::logs_manager::get_host_log_files_of;
+use crate::node_manager::is_up;
+use crate::power_manager::reboot;
+use crate::storage::Storage;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Instant;
+use std::{fs, thread, time};
+use structs::node::Node;
+use structs::node_state::NodeState;
+use structs::utils::{print_information, print_message};
+
+const DEFAULT_RETRIES: u64 = 2;
+
+/// The phases a netbooting node goes through, in the order they usually happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BootPhase {
+    DhcpLease,
+    TftpFetch,
+    NfsMount,
+    Reachable,
+    FirstLogLine,
+    SshReachable,
+}
+
+const PHASES: [BootPhase; 6] = [
+    BootPhase::DhcpLease,
+    BootPhase::TftpFetch,
+    BootPhase::NfsMount,
+    BootPhase::Reachable,
+    BootPhase::FirstLogLine,
+    BootPhase::SshReachable,
+];
+
+impl BootPhase {
+    #[must_use]
+    pub fn get_name(self) -> &'static str {
+        match self {
+            BootPhase::DhcpLease => "dhcp-lease",
+            BootPhase::TftpFetch => "tftp-fetch",
+            BootPhase::NfsMount => "nfs-mount",
+            BootPhase::Reachable => "reachable",
+            BootPhase::FirstLogLine => "first-log-line",
+            BootPhase::SshReachable => "ssh-reachable",
+        }
+    }
+
+    fn get_default_timeout(self) -> u64 {
+        match self {
+            BootPhase::DhcpLease | BootPhase::TftpFetch => 120,
+            BootPhase::NfsMount => 180,
+            BootPhase::Reachable | BootPhase::FirstLogLine | BootPhase::SshReachable => 300,
+        }
+    }
+
+    /// Seconds the phase may take, configured as `boot-watchdog.timeouts.<phase>` in config.yml.
+    fn get_timeout(self) -> u64 {
+        get_config()
+            .boot_watchdog
+            .timeouts
+            .get(self.get_name())
+            .copied()
+            .unwrap_or_else(|| self.get_default_timeout())
+    }
+}
+
+/// Where the log files of a node ended right before it was rebooted, the watchdog only looks at
+/// what was appended afterwards.
+pub struct BootStart {
+    offsets: HashMap<String, u64>,
+    started: Instant,
+}
+
+impl BootStart {
+    /// Has to be taken before the node is rebooted, otherwise the first phases may already be in
+    /// the logs.
+    #[must_use]
+    pub fn capture(node: &Node) -> Self {
+        let mut files = vec![String::from(DNSMASQ_LOG_FILE), String::from(SYSLOG_FILE)];
+        files.extend(get_host_log_files_of(node));
+        let offsets = files
+            .into_iter()
+            .map(|file| {
+                let length = fs::metadata(&file).map(|m| m.len()).unwrap_or(0);
+                (file, length)
+            })
+            .collect();
+        BootStart {
+            offsets,
+            started: Instant::now(),
+        }
+    }
+}
+
+fn get_retries() -> u64 {
+    get_config()
+        .boot_watchdog
+        .retries
+        .unwrap_or(DEFAULT_RETRIES)
+}
+
+/// Waits until the node rebooted after `start` went through all phases up to `last`. A phase that
+/// takes longer than its timeout power-cycles the node, once all retries are used up the node is
+/// marked as suspect. Returns how many seconds after the reboot each observed phase was reached.
+///
+/// # Errors
+///
+/// Will return `Err` with the phase the node got stuck in
+pub fn watch_boot(
+    storage: &dyn Storage,
+    node: &Node,
+    last: BootPhase,
+    mut start: BootStart,
+) -> Result<Vec<(BootPhase, u64)>, BootPhase> {
+    let retries = get_retries();
+    let mut attempt = 0;
+    loop {
+        match watch_boot_attempt(node, last, &start) {
+            Ok(durations) => {
+                for (phase, seconds) in &durations {
+                    print_information(&format!(
+                        "{}: {} after {} seconds",
+                        node.id,
+                        phase.get_name(),
+                        seconds
+                    ));
+                }
+                return Ok(durations);
+            }
+            Err(phase) if attempt < retries => {
+                attempt += 1;
+                start = BootStart::capture(node);
+                print_message(
+                    &format!(
+                        "{} got stuck at {}, power cycle ({}/{})",
+                        node.id,
+                        phase.get_name(),
+                        attempt,
+                        retries
+                    ),
+                    reboot(node),
+                );
+            }
+            Err(phase) => {
+                let reason = format!(
+                    "got stuck at {} after {} power cycles",
+                    phase.get_name(),
+                    retries
+                );
+                print_message(
+                    &format!("mark {} as suspect, it {}", node.id, reason),
+                    storage
+                        .set_state_for_node(&node.id, &NodeState::Failed, Some(&reason))
+                        .is_ok(),
+                );
+                return Err(phase);
+            }
+        }
+    }
+}
+
+/// Follows one boot of the node, a phase counts as passed as soon as it or any later phase is
+/// observed, e.g. nodes booting from local storage never fetch anything over tftp.
+fn watch_boot_attempt(
+    node: &Node,
+    last: BootPhase,
+    start: &BootStart,
+) -> Result<Vec<(BootPhase, u64)>, BootPhase> {
+    let offsets = &start.offsets;
+    let phases = PHASES
+        .iter()
+        .copied()
+        .filter(|phase| *phase <= last)
+        .collect::<Vec<BootPhase>>();
+    let mut durations = Vec::new();
+    let mut current = 0;
+    let mut phase_started = Instant::now();
+    while current < phases.len() {
+        if let Some(reached) = phases[current..]
+            .iter()
+            .rposition(|phase| is_observed(*phase, node, offsets))
+        {
+            current += reached;
+            durations.push((phases[current], start.started.elapsed().as_secs()));
+            current += 1;
+            phase_started = Instant::now();
+            continue;
+        }
+        if phase_started.elapsed().as_secs() >= phases[current].get_timeout() {
+            return Err(phases[current]);
+        }
+        thread::sleep(time::Duration::from_secs(2));
+    }
+    Ok(durations)
+}
+
+fn is_observed(phase: BootPhase, node: &Node, offsets: &HashMap<String, u64>) -> bool {
+    let ip = node.ipv4_address.as_str();
+    match phase {
+        BootPhase::DhcpLease => read_since(DNSMASQ_LOG_FILE, offsets)
+            .lines()
+            .any(|line| line.contains("DHCPACK") && has_word(line, ip)),
+        BootPhase::TftpFetch => read_since(DNSMASQ_LOG_FILE, offsets)
+            .lines()
+            .any(|line| line.contains("dnsmasq-tftp") && has_word(line, ip)),
+        BootPhase::NfsMount => read_since(SYSLOG_FILE, offsets)
+            .lines()
+            .any(|line| line.contains("mount request from") && line.contains(&format!("{}:", ip))),
+        BootPhase::Reachable => is_up(ip),
+        BootPhase::FirstLogLine => get_host_log_files_of(node)
+            .iter()
+            .any(|file| !read_since(file, offsets).is_empty()),
+        BootPhase::SshReachable => is_up(ip) && execute_command_over_ssh(node, "true"),
+    }
+}
+
+fn has_word(line: &str, word: &str) -> bool {
+    line.split_whitespace().any(|w| w.eq(word))
+}
+
+/// Returns what was appended to `file` since the watchdog started.
+fn read_since(file: &str, offsets: &HashMap<String, u64>) -> String {
+    let mut content = String::new();
+    if let Ok(mut handle) = File::open(file) {
+        let offset = *offsets.get(file).unwrap_or(&0);
+        let length = handle.metadata().map(|m| m.len()).unwrap_or(0);
+        // a rotated file starts over
+        let offset = if length < offset { 0 } else { offset };
+        if handle.seek(SeekFrom::Start(offset)).is_ok() {
+            let mut bytes = Vec::new();
+            if handle.read_to_end(&mut bytes).is_ok() {
+                content = String::from_utf8_lossy(&bytes).into_owned();
+            }
+        }
+    }
+    content
+}
@@ -1,67 +1,239 @@
+use crate::audit::record;
+use crate::config::check::get_problems;
 use crate::installer::BASE_DIR;
 use crate::node_manager::verify_node_usability;
-use config::{Config, Value};
-use std::collections::hash_map::RandomState;
-use std::collections::HashMap;
-use structs::logsource::LogSourceTypes::{HOST, SERIAL};
-use structs::logsource::{LogSource, LogSourceTypes};
+use config::{Config, File};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex, PoisonError};
+use structs::logsource::LogSource;
+use structs::logsource::LogSourceTypes::HOST;
 use structs::node::Node;
+use structs::node_definition::NodeDefinition;
 use structs::power_action::PowerAction;
 use structs::power_action::Type::{OFF, ON, REBOOT};
 use structs::power_action_set::PowerActionSet;
+use structs::utils::print_message;
 
-fn read_config() -> Config {
-    let mut config = Config::default();
-    config
-        .merge(config::File::with_name(
-            format!("{}/{}", BASE_DIR, "config.yml").as_str(),
-        ))
-        .unwrap();
-    config
+mod check;
+
+/// The contents of config.yml together with the nodes added through the API.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClusterConfig {
+    pub server_ip: Option<String>,
+    pub log_server: Option<String>,
+    pub logstash_base_directory: Option<String>,
+    #[serde(default)]
+    pub boot_watchdog: BootWatchdogConfig,
+    #[serde(default)]
+    pub nodes: BTreeMap<String, NodeDefinition>,
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct BootWatchdogConfig {
+    pub retries: Option<u64>,
+    #[serde(default)]
+    pub timeouts: HashMap<String, u64>,
+}
+
+static CONFIG: Mutex<Option<Arc<ClusterConfig>>> = Mutex::new(None);
+
+/// Nodes added through the API are kept apart from config.yml, so the file stays as it was written.
+const MANAGED_NODES_FILE: &str = "nodes.json";
+
+/// # Errors
+///
+/// Will return `Err` if config.yml can not be read or does not match `ClusterConfig`
+fn read_config_file() -> Result<ClusterConfig, String> {
+    let path = format!("{}/{}", BASE_DIR, "config.yml");
+    Config::builder()
+        .add_source(File::with_name(&path))
+        .build()
+        .and_then(Config::try_deserialize)
+        .map_err(|e| format!("could not read {}: {}", path, e))
 }
 
-pub fn get(property: &str) -> Option<String> {
-    let config = read_config();
-    match config.get::<String>(property) {
-        Ok(str) => Some(str),
-        Err(_) => None,
+/// # Errors
+///
+/// Will return `Err` if there are managed nodes which can not be read
+fn read_managed_nodes() -> Result<BTreeMap<String, NodeDefinition>, String> {
+    let path = format!("{}/{}", BASE_DIR, MANAGED_NODES_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            serde_json::from_str(&content).map_err(|e| format!("could not read {}: {}", path, e))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(format!("could not read {}: {}", path, e)),
     }
 }
 
-#[allow(dead_code)]
-pub fn get_or_default(property: &str, default: &str) -> String {
-    let config = read_config().try_into::<HashMap<String, String>>().unwrap();
-    match config.get(property) {
-        Some(str) => String::from(str),
-        None => String::from(default),
+/// # Errors
+///
+/// Will return `Err` if the managed nodes could not be written
+fn write_managed_nodes(nodes: &BTreeMap<String, NodeDefinition>) -> Result<(), String> {
+    let path = format!("{}/{}", BASE_DIR, MANAGED_NODES_FILE);
+    serde_json::to_string_pretty(nodes)
+        .map_err(|e| e.to_string())
+        .and_then(|content| fs::write(&path, content).map_err(|e| e.to_string()))
+        .map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+/// A managed node replaces a node with the same id in config.yml.
+///
+/// # Errors
+///
+/// Will return `Err` if config.yml or the managed nodes can not be read
+fn read_config() -> Result<ClusterConfig, String> {
+    let mut config = read_config_file()?;
+    config.nodes.extend(read_managed_nodes()?);
+    Ok(config)
+}
+
+/// Problems are reported once while loading, nodes with problems are left out of the cluster.
+fn load_config() -> ClusterConfig {
+    match read_config() {
+        Ok(config) => {
+            for problem in get_problems(&config) {
+                eprintln!("config.yml: {}", problem);
+            }
+            config
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ClusterConfig::default()
+        }
     }
 }
 
-pub fn get_log_sources_of(node: &Node) -> Vec<LogSource> {
-    let mut vec = get_log_sources(node.id.as_str());
-    vec.push(LogSource::new(node.ipv4_address.clone(), HOST));
-    vec
+fn get_or_load(config: &mut Option<Arc<ClusterConfig>>) -> Arc<ClusterConfig> {
+    config
+        .get_or_insert_with(|| Arc::new(load_config()))
+        .clone()
 }
 
-fn get_log_sources(id: &str) -> Vec<LogSource> {
-    let config = read_config();
-    let mut vec = Vec::new();
-    let nodes = config.get_table("nodes").unwrap();
-    if let Some(node) = nodes.get(id) {
-        if let Ok(node_table) = node.to_owned().into_table() {
-            if let Some(log_inputs) = node_table.get("log-inputs") {
-                if let Ok(log_inputs_table) = log_inputs.to_owned().into_table() {
-                    for tuple in [("hosts", HOST), ("serial", SERIAL)].to_vec() {
-                        if let Some(entries) = log_inputs_table.get(tuple.0) {
-                            if let Ok(array) = entries.to_owned().into_array() {
-                                vec.append(&mut get_log_source_from_array(array, &tuple.1));
-                            }
-                        }
-                    }
-                }
+/// Returns the configuration, config.yml is only read on first use and on `reload_config`.
+pub fn get_config() -> Arc<ClusterConfig> {
+    get_or_load(&mut CONFIG.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Reads config.yml again and uses it from now on, as long as it has no problems.
+///
+/// # Errors
+///
+/// Will return `Err` with the problems of config.yml, the previous configuration stays in use
+pub fn reload_config() -> Result<(), Vec<String>> {
+    let result = match read_config() {
+        Ok(config) => {
+            let problems = get_problems(&config);
+            if problems.is_empty() {
+                *CONFIG.lock().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(config));
+                Ok(())
+            } else {
+                Err(problems)
             }
         }
+        Err(e) => Err(vec![e]),
+    };
+    record("reload config", "config.yml", None, result.is_ok());
+    result
+}
+
+/// Prints every problem of config.yml as it is on disk, returns whether there are none.
+pub fn check_config() -> bool {
+    let problems = match read_config() {
+        Ok(config) => get_problems(&config),
+        Err(e) => vec![e],
+    };
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    print_message("check config.yml", problems.is_empty());
+    problems.is_empty()
+}
+
+/// Adds the definition of a `new` node or replaces the one of an existing node, as long as it
+/// causes no new problems. Nodes from config.yml are left alone, a copy in the managed nodes would
+/// hide later changes to the file.
+///
+/// # Errors
+///
+/// Will return `Err` with the problems the definition would cause
+pub fn save_node_definition(
+    id: &str,
+    definition: NodeDefinition,
+    new: bool,
+) -> Result<(), Vec<String>> {
+    // held until the end, so concurrent edits do not overwrite each other
+    let mut config = CONFIG.lock().unwrap_or_else(PoisonError::into_inner);
+    let current = get_or_load(&mut config);
+    match (new, current.nodes.contains_key(id)) {
+        (true, true) => return Err(vec![format!("{} already exists", id)]),
+        (false, false) => return Err(vec![format!("{} does not exist", id)]),
+        _ => {}
+    }
+    if read_config_file().map_or(false, |file| file.nodes.contains_key(id)) {
+        return Err(vec![format!(
+            "{} is defined in config.yml, edit it there",
+            id
+        )]);
+    }
+    let mut updated = (*current).clone();
+    updated.nodes.insert(String::from(id), definition.clone());
+    let known = get_problems(&current);
+    let problems = get_problems(&updated)
+        .into_iter()
+        .filter(|problem| !known.contains(problem))
+        .collect::<Vec<String>>();
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    let mut managed = read_managed_nodes().map_err(|e| vec![e])?;
+    managed.insert(String::from(id), definition);
+    write_managed_nodes(&managed).map_err(|e| vec![e])?;
+    *config = Some(Arc::new(updated));
+    Ok(())
+}
+
+/// Removes the definition of a node which was added through the API.
+///
+/// # Errors
+///
+/// Will return `Err` if the node is defined in config.yml or the managed nodes can not be written
+pub fn remove_node_definition(id: &str) -> Result<(), String> {
+    let mut config = CONFIG.lock().unwrap_or_else(PoisonError::into_inner);
+    if !is_managed_node(id) {
+        return Err(format!("{} is defined in config.yml, remove it there", id));
     }
+    let mut managed = read_managed_nodes()?;
+    managed.remove(id);
+    write_managed_nodes(&managed)?;
+    let mut updated = (*get_or_load(&mut config)).clone();
+    updated.nodes.remove(id);
+    *config = Some(Arc::new(updated));
+    Ok(())
+}
+
+/// Whether the node was added through the API and is not defined in config.yml.
+pub fn is_managed_node(id: &str) -> bool {
+    read_managed_nodes().map_or(false, |nodes| nodes.contains_key(id))
+        && read_config_file().map_or(false, |config| !config.nodes.contains_key(id))
+}
+
+/// Returns how the node is defined, `None` if it is not.
+pub fn get_node_definition(id: &str) -> Option<NodeDefinition> {
+    get_config().nodes.get(id).cloned()
+}
+
+pub fn get_log_sources_of(node: &Node) -> Vec<LogSource> {
+    let mut vec = get_config()
+        .nodes
+        .get(&node.id)
+        .map(NodeDefinition::get_log_sources)
+        .unwrap_or_default();
+    vec.push(LogSource::new(node.ipv4_address.clone(), HOST));
     vec
 }
 
@@ -81,16 +253,11 @@ pub fn get_nodes() -> Result<Vec<Node>, &'static str> {
 }
 
 pub fn get_all_nodes() -> Result<Vec<Node>, &'static str> {
-    let config = read_config();
-    let mut vec: Vec<Node> = Vec::new();
-    let nodes = config.get_table("nodes").unwrap();
-    for (id, values) in nodes {
-        vec.push(Node::from_config(
-            id.clone(),
-            &values.into_table().unwrap(),
-            get_log_sources(id.as_str()),
-        ));
-    }
+    let vec = get_config()
+        .nodes
+        .iter()
+        .filter_map(|(id, node)| node.to_node(id).ok())
+        .collect::<Vec<Node>>();
     if vec.is_empty() {
         Err("no nodes")
     } else {
@@ -113,92 +280,40 @@ pub fn get_node_by_id(id: &str, all: bool) -> Option<Node> {
     None
 }
 
-fn get_log_source_from_array(source: Vec<Value>, logtype: &LogSourceTypes) -> Vec<LogSource> {
-    let mut vec = Vec::new();
-    for path in source {
-        match logtype {
-            LogSourceTypes::HOST => vec.push(LogSource::host(path.into_str().unwrap())),
-            LogSourceTypes::SERIAL => vec.push(LogSource::serial(path.into_str().unwrap())),
-        }
-    }
-    vec
-}
-
-fn get_power_table_of(node: &Node) -> Option<HashMap<String, Value, RandomState>> {
-    let config = read_config();
-    let nodes = config.get_table("nodes").unwrap();
-    if let Some(node) = nodes.get(&node.id) {
-        if let Ok(node_table) = node.to_owned().into_table() {
-            if let Some(power_actions) = node_table.get("power") {
-                if let Ok(power_actions_table) = power_actions.to_owned().into_table() {
-                    return Some(power_actions_table);
-                }
-            }
-        }
-    }
-    Option::None
+fn get_from_node_config<T, F>(node: &Node, read: F) -> Option<T>
+where
+    F: FnOnce(&NodeDefinition) -> Option<T>,
+{
+    get_config().nodes.get(&node.id).and_then(read)
 }
 
 pub fn get_power_commands_of(node: &Node) -> PowerActionSet {
-    let mut on: Result<PowerAction, String> = Err("no on command".to_string());
-    let mut off: Result<PowerAction, String> = Err("no off command".to_string());
-    let mut reboot: Result<PowerAction, String> = Err("no reboot command".to_string());
-    if let Some(power_actions_table) = get_power_table_of(node) {
-        for (key, t) in [("on", ON), ("off", OFF), ("reboot", REBOOT)].to_vec() {
-            if let Some(entry) = power_actions_table.get(key) {
-                let command = entry.to_owned().into_str().unwrap();
-                match t {
-                    ON => on = PowerAction::parse(ON, &command),
-                    OFF => off = PowerAction::parse(OFF, &command),
-                    REBOOT => reboot = PowerAction::parse(REBOOT, &command),
-                }
-            }
-        }
-    }
-    PowerActionSet::new(on, off, reboot)
+    let config = get_config();
+    let power = config.nodes.get(&node.id).map(|node| &node.power);
+    let parse = |command: Option<&String>, action, missing: &str| match command {
+        Some(command) => PowerAction::parse(action, command),
+        None => Err(String::from(missing)),
+    };
+    PowerActionSet::new(
+        parse(power.and_then(|p| p.on.as_ref()), ON, "no on command"),
+        parse(power.and_then(|p| p.off.as_ref()), OFF, "no off command"),
+        parse(
+            power.and_then(|p| p.reboot.as_ref()),
+            REBOOT,
+            "no reboot command",
+        ),
+    )
 }
 
 pub fn get_default_os_for(node: &Node) -> Option<String> {
-    let config = read_config();
-    let nodes = config.get_table("nodes").unwrap();
-    if let Some(node) = nodes.get(&node.id) {
-        if let Ok(node_table) = node.to_owned().into_table() {
-            if let Some(default_os) = node_table.get("default-os") {
-                if let Ok(os) = default_os.to_owned().into_str() {
-                    return Some(os);
-                }
-            }
-        }
-    }
-    Option::None
+    get_from_node_config(node, |config| config.default_os.clone())
 }
 
+#[allow(dead_code)]
 pub fn get_default_user_for(node: &Node) -> Option<String> {
-    let config = read_config();
-    let nodes = config.get_table("nodes").unwrap();
-    if let Some(node) = nodes.get(&node.id) {
-        if let Ok(node_table) = node.to_owned().into_table() {
-            if let Some(default_user) = node_table.get("default-user") {
-                if let Ok(os) = default_user.to_owned().into_str() {
-                    return Some(os);
-                }
-            }
-        }
-    }
-    Option::None
+    get_from_node_config(node, |config| config.default_user.clone())
 }
 
 pub fn get_storage_device_of(node: &Node) -> Option<String> {
-    let config = read_config();
-    let nodes = config.get_table("nodes").unwrap();
-    if let Some(node) = nodes.get(&node.id) {
-        if let Ok(node_table) = node.to_owned().into_table() {
-            if let Some(storage) = node_table.get("storage-device") {
-                if let Ok(os) = storage.to_owned().into_str() {
-                    return Some(os);
-                }
-            }
-        }
-    }
-    Option::None
+    get_from_node_config(node, |config| config.storage_device.clone())
 }
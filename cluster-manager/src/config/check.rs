@@ -0,0 +1,77 @@
+use crate::config::ClusterConfig;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use structs::architecture::Architecture;
+
+/// Remembers which node uses `value` and describes the collision if another node already does.
+fn find_collision<'a>(
+    seen: &mut HashMap<String, &'a str>,
+    id: &'a str,
+    value: &Option<String>,
+    what: &str,
+) -> Option<String> {
+    let value = value.as_ref()?;
+    seen.insert(value.to_lowercase(), id)
+        .map(|other| format!("{} and {} share the {} {}", other, id, what, value))
+}
+
+/// Returns everything wrong with the configuration, it is fine to use if there is nothing.
+pub fn get_problems(config: &ClusterConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (key, value) in [
+        ("server-ip", &config.server_ip),
+        ("log-server", &config.log_server),
+        ("logstash-base-directory", &config.logstash_base_directory),
+    ] {
+        if value.is_none() {
+            problems.push(format!("{} is missing", key));
+        }
+    }
+    if config.nodes.is_empty() {
+        problems.push(String::from("no nodes are configured"));
+    }
+    let mut mac_addresses = HashMap::new();
+    let mut ipv4_addresses = HashMap::new();
+    let mut tftp_prefixes = HashMap::new();
+    if let Some(server_ip) = &config.server_ip {
+        ipv4_addresses.insert(server_ip.clone(), "server-ip");
+    }
+    for (id, node) in &config.nodes {
+        let missing = node.get_missing_fields();
+        if !missing.is_empty() {
+            problems.push(format!("{} misses {}", id, missing.join(", ")));
+        }
+        if let Some(architecture) = &node.architecture {
+            if Architecture::parse(architecture).is_err() {
+                problems.push(format!(
+                    "{} has the unknown architecture {}",
+                    id, architecture
+                ));
+            }
+        }
+        if let Some(address) = &node.ipv4_address {
+            if address.parse::<Ipv4Addr>().is_err() {
+                problems.push(format!("{} has the invalid IPv4 address {}", id, address));
+            }
+        }
+        problems.extend(find_collision(
+            &mut mac_addresses,
+            id,
+            &node.mac_address,
+            "MAC address",
+        ));
+        problems.extend(find_collision(
+            &mut ipv4_addresses,
+            id,
+            &node.ipv4_address,
+            "IPv4 address",
+        ));
+        problems.extend(find_collision(
+            &mut tftp_prefixes,
+            id,
+            &node.tftp_prefix,
+            "tftp-prefix",
+        ));
+    }
+    problems
+}
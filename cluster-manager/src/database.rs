@@ -1,14 +1,29 @@
-use crate::config::get_nodes;
+use self::migrations::{get_latest_version, get_schema_version};
+use crate::config::get_all_nodes;
 use crate::installer::BASE_DIR;
+use chrono::{NaiveDateTime, Utc};
+use r2d2_sqlite::SqliteConnectionManager;
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
-use rusqlite::{params, Connection, Error, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result, TransactionBehavior};
 use std::collections::HashMap;
+use std::fmt;
 use std::ops::Add;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use structs::audit_entry::{AuditEntry, AuditFilter};
 use structs::deployment::Deployment;
+use structs::job::{Job, JobStep};
 use structs::node::Node;
+use structs::node_state::{NodeState, NodeStateEntry};
+use structs::node_state_transition::NodeStateTransition;
+use structs::reservation::Reservation;
 use structs::service::Service;
+use structs::state::State;
+use structs::state_transition::StateTransition;
 use structs::task::Task;
+use structs::timeline_event::TimelineEvent;
+use structs::user::User;
 
 #[derive(Debug)]
 struct Person {
@@ -17,95 +32,145 @@ struct Person {
     data: Option<Vec<u8>>,
 }
 
-fn get_connection() -> Result<Connection, Error> {
-    Connection::open(format!("{}/{}", BASE_DIR, "cluster-manager.db"))
+pub mod migrations;
+
+const POOL_SIZE: u32 = 8;
+const BUSY_TIMEOUT: u64 = 5;
+
+type Pool = r2d2::Pool<SqliteConnectionManager>;
+type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+static POOL: Mutex<Option<Pool>> = Mutex::new(None);
+
+/// Connections are shared by all threads. With WAL readers do not block the writer and a busy
+/// database is retried for a while instead of failing right away.
+fn create_pool() -> Result<Pool, r2d2::Error> {
+    let manager = SqliteConnectionManager::file(format!("{}/{}", BASE_DIR, "cluster-manager.db"))
+        .with_init(|connection| {
+            connection.busy_timeout(Duration::from_secs(BUSY_TIMEOUT))?;
+            connection.execute_batch("PRAGMA journal_mode = WAL;")
+        });
+    r2d2::Pool::builder().max_size(POOL_SIZE).build(manager)
+}
+
+/// Everything that can go wrong with the database, either in SQLite itself or while waiting for
+/// a connection of the pool.
+#[derive(Debug)]
+pub enum Error {
+    Sqlite(rusqlite::Error),
+    Pool(r2d2::Error),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sqlite(e) => write!(f, "{}", e),
+            Error::Pool(e) => write!(f, "no database connection available: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::Sqlite(error)
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(error: r2d2::Error) -> Self {
+        Error::Pool(error)
+    }
+}
+
+fn get_pool() -> Result<Pool, Error> {
+    let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    if pool.is_none() {
+        *pool = Some(create_pool()?);
+    }
+    Ok(pool.as_ref().unwrap().clone())
+}
+
+/// Waits for a free connection without holding the lock on the pool, so other threads can get
+/// theirs in the meantime.
+fn get_connection() -> Result<PooledConnection, Error> {
+    Ok(get_pool()?.get()?)
+}
+
+/// Makes sure the schema of the database is the one this binary expects.
 pub fn check() -> bool {
-    setup()
-}
-
-fn setup() -> bool {
-    if let Ok(conn) = get_connection() {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS deployments  (
-                  id              INTEGER PRIMARY KEY AUTOINCREMENT,
-                  name            VARCHAR2(20) NOT NULL,
-                  owner           VARCHAR2(20) NOT NULL,
-                  start           DATETIME DEFAULT CURRENT_TIMESTAMP,
-                  end             DATETIME
-                  )",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS services (
-                  id              INTEGER PRIMARY KEY AUTOINCREMENT,
-                  name            VARCHAR2(20) NOT NULL,
-                  image           VARCHAR2(20) NOT NULL,
-                  deployment      INTEGER NOT NULL,
-                  node            VARCHAR2(20) NOT NULL,
-                  start           DATETIME DEFAULT CURRENT_TIMESTAMP,
-                  end             DATETIME,
-                  ipv4_address    VARCHAR(15),
-                  hostname        VARCHAR(100) NOT NULL,
-                  architecture    VARCHAR(20),
-                  FOREIGN KEY(deployment) REFERENCES deployments(id),
-                  FOREIGN KEY(node) REFERENCES nodes(id)
-                  )",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS logs (
-                timestamp       DATETIME DEFAULT CURRENT_TIMESTAMP,
-                message         TEXT NOT NULL
-                )",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS tasks  (
-                  id                INTEGER PRIMARY KEY AUTOINCREMENT,
-                  deployment        INTEGER NOT NULL,
-                  service           INTEGER,
-                  type              INTEGER NOT NULL,
-                  parameters        VARCHAR2(200) NOT NULL,
-                  during_deployment BOOLEAN NOT NULL CHECK (during_deployment IN (0, 1)),
-                  start             DATETIME DEFAULT NULL,
-                  end               DATETIME DEFAULT NULL,
-                  FOREIGN KEY(deployment) REFERENCES deployments(id),
-                  FOREIGN KEY(service) REFERENCES services(id)
-                  )",
-            [],
-        )
-        .unwrap();
-        return true;
+    let version = match get_schema_version() {
+        Ok(version) => version,
+        Err(e) => {
+            eprintln!("could not read schema version: {}", e);
+            return false;
+        }
+    };
+    let latest = get_latest_version();
+    if version > latest {
+        eprintln!(
+            "database schema version {} is newer than {} known to this cluster-manager, please upgrade",
+            version, latest
+        );
+        return false;
     }
-    false
+    if version < latest {
+        eprintln!(
+            "database schema version {} is older than {}, please run cluster-manager db migrate",
+            version, latest
+        );
+        return false;
+    }
+    true
+}
+
+/// Jobs which were running when the server went down will never finish.
+pub fn fail_unfinished_jobs() -> Result<usize, Error> {
+    let connection = get_connection()?;
+    Ok(connection.execute(
+        "UPDATE jobs SET finished = CURRENT_TIMESTAMP, success = 0 WHERE finished IS NULL",
+        [],
+    )?)
 }
 
 pub fn insert_deployment(deployment: &Deployment) -> Result<i64, Error> {
     let connection = get_connection()?;
-    let mut stmt = connection.prepare("INSERT INTO deployments (name, owner) VALUES (?1, ?2)")?;
-    let result = stmt.insert(params![deployment.name, deployment.owner]);
+    let mut stmt = connection.prepare(
+        "INSERT INTO deployments (name, owner, state, spec, rerun_of) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    let result = stmt.insert(params![
+        deployment.name,
+        deployment.owner,
+        State::Provisioning.get_name(),
+        serde_json::to_string(deployment).ok(),
+        deployment.rerun_of
+    ]);
     if let Ok(id) = result {
+        insert_state_transition(
+            &connection,
+            DEPLOYMENT,
+            id,
+            None,
+            &State::Provisioning,
+            None,
+        )?;
         for task in &deployment.tasks {
             insert_task(task, id).unwrap();
         }
     }
-    result
+    Ok(result?)
 }
 
 pub fn insert_service(service: &Service) -> Result<i64, Error> {
     let connection = get_connection()?;
     let mut stmt = connection.prepare(
-            "INSERT INTO services (name, image, deployment, node, ipv4_address, hostname, architecture) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            "INSERT INTO services (name, image, deployment, node, ipv4_address, hostname, architecture, state, image_checksum) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?;
     let arch = match &service.architecture {
         Some(a) => Some(a.get_name()),
         None => None,
     };
-    stmt.insert(params![
+    let id = stmt.insert(params![
         service.name,
         service.image,
         service.deployment.unwrap(),
@@ -113,7 +178,122 @@ pub fn insert_service(service: &Service) -> Result<i64, Error> {
         service.ipv4_address.as_ref(),
         service.hostname,
         arch.unwrap(),
-    ])
+        State::Provisioning.get_name(),
+        service.image_checksum,
+    ])?;
+    insert_state_transition(&connection, SERVICE, id, None, &State::Provisioning, None)?;
+    Ok(id)
+}
+
+const DEPLOYMENT: &str = "deployment";
+const SERVICE: &str = "service";
+
+fn insert_state_transition(
+    connection: &Connection,
+    entity: &str,
+    id: i64,
+    from: Option<&State>,
+    to: &State,
+    reason: Option<&str>,
+) -> Result<i64, Error> {
+    let mut stmt = connection.prepare(
+        "INSERT INTO state_transitions (entity, entity_id, from_state, to_state, reason) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    Ok(stmt.insert(params![
+        entity,
+        id,
+        from.map(State::get_name),
+        to.get_name(),
+        reason
+    ])?)
+}
+
+/// Moves the deployment or service into `state`, returns `false` if that transition is not allowed.
+fn set_state(
+    table: &str,
+    entity: &str,
+    id: i64,
+    state: &State,
+    reason: Option<&str>,
+) -> Result<bool, Error> {
+    let connection = get_connection()?;
+    let current: Option<String> = connection.query_row(
+        &format!("SELECT state FROM {} WHERE id = ?1", table),
+        params![id],
+        |row| row.get(0),
+    )?;
+    let current = current.and_then(|s| State::parse(&s).ok());
+    if let Some(current) = &current {
+        if !current.can_transition_to(state) {
+            return Ok(false);
+        }
+    }
+    connection.execute(
+        &format!("UPDATE {} SET state = ?2 WHERE id = ?1", table),
+        params![id, state.get_name()],
+    )?;
+    insert_state_transition(&connection, entity, id, current.as_ref(), state, reason)?;
+    Ok(true)
+}
+
+pub fn set_state_for_deployment(
+    id: i64,
+    state: &State,
+    reason: Option<&str>,
+) -> Result<bool, Error> {
+    set_state("deployments", DEPLOYMENT, id, state, reason)
+}
+
+pub fn set_state_for_service(id: i64, state: &State, reason: Option<&str>) -> Result<bool, Error> {
+    set_state("services", SERVICE, id, state, reason)
+}
+
+pub fn get_state_transitions_of_deployment(id: i64) -> Result<Vec<StateTransition>, Error> {
+    let mut vec = Vec::new();
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare(
+        "SELECT * FROM state_transitions t WHERE (t.entity = ?1 AND t.entity_id = ?3)
+            OR (t.entity = ?2 AND t.entity_id IN (SELECT s.id FROM services s WHERE s.deployment = ?3))
+            ORDER BY t.timestamp, t.id",
+    )?;
+    let iter = stmt.query_map(params![DEPLOYMENT, SERVICE, id], |row| {
+        Ok(StateTransition::from_row(row))
+    })?;
+    iter.filter(std::result::Result::is_ok)
+        .for_each(|t| vec.push(t.unwrap()));
+    Ok(vec)
+}
+
+pub fn insert_timeline_event(
+    deployment: i64,
+    service: Option<i64>,
+    message: &str,
+) -> Result<i64, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection
+        .prepare("INSERT INTO timeline (deployment, service, message) VALUES (?1, ?2, ?3)")?;
+    Ok(stmt.insert(params![deployment, service, message])?)
+}
+
+pub fn get_timeline_of_deployment(id: i64) -> Result<Vec<TimelineEvent>, Error> {
+    let mut vec = Vec::new();
+    let connection = get_connection()?;
+    let mut stmt = connection
+        .prepare("SELECT * FROM timeline t WHERE t.deployment = ?1 ORDER BY t.timestamp, t.id")?;
+    let iter = stmt.query_map([id], |row| Ok(TimelineEvent::from_row(row)))?;
+    iter.filter(std::result::Result::is_ok)
+        .for_each(|e| vec.push(e.unwrap()));
+    Ok(vec)
+}
+
+/// Returns when all services of the deployment were up, `None` if they are not yet.
+pub fn get_running_since(id: i64) -> Result<Option<NaiveDateTime>, Error> {
+    let connection = get_connection()?;
+    Ok(connection.query_row(
+        "SELECT MIN(t.timestamp) FROM state_transitions t WHERE t.entity = ?1 AND t.entity_id = ?2 AND t.to_state = ?3",
+        params![DEPLOYMENT, id, State::Running.get_name()],
+        |row| row.get(0),
+    )?)
 }
 
 pub fn insert_task(task: &Task, deployment_id: i64) -> Result<i64, Error> {
@@ -122,41 +302,129 @@ pub fn insert_task(task: &Task, deployment_id: i64) -> Result<i64, Error> {
         let mut stmt = connection.prepare(
             "INSERT INTO tasks (deployment, service, type, parameters, during_deployment) VALUES (?1, ?2, ?3, ?4, ?5)",
         )?;
-        stmt.insert(params![
+        Ok(stmt.insert(params![
             deployment_id,
             task.service.as_ref().unwrap().id.unwrap(),
             task.task_type as usize,
             task.parameters,
             if task.during_deployment { 1 } else { 0 },
-        ])
+        ])?)
     } else {
         let mut stmt = connection
             .prepare("INSERT INTO tasks (deployment, type, parameters, during_deployment) VALUES (?1, ?2, ?3, ?4)")?;
-        stmt.insert(params![
+        Ok(stmt.insert(params![
             deployment_id,
             task.task_type as usize,
             task.parameters,
             if task.during_deployment { 1 } else { 0 },
-        ])
+        ])?)
     };
 }
 
-pub fn get_idle_nodes() -> Result<Vec<Node>, Error> {
+/// Returns the available nodes, the ones reserved for `owner` first.
+pub fn get_idle_nodes(owner: &str) -> Result<Vec<Node>, Error> {
     let mut vec = Vec::new();
-    let mut idle_nodes = HashMap::new();
-    if let Ok(nodes) = get_nodes() {
-        for node in nodes {
-            idle_nodes.insert(node.id.clone(), node);
-        }
-        for service in get_running_services().unwrap() {
-            idle_nodes.remove(&service.node.unwrap());
-        }
-        for (_key, node) in idle_nodes {
-            vec.push(node.to_owned());
+    let mut reserved = Vec::new();
+    let states = get_node_states()?;
+    let owned = get_active_reservations()?
+        .into_iter()
+        .filter(|r| r.owner.eq(owner))
+        .flat_map(|r| r.nodes)
+        .collect::<Vec<String>>();
+    for node in get_all_nodes().unwrap_or_default() {
+        match states.get(&node.id).map(|entry| &entry.state) {
+            Some(NodeState::Available) => vec.push(node),
+            Some(NodeState::Reserved) if owned.contains(&node.id) => reserved.push(node),
+            _ => {}
         }
     }
     vec.shuffle(&mut thread_rng());
-    Ok(vec)
+    reserved.shuffle(&mut thread_rng());
+    reserved.append(&mut vec);
+    Ok(reserved)
+}
+
+/// Moves the node into `state`, returns `false` if it already is in this state. The state is read
+/// and changed in one transaction, so concurrent changes can not record a wrong transition.
+pub fn set_state_for_node(
+    node: &str,
+    state: &NodeState,
+    reason: Option<&str>,
+) -> Result<bool, Error> {
+    let mut connection = get_connection()?;
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let current: Option<String> = transaction
+        .query_row(
+            "SELECT n.state FROM node_states n WHERE n.node = ?1",
+            params![node],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let current = current.and_then(|s| NodeState::parse(&s).ok());
+    if current.as_ref() == Some(state) {
+        return Ok(false);
+    }
+    transaction.execute(
+        "INSERT OR REPLACE INTO node_states (node, state, reason, since) VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)",
+        params![node, state.get_name(), reason],
+    )?;
+    transaction.execute(
+        "INSERT INTO node_state_transitions (node, from_state, to_state, reason) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            node,
+            current.as_ref().map(NodeState::get_name),
+            state.get_name(),
+            reason
+        ],
+    )?;
+    transaction.commit()?;
+    Ok(true)
+}
+
+/// Returns the state of every node which has one.
+pub fn get_node_states() -> Result<HashMap<String, NodeStateEntry>, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection
+        .prepare("SELECT n.node, n.state, n.reason, n.expected_return FROM node_states n")?;
+    let iter = stmt.query_map([], |row| {
+        let state: String = row.get(1)?;
+        Ok((
+            row.get(0)?,
+            NodeStateEntry {
+                state: NodeState::parse(&state).unwrap_or(NodeState::Unknown),
+                reason: row.get(2)?,
+                expected_return: row.get(3)?,
+            },
+        ))
+    })?;
+    Ok(iter.filter_map(std::result::Result::ok).collect())
+}
+
+/// Entering another state forgets the expected return.
+pub fn set_expected_return_for_node(
+    node: &str,
+    expected_return: Option<&NaiveDateTime>,
+) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut stmt =
+        connection.prepare("UPDATE node_states SET expected_return = ?2 WHERE node = ?1")?;
+    Ok(stmt.execute(params![node, expected_return])?)
+}
+
+/// Changes why the node is in its state without entering it again.
+pub fn set_reason_for_node(node: &str, reason: &str) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare("UPDATE node_states SET reason = ?2 WHERE node = ?1")?;
+    Ok(stmt.execute(params![node, reason])?)
+}
+
+pub fn get_state_transitions_of_node(node: &str) -> Result<Vec<NodeStateTransition>, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare(
+        "SELECT * FROM node_state_transitions t WHERE t.node = ?1 ORDER BY t.timestamp, t.id",
+    )?;
+    let iter = stmt.query_map(params![node], |row| Ok(NodeStateTransition::from_row(row)))?;
+    Ok(iter.filter_map(std::result::Result::ok).collect())
 }
 
 pub fn get_running_services() -> Result<Vec<Service>, Error> {
@@ -219,7 +487,23 @@ pub fn set_enddate_for_service(id: i64) -> Result<usize, Error> {
     let connection = get_connection()?;
     let mut statement =
         connection.prepare("UPDATE services SET end = CURRENT_TIMESTAMP WHERE id = ?1")?;
-    statement.execute(params![id])
+    Ok(statement.execute(params![id])?)
+}
+
+pub fn get_deployment_names() -> Result<HashMap<i64, String>, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare("SELECT d.id, d.name FROM deployments d")?;
+    let iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(iter.filter_map(std::result::Result::ok).collect())
+}
+
+/// Returns how many services each deployment has.
+pub fn get_service_counts() -> Result<HashMap<i64, usize>, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection
+        .prepare("SELECT s.deployment, COUNT(*) FROM services s GROUP BY s.deployment")?;
+    let iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?;
+    Ok(iter.filter_map(std::result::Result::ok).collect())
 }
 
 pub fn get_deployments() -> Result<Vec<Deployment>, Error> {
@@ -233,6 +517,17 @@ pub fn get_deployments() -> Result<Vec<Deployment>, Error> {
     Ok(vec)
 }
 
+pub fn get_deployments_by_owner(owner: &str) -> Result<Vec<Deployment>, Error> {
+    let mut vec = Vec::new();
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare("SELECT * FROM deployments d WHERE d.owner = ?1")?;
+    let node_iter = stmt.query_map([owner], |row| Ok(Deployment::from_row(row)))?;
+    node_iter
+        .filter(std::result::Result::is_ok)
+        .for_each(|s| vec.push(s.unwrap()));
+    Ok(vec)
+}
+
 pub fn get_running_deployments() -> Result<Vec<Deployment>, Error> {
     let mut vec = Vec::new();
     let connection = get_connection()?;
@@ -264,30 +559,314 @@ pub fn set_enddate_for_deployment(id: i64) -> Result<usize, Error> {
     let connection = get_connection()?;
     let mut statement =
         connection.prepare("UPDATE deployments SET end = CURRENT_TIMESTAMP WHERE id = ?1")?;
-    statement.execute(params![id])
+    Ok(statement.execute(params![id])?)
+}
+
+/// Returns the deployment as it was submitted, `None` for deployments from before specifications
+/// were stored.
+pub fn get_spec_of_deployment(id: i64) -> Result<Option<String>, Error> {
+    let connection = get_connection()?;
+    Ok(
+        connection.query_row("SELECT spec FROM deployments WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })?,
+    )
+}
+
+pub fn set_failure_for_deployment(id: i64, reason: &str) -> Result<usize, Error> {
+    set_state_for_deployment(id, &State::Failed, Some(reason))?;
+    let connection = get_connection()?;
+    let mut statement = connection
+        .prepare("UPDATE deployments SET end = CURRENT_TIMESTAMP, failure = ?2 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, reason])?)
 }
 
 pub fn get_tasks_by_deployment(id: i64) -> Result<Vec<Task>, Error> {
     let mut vec = Vec::new();
+    let deployment = get_deployment_by_id(id, false)?;
     let connection = get_connection()?;
-    let query = String::from("SELECT * FROM tasks t WHERE t.deployment = ?1");
-    let mut stmt = connection.prepare(&query)?;
+    // the columns of the service follow the ones Task::from_row reads
+    let mut stmt = connection.prepare(
+        "SELECT t.id, t.deployment, t.service, t.type, t.parameters, t.during_deployment, t.start,
+            t.end, t.hits, t.executed, t.success, s.* FROM tasks t
+            LEFT JOIN services s ON s.id = t.service
+            WHERE t.deployment = ?1 ORDER BY t.id",
+    )?;
     let iter = stmt.query_map([id], |row| {
-        let deployment = get_deployment_by_id(row.get(1).unwrap(), false).unwrap();
-        let service = match row.get(2) {
-            Ok(service_id) => Some(get_service_by_id(service_id, false).unwrap()),
-            Err(_) => None,
+        let service = match row.get::<_, Option<i64>>(TASK_COLUMNS)? {
+            Some(_) => Some(Service::from_row_at(row, TASK_COLUMNS)),
+            None => None,
         };
-        Ok(Task::from_row(deployment, service, row))
+        Ok(Task::from_row(deployment.clone(), service, row))
     })?;
     iter.filter(std::result::Result::is_ok)
         .for_each(|s| vec.push(s.unwrap()));
     Ok(vec)
 }
 
-pub fn set_end_and_executed_for_task(id: i64) -> Result<usize, Error> {
+const TASK_COLUMNS: usize = 11;
+
+pub fn set_startdate_for_task(id: i64) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare("UPDATE tasks SET start = ?2 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, Utc::now().naive_utc()])?)
+}
+
+/// Ends the task and records that it was executed and whether it succeeded.
+pub fn set_outcome_for_task(id: i64, success: bool) -> Result<usize, Error> {
     let connection = get_connection()?;
     let mut statement = connection
-        .prepare("UPDATE tasks SET end = CURRENT_TIMESTAMP, executed = 1 WHERE id = ?1")?;
-    statement.execute(params![id])
+        .prepare("UPDATE tasks SET end = ?2, executed = 1, success = ?3 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, Utc::now().naive_utc(), success])?)
+}
+
+pub fn set_hits_for_task(id: i64, hits: i64) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare("UPDATE tasks SET hits = ?2 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, hits])?)
+}
+
+/// Returns how far the log files of the deployment have been read, in bytes per file.
+pub fn get_log_offsets(deployment: i64) -> Result<HashMap<String, u64>, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection
+        .prepare("SELECT o.file, o.position FROM log_offsets o WHERE o.deployment = ?1")?;
+    let iter = stmt.query_map([deployment], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+    })?;
+    Ok(iter.filter_map(std::result::Result::ok).collect())
+}
+
+pub fn set_log_offset(deployment: i64, file: &str, offset: u64) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare(
+        "INSERT OR REPLACE INTO log_offsets (deployment, file, position) VALUES (?1, ?2, ?3)",
+    )?;
+    Ok(statement.execute(params![deployment, file, offset as i64])?)
+}
+
+/// Adds the reservation unless one of its nodes is reserved during its time window, which returns
+/// `None`. Both are done in one transaction, so concurrent reservations can not take the same node.
+pub fn insert_reservation(reservation: &Reservation) -> Result<Option<i64>, Error> {
+    let mut connection = get_connection()?;
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let conflicting = query_reservations(
+        &transaction,
+        "SELECT * FROM reservations r WHERE r.released IS NULL AND r.start < ?2 AND r.end > ?1",
+        &[&reservation.start, &reservation.end],
+    )?;
+    if reservation
+        .nodes
+        .iter()
+        .any(|node| conflicting.iter().any(|r| r.contains_node(node)))
+    {
+        return Ok(None);
+    }
+    transaction.execute(
+        "INSERT INTO reservations (owner, start, end) VALUES (?1, ?2, ?3)",
+        params![reservation.owner, reservation.start, reservation.end],
+    )?;
+    let id = transaction.last_insert_rowid();
+    {
+        let mut stmt = transaction
+            .prepare("INSERT INTO reserved_nodes (reservation, node) VALUES (?1, ?2)")?;
+        for node in &reservation.nodes {
+            stmt.insert(params![id, node])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(Some(id))
+}
+
+fn get_reservations_by_query(
+    query: &str,
+    parameters: &[&dyn rusqlite::ToSql],
+) -> Result<Vec<Reservation>, Error> {
+    let connection = get_connection()?;
+    query_reservations(&connection, query, parameters)
+}
+
+fn query_reservations(
+    connection: &Connection,
+    query: &str,
+    parameters: &[&dyn rusqlite::ToSql],
+) -> Result<Vec<Reservation>, Error> {
+    let mut vec = Vec::new();
+    let mut stmt = connection.prepare(query)?;
+    let iter = stmt.query_map(parameters, |row| Ok(Reservation::from_row(row)))?;
+    iter.filter(std::result::Result::is_ok)
+        .for_each(|r| vec.push(r.unwrap()));
+    let mut stmt = connection.prepare("SELECT node FROM reserved_nodes WHERE reservation = ?1")?;
+    for reservation in &mut vec {
+        let nodes = stmt.query_map([reservation.id.unwrap()], |row| row.get(0))?;
+        reservation.nodes = nodes.filter_map(std::result::Result::ok).collect();
+    }
+    Ok(vec)
+}
+
+pub fn get_reservations() -> Result<Vec<Reservation>, Error> {
+    get_reservations_by_query("SELECT * FROM reservations r", &[])
+}
+
+pub fn get_active_reservations() -> Result<Vec<Reservation>, Error> {
+    let now = Utc::now().naive_utc();
+    get_reservations_by_query(
+        "SELECT * FROM reservations r WHERE r.released IS NULL AND r.start <= ?1 AND r.end > ?1",
+        &[&now],
+    )
+}
+
+pub fn get_upcoming_reservations() -> Result<Vec<Reservation>, Error> {
+    let now = Utc::now().naive_utc();
+    get_reservations_by_query(
+        "SELECT * FROM reservations r WHERE r.released IS NULL AND r.end > ?1",
+        &[&now],
+    )
+}
+
+pub fn get_overlapping_reservations(
+    start: &NaiveDateTime,
+    end: &NaiveDateTime,
+) -> Result<Vec<Reservation>, Error> {
+    get_reservations_by_query(
+        "SELECT * FROM reservations r WHERE r.released IS NULL AND r.start < ?2 AND r.end > ?1",
+        &[start, end],
+    )
+}
+
+pub fn get_reservation_by_id(id: i64) -> Result<Reservation, Error> {
+    match get_reservations_by_query("SELECT * FROM reservations r WHERE r.id = ?1", &[&id])?
+        .into_iter()
+        .next()
+    {
+        Some(reservation) => Ok(reservation),
+        None => Err(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows)),
+    }
+}
+
+pub fn set_enddate_for_reservation(id: i64, end: &NaiveDateTime) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare("UPDATE reservations SET end = ?2 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, end])?)
+}
+
+pub fn set_released_for_reservation(id: i64) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement =
+        connection.prepare("UPDATE reservations SET released = ?2 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, Utc::now().naive_utc()])?)
+}
+
+pub fn insert_user(user: &User, token_hash: &str) -> Result<i64, Error> {
+    let connection = get_connection()?;
+    let mut stmt =
+        connection.prepare("INSERT INTO users (name, token_hash, admin) VALUES (?1, ?2, ?3)")?;
+    Ok(stmt.insert(params![
+        user.name,
+        token_hash,
+        if user.admin { 1 } else { 0 }
+    ])?)
+}
+
+pub fn get_users() -> Result<Vec<User>, Error> {
+    let mut vec = Vec::new();
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare("SELECT * FROM users u")?;
+    let iter = stmt.query_map([], |row| Ok(User::from_row(row)))?;
+    iter.filter(std::result::Result::is_ok)
+        .for_each(|u| vec.push(u.unwrap()));
+    Ok(vec)
+}
+
+pub fn get_user_by_token_hash(token_hash: &str) -> Result<User, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare("SELECT * FROM users u WHERE u.token_hash = ?1")?;
+    Ok(statement.query_row(params![token_hash], |row| Ok(User::from_row(row)))?)
+}
+
+pub fn set_token_hash_for_user(name: &str, token_hash: &str) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare("UPDATE users SET token_hash = ?2 WHERE name = ?1")?;
+    Ok(statement.execute(params![name, token_hash])?)
+}
+
+pub fn delete_user(name: &str) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection.prepare("DELETE FROM users WHERE name = ?1")?;
+    Ok(statement.execute(params![name])?)
+}
+
+pub fn insert_job(kind: &str, owner: &str) -> Result<i64, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare("INSERT INTO jobs (kind, owner) VALUES (?1, ?2)")?;
+    Ok(stmt.insert(params![kind, owner])?)
+}
+
+pub fn insert_job_step(job: i64, message: &str, status: Option<bool>) -> Result<i64, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare(
+        "INSERT INTO job_steps (job, timestamp, message, status) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    Ok(stmt.insert(params![job, Utc::now().naive_utc(), message, status])?)
+}
+
+pub fn set_finished_for_job(
+    id: i64,
+    success: bool,
+    deployment: Option<i64>,
+) -> Result<usize, Error> {
+    let connection = get_connection()?;
+    let mut statement = connection
+        .prepare("UPDATE jobs SET finished = ?2, success = ?3, deployment = ?4 WHERE id = ?1")?;
+    Ok(statement.execute(params![id, Utc::now().naive_utc(), success, deployment])?)
+}
+
+/// Returns the job with all steps newer than `after`.
+pub fn get_job_by_id(id: i64, after: i64) -> Result<Job, Error> {
+    let connection = get_connection()?;
+    let mut job = connection.query_row("SELECT * FROM jobs j WHERE j.id = ?1", [id], |row| {
+        Ok(Job::from_row(row))
+    })?;
+    let mut stmt = connection
+        .prepare("SELECT * FROM job_steps s WHERE s.job = ?1 AND s.id > ?2 ORDER BY s.id")?;
+    let iter = stmt.query_map(params![id, after], |row| Ok(JobStep::from_row(row)))?;
+    job.steps = iter.filter_map(std::result::Result::ok).collect();
+    Ok(job)
+}
+
+pub fn insert_audit_entry(
+    actor: &str,
+    action: &str,
+    target: &str,
+    node: Option<&str>,
+    success: bool,
+) -> Result<i64, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare(
+        "INSERT INTO logs (timestamp, message, actor, action, target, node, success) VALUES (?1, '', ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    Ok(stmt.insert(params![
+        Utc::now().naive_utc(),
+        actor,
+        action,
+        target,
+        node,
+        success
+    ])?)
+}
+
+/// Returns the audit log matching `filter`, oldest first.
+pub fn get_audit_entries(filter: &AuditFilter) -> Result<Vec<AuditEntry>, Error> {
+    let connection = get_connection()?;
+    let mut stmt = connection.prepare(
+        "SELECT l.timestamp, l.actor, l.action, l.target, l.node, l.success FROM logs l
+            WHERE (?1 IS NULL OR l.actor = ?1) AND (?2 IS NULL OR l.node = ?2)
+            AND (?3 IS NULL OR l.timestamp >= ?3) AND (?4 IS NULL OR l.timestamp <= ?4)
+            ORDER BY l.timestamp, l.rowid",
+    )?;
+    let iter = stmt.query_map(
+        params![filter.user, filter.node, filter.since, filter.until],
+        |row| Ok(AuditEntry::from_row(row)),
+    )?;
+    Ok(iter.filter_map(std::result::Result::ok).collect())
 }
@@ -0,0 +1,374 @@
+use super::{get_connection, Error};
+use rusqlite::{Connection, Result};
+
+/// A change of the database schema. Migrations are applied in order of their version and must be
+/// idempotent, databases from before versioning already contain parts of them.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+const MIGRATIONS: [Migration; 13] = [
+    Migration {
+        version: 1,
+        description: "create deployments, services, tasks, reservations and users",
+        apply: create_initial_tables,
+    },
+    Migration {
+        version: 2,
+        description: "track states and failures of deployments and services",
+        apply: add_states,
+    },
+    Migration {
+        version: 3,
+        description: "create jobs and job steps",
+        apply: create_jobs,
+    },
+    Migration {
+        version: 4,
+        description: "store specs of deployments and what they rerun",
+        apply: add_specs,
+    },
+    Migration {
+        version: 5,
+        description: "store checksums of deployed images",
+        apply: add_image_checksums,
+    },
+    Migration {
+        version: 6,
+        description: "store hits of stop conditions and log offsets",
+        apply: add_log_offsets,
+    },
+    Migration {
+        version: 7,
+        description: "create the timeline of deployments",
+        apply: create_timeline,
+    },
+    Migration {
+        version: 8,
+        description: "create suspect nodes",
+        apply: create_suspect_nodes,
+    },
+    Migration {
+        version: 9,
+        description: "store whether tasks were executed",
+        apply: add_executed_to_tasks,
+    },
+    Migration {
+        version: 10,
+        description: "record the audit log in the logs table",
+        apply: add_audit_columns_to_logs,
+    },
+    Migration {
+        version: 11,
+        description: "track states of nodes, suspect nodes become failed ones",
+        apply: create_node_states,
+    },
+    Migration {
+        version: 12,
+        description: "store when nodes in maintenance return",
+        apply: add_expected_return_to_node_states,
+    },
+    Migration {
+        version: 13,
+        description: "store whether tasks succeeded",
+        apply: add_success_to_tasks,
+    },
+];
+
+#[must_use]
+pub fn get_migrations() -> &'static [Migration] {
+    &MIGRATIONS
+}
+
+#[must_use]
+pub fn get_latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub fn get_schema_version() -> Result<i64, Error> {
+    let connection = get_connection()?;
+    Ok(read_user_version(&connection)?)
+}
+
+fn read_user_version(connection: &Connection) -> Result<i64> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Applies every migration newer than the schema of the database, each in its own transaction,
+/// and returns the migrations it applied.
+///
+/// # Errors
+///
+/// Will return `Err` if the database is newer than this binary or a migration failed
+pub fn migrate() -> Result<Vec<&'static Migration>, String> {
+    let mut connection = get_connection().map_err(|e| e.to_string())?;
+    let version = read_user_version(&connection).map_err(|e| e.to_string())?;
+    if version > get_latest_version() {
+        return Err(format!(
+            "database schema version {} is newer than {}",
+            version,
+            get_latest_version()
+        ));
+    }
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let transaction = connection.transaction().map_err(|e| e.to_string())?;
+        (migration.apply)(&transaction)
+            .and_then(|_| transaction.pragma_update(None, "user_version", migration.version))
+            .and_then(|_| transaction.commit())
+            .map_err(|e| format!("migration {} failed: {}", migration.version, e))?;
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+fn add_column(connection: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    if connection
+        .prepare(&format!("SELECT {} FROM {} LIMIT 0", column, table))
+        .is_err()
+    {
+        connection.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn create_initial_tables(connection: &Connection) -> Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS deployments  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              name            VARCHAR2(20) NOT NULL,
+              owner           VARCHAR2(20) NOT NULL,
+              start           DATETIME DEFAULT CURRENT_TIMESTAMP,
+              end             DATETIME
+              );
+        CREATE TABLE IF NOT EXISTS services (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              name            VARCHAR2(20) NOT NULL,
+              image           VARCHAR2(20) NOT NULL,
+              deployment      INTEGER NOT NULL,
+              node            VARCHAR2(20) NOT NULL,
+              start           DATETIME DEFAULT CURRENT_TIMESTAMP,
+              end             DATETIME,
+              ipv4_address    VARCHAR(15),
+              hostname        VARCHAR(100) NOT NULL,
+              architecture    VARCHAR(20),
+              FOREIGN KEY(deployment) REFERENCES deployments(id),
+              FOREIGN KEY(node) REFERENCES nodes(id)
+              );
+        CREATE TABLE IF NOT EXISTS logs (
+              timestamp       DATETIME DEFAULT CURRENT_TIMESTAMP,
+              message         TEXT NOT NULL
+              );
+        CREATE TABLE IF NOT EXISTS tasks  (
+              id                INTEGER PRIMARY KEY AUTOINCREMENT,
+              deployment        INTEGER NOT NULL,
+              service           INTEGER,
+              type              INTEGER NOT NULL,
+              parameters        VARCHAR2(200) NOT NULL,
+              during_deployment BOOLEAN NOT NULL CHECK (during_deployment IN (0, 1)),
+              start             DATETIME DEFAULT NULL,
+              end               DATETIME DEFAULT NULL,
+              FOREIGN KEY(deployment) REFERENCES deployments(id),
+              FOREIGN KEY(service) REFERENCES services(id)
+              );
+        CREATE TABLE IF NOT EXISTS reservations  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              owner           VARCHAR2(20) NOT NULL,
+              start           DATETIME NOT NULL,
+              end             DATETIME NOT NULL,
+              released        DATETIME DEFAULT NULL
+              );
+        CREATE TABLE IF NOT EXISTS reserved_nodes  (
+              reservation     INTEGER NOT NULL,
+              node            VARCHAR2(20) NOT NULL,
+              FOREIGN KEY(reservation) REFERENCES reservations(id)
+              );
+        CREATE TABLE IF NOT EXISTS users  (
+              name            VARCHAR2(20) PRIMARY KEY,
+              token_hash      VARCHAR2(64) NOT NULL UNIQUE,
+              admin           BOOLEAN NOT NULL CHECK (admin IN (0, 1)),
+              created         DATETIME DEFAULT CURRENT_TIMESTAMP
+              );",
+    )
+}
+
+fn add_states(connection: &Connection) -> Result<()> {
+    add_column(connection, "deployments", "failure", "TEXT DEFAULT NULL")?;
+    for table in &["deployments", "services"] {
+        add_column(connection, table, "state", "VARCHAR2(20) DEFAULT NULL")?;
+        // rows from before states were tracked
+        connection.execute(
+            &format!(
+                "UPDATE {} SET state = CASE WHEN end IS NULL THEN 'running' ELSE 'finished' END WHERE state IS NULL",
+                table
+            ),
+            [],
+        )?;
+    }
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS state_transitions  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              entity          VARCHAR2(20) NOT NULL,
+              entity_id       INTEGER NOT NULL,
+              from_state      VARCHAR2(20),
+              to_state        VARCHAR2(20) NOT NULL,
+              timestamp       DATETIME DEFAULT CURRENT_TIMESTAMP,
+              reason          TEXT
+              );",
+    )
+}
+
+fn create_jobs(connection: &Connection) -> Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS jobs  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              kind            VARCHAR2(20) NOT NULL,
+              owner           VARCHAR2(20) NOT NULL,
+              deployment      INTEGER DEFAULT NULL,
+              created         DATETIME DEFAULT CURRENT_TIMESTAMP,
+              finished        DATETIME DEFAULT NULL,
+              success         BOOLEAN DEFAULT NULL CHECK (success IN (0, 1)),
+              FOREIGN KEY(deployment) REFERENCES deployments(id)
+              );
+        CREATE TABLE IF NOT EXISTS job_steps  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              job             INTEGER NOT NULL,
+              timestamp       DATETIME DEFAULT CURRENT_TIMESTAMP,
+              message         TEXT NOT NULL,
+              status          BOOLEAN DEFAULT NULL CHECK (status IN (0, 1)),
+              FOREIGN KEY(job) REFERENCES jobs(id)
+              );",
+    )
+}
+
+fn add_specs(connection: &Connection) -> Result<()> {
+    add_column(connection, "deployments", "spec", "TEXT DEFAULT NULL")?;
+    add_column(
+        connection,
+        "deployments",
+        "rerun_of",
+        "INTEGER DEFAULT NULL",
+    )
+}
+
+fn add_image_checksums(connection: &Connection) -> Result<()> {
+    add_column(
+        connection,
+        "services",
+        "image_checksum",
+        "VARCHAR2(64) DEFAULT NULL",
+    )
+}
+
+fn add_log_offsets(connection: &Connection) -> Result<()> {
+    add_column(connection, "tasks", "hits", "INTEGER NOT NULL DEFAULT 0")?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS log_offsets  (
+              deployment      INTEGER NOT NULL,
+              file            TEXT NOT NULL,
+              position        INTEGER NOT NULL,
+              PRIMARY KEY(deployment, file),
+              FOREIGN KEY(deployment) REFERENCES deployments(id)
+              );",
+    )
+}
+
+fn create_timeline(connection: &Connection) -> Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS timeline  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              deployment      INTEGER NOT NULL,
+              service         INTEGER DEFAULT NULL,
+              timestamp       DATETIME DEFAULT CURRENT_TIMESTAMP,
+              message         TEXT NOT NULL,
+              FOREIGN KEY(deployment) REFERENCES deployments(id),
+              FOREIGN KEY(service) REFERENCES services(id)
+              );",
+    )
+}
+
+fn create_suspect_nodes(connection: &Connection) -> Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS suspect_nodes  (
+              node            VARCHAR2(20) PRIMARY KEY,
+              reason          TEXT NOT NULL,
+              since           DATETIME DEFAULT CURRENT_TIMESTAMP
+              );",
+    )
+}
+
+fn add_executed_to_tasks(connection: &Connection) -> Result<()> {
+    add_column(
+        connection,
+        "tasks",
+        "executed",
+        "BOOLEAN NOT NULL DEFAULT 0 CHECK (executed IN (0, 1))",
+    )
+}
+
+fn add_audit_columns_to_logs(connection: &Connection) -> Result<()> {
+    add_column(connection, "logs", "actor", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "logs", "action", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "logs", "target", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "logs", "node", "VARCHAR2(20) DEFAULT NULL")?;
+    add_column(
+        connection,
+        "logs",
+        "success",
+        "BOOLEAN NOT NULL DEFAULT 0 CHECK (success IN (0, 1))",
+    )?;
+    connection.execute_batch("CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (timestamp);")
+}
+
+fn create_node_states(connection: &Connection) -> Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS node_states  (
+              node            VARCHAR2(20) PRIMARY KEY,
+              state           VARCHAR2(20) NOT NULL,
+              reason          TEXT,
+              since           DATETIME DEFAULT CURRENT_TIMESTAMP
+              );
+        CREATE TABLE IF NOT EXISTS node_state_transitions  (
+              id              INTEGER PRIMARY KEY AUTOINCREMENT,
+              node            VARCHAR2(20) NOT NULL,
+              from_state      VARCHAR2(20),
+              to_state        VARCHAR2(20) NOT NULL,
+              timestamp       DATETIME DEFAULT CURRENT_TIMESTAMP,
+              reason          TEXT
+              );
+        CREATE TABLE IF NOT EXISTS suspect_nodes  (
+              node            VARCHAR2(20) PRIMARY KEY,
+              reason          TEXT NOT NULL,
+              since           DATETIME DEFAULT CURRENT_TIMESTAMP
+              );
+        INSERT OR IGNORE INTO node_states (node, state, reason, since)
+              SELECT s.node, 'failed', s.reason, s.since FROM suspect_nodes s;
+        INSERT INTO node_state_transitions (node, to_state, timestamp, reason)
+              SELECT s.node, 'failed', s.since, s.reason FROM suspect_nodes s
+              WHERE s.node NOT IN (SELECT t.node FROM node_state_transitions t);
+        DROP TABLE suspect_nodes;",
+    )
+}
+
+fn add_expected_return_to_node_states(connection: &Connection) -> Result<()> {
+    add_column(
+        connection,
+        "node_states",
+        "expected_return",
+        "DATETIME DEFAULT NULL",
+    )
+}
+
+fn add_success_to_tasks(connection: &Connection) -> Result<()> {
+    add_column(
+        connection,
+        "tasks",
+        "success",
+        "BOOLEAN DEFAULT NULL CHECK (success IN (0, 1))",
+    )
+}
@@ -1,105 +1,389 @@
+use crate::audit::{as_actor, get_actor, record_for_nodes};
+use crate::boot_watchdog::{watch_boot, BootPhase, BootStart};
 use crate::config::{
-    get, get_default_os_for, get_log_sources_of, get_node_by_id, get_storage_device_of,
+    get_config, get_default_os_for, get_log_sources_of, get_node_by_id, get_storage_device_of,
 };
-use crate::database::{get_idle_nodes, insert_deployment, insert_service, insert_task};
 use crate::installer::{
     BASE_DIR, COPY, FDISK, KPARTX, LVDISPLAY, MOUNT, NFS_BASE_DIR, OS_IMAGES_DIR, PVS, QEMU_IMG,
     RESULTS_DIR, RSYNC, SCREEN, SSH, TFTP_BASE_DIR, TMP_DIR, UMOUNT, UNZIP, VGCHANGE,
 };
+use crate::manager::stop_service;
 use crate::node_manager::{
-    change_hostname, change_ipv4address, get_ipv4_address, remove_nfsroot, restart_services,
-    umount_tftp_directory,
+    change_hostname, change_ipv4address, get_ipv4_address, refresh_node_states, remove_nfsroot,
+    restart_services, umount_tftp_directory,
 };
 use crate::power_manager::reboot;
-use chrono::Utc;
+use crate::rollback::{Rollback, SideEffect};
+use crate::scheduler::{schedule, PlacementContext};
+use crate::storage::Storage;
 use colored::Colorize;
-use core::time;
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Output};
 use std::str;
 use std::{fs, io, thread};
 use structs::architecture::Architecture::X86;
 use structs::bootconfig::{group, BootConfig};
 use structs::configuration::Configuration;
 use structs::deployment::Deployment;
+use structs::deployment_plan::DeploymentPlan;
 use structs::logsource::LogSourceTypes;
 use structs::mountpoint::Mountpoint;
 use structs::node::Node;
+use structs::node_state::NodeState;
 use structs::partition::Partition;
+use structs::plan_row::PlanRow;
+use structs::rerun_request::RerunRequest;
 use structs::service::Service;
+use structs::state::State;
 use structs::task::Task;
 use structs::task::Type::GetResults;
-use structs::utils::{get_random_name, print_information, print_message, replace_in_file};
+use structs::utils::{
+    get_current_user, get_step_sink, print_information, print_message, replace_in_file,
+    sha256sum_of_file, with_step_sink,
+};
 use yaml_rust::YamlLoader;
 use crate::logs_manager::gather_logs;
 
 const BUILD_DIRECTORY: &str = "os-build";
 
-pub fn deploy_deployment(deployment_yaml: &str) -> bool {
+pub fn deploy_deployment(storage: &dyn Storage, deployment_yaml: &str) -> bool {
+    match read_deployment(deployment_yaml) {
+        Some(mut deployment) => deploy(storage, &mut deployment),
+        None => false,
+    }
+}
+
+pub fn read_deployment(deployment_yaml: &str) -> Option<Deployment> {
     let path = Path::new(deployment_yaml);
     if !path.exists() {
         println!("{}", "please provide an existing yaml file".red());
-        return false;
+        return None;
     }
     let content =
         &fs::read_to_string(deployment_yaml).expect("Something went wrong reading the file");
     let yaml = YamlLoader::load_from_str(content).unwrap();
-    let mut deployment = Deployment::from_yaml(deployment_yaml, &yaml[0]);
-    deploy(&mut deployment)
+    match Deployment::from_yaml(deployment_yaml, &get_current_user(), &yaml[0]) {
+        Ok(deployment) => Some(deployment),
+        Err(e) => {
+            println!("{}", e.red());
+            None
+        }
+    }
+}
+
+/// Builds a new deployment from the stored specification of deployment `id`.
+pub fn prepare_rerun(
+    storage: &dyn Storage,
+    id: i64,
+    owner: &str,
+    request: &RerunRequest,
+) -> Result<Deployment, String> {
+    let spec = match storage.get_spec_of_deployment(id) {
+        Ok(Some(spec)) => spec,
+        Ok(None) => {
+            return Err(format!(
+                "deployment {} was deployed before specifications were stored",
+                id
+            ))
+        }
+        Err(_) => return Err(format!("deployment {} does not exist", id)),
+    };
+    let mut deployment = serde_json::from_str::<Deployment>(&spec).map_err(|e| e.to_string())?;
+    request.apply(id, owner, &mut deployment)?;
+    Ok(deployment)
+}
+
+pub fn deploy(storage: &dyn Storage, deployment: &mut Deployment) -> bool {
+    let status = run_deployment(storage, deployment);
+    let target = match deployment.id {
+        Some(id) => format!("deployment {}", id),
+        None => format!("deployment {}", deployment.name),
+    };
+    let nodes = deployment
+        .id
+        .and_then(|id| storage.get_services_by_deployment(id).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|s| s.node)
+        .collect::<Vec<String>>();
+    record_for_nodes("deploy", &target, &nodes, status)
 }
 
-pub fn deploy(deployment: &mut Deployment) -> bool {
+fn run_deployment(storage: &dyn Storage, deployment: &mut Deployment) -> bool {
     print_message(
         "check image architectures",
         associate_architectures(deployment),
     );
-    if let Ok(services_with_nodes) = check_availability(deployment.get_services()) {
-        let id = insert_deployment(&deployment).unwrap();
-        deployment.id = Some(id);
-        print_message("add deployment to database", true);
-        let mut nodes = Vec::new();
-        for (mut service, mut node) in services_with_nodes {
-            service.deployment = Some(id);
-            deploy_service(deployment, &mut service, &mut node);
-            nodes.push(node);
+    let services_with_nodes =
+        match check_availability(storage, deployment.get_services(), &deployment.owner) {
+            Ok(services_with_nodes) => services_with_nodes,
+            Err(message) => {
+                print_message(
+                    &format!("can not deploy this deployment: {}", message),
+                    false,
+                );
+                deployment.failure = Some(message);
+                return false;
+            }
+        };
+    let id = storage.insert_deployment(&deployment).unwrap();
+    deployment.id = Some(id);
+    print_message("add deployment to database", true);
+    let mut booting = Vec::new();
+    let mut rollback = Rollback::default();
+    for (mut service, mut node) in services_with_nodes {
+        service.deployment = Some(id);
+        if !deploy_service(storage, deployment, &mut service, &mut node, &mut rollback) {
+            let reason = format!("deploying service {} on {} failed", service.name, node.id);
+            println!("{}", reason.red());
+            print_message("roll back deployment", rollback.undo(storage));
+            restart_services();
+            print_message(
+                "mark deployment as failed",
+                storage.set_failure_for_deployment(id, &reason).is_ok(),
+            );
+            deployment.failure = Some(reason);
+            return false;
         }
-        restart_services();
-        for node in nodes {
+        booting.push((service.id, node));
+    }
+    rollback.commit();
+    restart_services();
+    let booting = booting
+        .into_iter()
+        .map(|(service_id, node)| {
+            let start = BootStart::capture(&node);
             print_message(&format!("rebooting node {}", node.id), reboot_node(&node));
+            (service_id, node, start)
+        })
+        .collect::<Vec<(Option<i64>, Node, BootStart)>>();
+    for service_id in booting.iter().filter_map(|(service_id, _, _)| *service_id) {
+        storage
+            .set_state_for_service(service_id, &State::Booting, None)
+            .unwrap_or_default();
+    }
+    print_message(
+        "mark deployment as booting",
+        storage
+            .set_state_for_deployment(id, &State::Booting, None)
+            .unwrap_or_default(),
+    );
+    await_booted_services(storage, id, booting)
+}
+
+/// Watches the boot of every node in parallel, services whose node does not come up fail and so does
+/// the deployment, which stops all of its services.
+fn await_booted_services(
+    storage: &dyn Storage,
+    id: i64,
+    booting: Vec<(Option<i64>, Node, BootStart)>,
+) -> bool {
+    let actor = get_actor();
+    let sink = get_step_sink();
+    let results = thread::scope(|scope| {
+        booting
+            .into_iter()
+            .map(|(service, node, start)| {
+                let actor = &actor;
+                let sink = sink.clone();
+                scope.spawn(move || {
+                    let result = with_step_sink(sink, || {
+                        as_actor(actor, || {
+                            watch_boot(storage, &node, BootPhase::Reachable, start)
+                        })
+                    });
+                    (service, node, result)
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    let services = results
+        .iter()
+        .filter_map(|(service, _, _)| *service)
+        .collect::<Vec<i64>>();
+    let mut failures = Vec::new();
+    let mut stuck = Vec::new();
+    for (service, node, result) in results {
+        match result {
+            Ok(durations) => {
+                let summary = durations
+                    .iter()
+                    .map(|(phase, seconds)| format!("{} after {}s", phase.get_name(), seconds))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                if let Some(service) = service {
+                    storage
+                        .set_state_for_service(service, &State::Running, Some("node is up"))
+                        .unwrap_or_default();
+                    storage
+                        .insert_timeline_event(
+                            id,
+                            Some(service),
+                            &format!("{} booted: {}", node.id, summary),
+                        )
+                        .unwrap_or_default();
+                }
+            }
+            Err(phase) => {
+                let reason = format!("{} got stuck at {}", node.id, phase.get_name());
+                if let Some(service) = service {
+                    stuck.push((service, reason.clone()));
+                }
+                failures.push(reason);
+            }
         }
-    } else {
-        println!(
-            "{}",
-            "can not deploy this deployment because not enough nodes are available".red()
+    }
+    if failures.is_empty() {
+        return report(
+            "mark deployment as running",
+            storage
+                .set_state_for_deployment(id, &State::Running, Some("all services are up"))
+                .unwrap_or_default(),
         );
-        return false;
     }
-    true
+    let reason = failures.join(", ");
+    println!("{}", reason.red());
+    print_message(
+        "mark deployment as failed",
+        storage.set_failure_for_deployment(id, &reason).is_ok(),
+    );
+    // the services which came up are stopped as well, so none of the nodes stays occupied
+    for service in services {
+        stop_service(storage, service, false);
+    }
+    for (service, reason) in stuck {
+        storage
+            .set_state_for_service(service, &State::Failed, Some(&reason))
+            .unwrap_or_default();
+    }
+    storage
+        .set_state_for_deployment(id, &State::Failed, Some(&reason))
+        .unwrap_or_default();
+    false
 }
 
-pub fn deploy_single_image(image: &str, node: Option<Node>) -> bool {
-    let mut deployment = Deployment::new(get_random_name().as_str());
-    let mut service = Service::new(
-        get_random_name().as_str(),
-        image,
-        get_random_name().as_str(),
-    );
-    service.preferred_node = match node {
-        Some(n) => Some(n.id),
-        None => None,
+pub fn deploy_single_image(
+    storage: &dyn Storage,
+    image: &str,
+    node: Option<Node>,
+    owner: &str,
+) -> bool {
+    let mut deployment = Deployment::for_image(image, node.map(|n| n.id), owner);
+    deploy(storage, &mut deployment)
+}
+
+/// Runs the same checks and node assignment as `deploy` without touching nfsroots, dnsmasq or
+/// power.
+pub fn plan(storage: &dyn Storage, deployment: &mut Deployment) -> DeploymentPlan {
+    let mut plan = DeploymentPlan::new(&deployment.name, &deployment.owner);
+    for service in &deployment.services {
+        if !Path::new(&format!("{}/{}.zip", OS_IMAGES_DIR, service.image)).exists() {
+            plan.problems.push(format!(
+                "image {} of {} does not exist",
+                service.image, service.name
+            ));
+        }
+    }
+    if !associate_architectures(deployment) {
+        plan.problems.push(String::from(
+            "could not determine the architecture of every image",
+        ));
+        return plan;
+    }
+    let assignments = match assign_nodes(storage, deployment.get_services(), &deployment.owner) {
+        Ok(assignments) => assignments,
+        Err(message) => {
+            plan.problems.push(message);
+            return plan;
+        }
     };
-    deployment.services.push(service);
-    deploy(&mut deployment)
+    for (service, node, rationale) in assignments {
+        let mut images = Vec::new();
+        if let Some(configuration) = extract_configuration(&service.image) {
+            if configuration.on_device {
+                match get_default_os_for(&node) {
+                    Some(default_os) => images.push(default_os),
+                    None => plan
+                        .problems
+                        .push(format!("{} has no default-os to flash from", node.id)),
+                }
+            }
+        }
+        images.push(service.image.clone());
+        let nfsroot = format!("{}/{}", NFS_BASE_DIR, node.tftp_prefix);
+        let replaced_nfsroot = match fs::read_dir(&nfsroot) {
+            Ok(mut entries) => entries.next().map(|_| nfsroot),
+            Err(_) => None,
+        };
+        plan.replicas.push(PlanRow {
+            service: service.name.clone(),
+            hostname: service.hostname.clone(),
+            node: node.id.clone(),
+            images,
+            replaced_nfsroot,
+            rationale,
+        });
+    }
+    plan
+}
+
+pub fn print_plan(plan: &DeploymentPlan) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("service"),
+            Cell::new("hostname"),
+            Cell::new("node"),
+            Cell::new("images"),
+            Cell::new("replaced nfsroot"),
+            Cell::new("rationale"),
+        ]
+        .to_vec(),
+    ));
+    for row in &plan.replicas {
+        table.add_row(Row::new(row.get_cells()));
+    }
+    table.printstd();
+    for problem in &plan.problems {
+        eprintln!("{}", problem.red());
+    }
+    print_message(
+        &format!("deployment {} can be deployed", plan.name),
+        plan.is_feasible(),
+    );
 }
 
-fn deploy_service(deployment: &mut Deployment, service: &mut Service, node: &mut Node) -> bool {
-    let status = deploy_image(deployment, service, &service.image, &node);
+fn deploy_service(
+    storage: &dyn Storage,
+    deployment: &mut Deployment,
+    service: &mut Service,
+    node: &mut Node,
+    rollback: &mut Rollback,
+) -> bool {
+    let backed_up = rollback.backup_nfsroot(node);
+    print_message("back up nfsroot", backed_up);
+    if !backed_up {
+        return false;
+    }
+    rollback.backup_pxe_file(node);
+    service.image_checksum = sha256sum_of_file(&format!("{}/{}.zip", OS_IMAGES_DIR, service.image));
+    print_message("compute image checksum", service.image_checksum.is_some());
+    let status = deploy_image(storage, deployment, service, &service.image, &node);
     print_message(
         format!("deploying service {} on {}", service.name, node.id).as_str(),
         status,
     );
     if status {
-        change_hostname(node, service.hostname.as_str());
+        rollback.backup_dnsmasq(node);
+        if !change_hostname(node, service.hostname.as_str()) {
+            return false;
+        }
         if let Some(ipv4) = &service.ipv4_address {
             change_ipv4address(node, ipv4);
         } else {
@@ -108,8 +392,27 @@ fn deploy_service(deployment: &mut Deployment, service: &mut Service, node: &mut
         print_message("create results directory", create_results_directory(&node));
         print_information("starting logging from serial inputs");
         open_screens_for_serial_logging(&node);
-        let service_id = insert_service(&service);
+        rollback.record(SideEffect::Screens { node: node.clone() });
+        let service_id = storage.insert_service(&service);
         print_message("add service in database", service_id.is_ok());
+        match service_id {
+            Ok(id) => {
+                service.id = Some(id);
+                rollback.record(SideEffect::ServiceRow {
+                    id,
+                    node: node.clone(),
+                });
+                let reason = format!(
+                    "runs service {} of deployment {}",
+                    service.name,
+                    deployment.id.unwrap()
+                );
+                storage
+                    .set_state_for_node(&node.id, &NodeState::Busy, Some(&reason))
+                    .unwrap_or_default();
+            }
+            Err(_) => return false,
+        }
         if let Some(configuration) = extract_configuration(&service.image) {
             if configuration.on_device {
                 let mountpoint = configuration
@@ -123,16 +426,26 @@ fn deploy_service(deployment: &mut Deployment, service: &mut Service, node: &mut
                         }
                     })
                     .collect::<Vec<Mountpoint>>();
-                service.id = Some(service_id.unwrap());
                 if !mountpoint.is_empty() {
-                    let task: Task = Task::new(
+                    let mut task: Task = Task::new(
                         Some(deployment.clone()),
                         Some(service.clone()),
                         GetResults,
                         serde_json::to_string(&mountpoint.get(0).unwrap()).unwrap(),
                         false,
                     );
-                    insert_task(&task, deployment.id.unwrap()).unwrap();
+                    task.id = Some(storage.insert_task(&task, deployment.id.unwrap()).unwrap());
+                    deployment.tasks.push(task);
+                }
+                if let Some(cleanup) = deployment.local_storage {
+                    let mut task: Task = Task::new(
+                        Some(deployment.clone()),
+                        Some(service.clone()),
+                        cleanup,
+                        String::new(),
+                        false,
+                    );
+                    task.id = Some(storage.insert_task(&task, deployment.id.unwrap()).unwrap());
                     deployment.tasks.push(task);
                 }
             }
@@ -141,47 +454,155 @@ fn deploy_service(deployment: &mut Deployment, service: &mut Service, node: &mut
     status
 }
 
-pub fn check_availability(services: Vec<Service>) -> Result<Vec<(Service, Node)>, String> {
+pub fn check_availability(
+    storage: &dyn Storage,
+    services: Vec<Service>,
+    owner: &str,
+) -> Result<Vec<(Service, Node)>, String> {
+    let assignments = assign_nodes(storage, services, owner)?;
+    Ok(assignments
+        .into_iter()
+        .map(|(service, node, rationale)| {
+            print_information(&rationale);
+            (service, node)
+        })
+        .collect())
+}
+
+fn assign_nodes(
+    storage: &dyn Storage,
+    services: Vec<Service>,
+    owner: &str,
+) -> Result<Vec<(Service, Node, String)>, String> {
     let mut services_with_nodes = Vec::new();
-    let mut available_nodes = get_idle_nodes().unwrap();
+    let mut rationales = Vec::new();
+    refresh_node_states(storage);
+    let mut available_nodes = storage.get_idle_nodes(owner).unwrap();
+    let all_nodes = storage.get_all_nodes();
+    let running = storage
+        .get_running_services()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|s| {
+            let node = all_nodes
+                .iter()
+                .find(|n| s.node.as_deref() == Some(n.id.as_str()))?;
+            Some((s.clone(), node.clone()))
+        })
+        .collect::<Vec<(Service, Node)>>();
     for mut service in services {
         for _replica in 0..service.replicas {
-            let mut to_remove = None;
-            for (index, node) in available_nodes.iter().enumerate() {
-                let arch = service.architecture.as_ref().unwrap();
-                let mut matched = false;
-                match &service.preferred_node {
-                    Some(preferred_node) => {
-                        if node.id.eq(preferred_node) {
-                            matched = true;
-                        }
-                    }
-                    None => {
-                        matched = true;
-                    }
-                }
-                if matched && node.architecture.eq(arch) {
-                    to_remove = Some((index, node.clone()));
+            let arch = service.architecture.as_ref().unwrap();
+            let candidates = available_nodes
+                .iter()
+                .filter(|node| match &service.preferred_node {
+                    Some(preferred_node) => node.id.eq(preferred_node),
+                    None => true,
+                })
+                .filter(|node| {
+                    node.architecture.eq(arch) && node.matches_labels(&service.node_selector)
+                })
+                .cloned()
+                .collect::<Vec<Node>>();
+            let context = PlacementContext {
+                placed: &services_with_nodes,
+                running: &running,
+            };
+            match schedule(&service, &candidates, &context) {
+                Some((index, rationale)) => {
+                    let node = candidates[index].clone();
+                    rationales.push(rationale);
                     service.node = Some(node.id.clone());
-                    break;
-                }
-            }
-            match to_remove {
-                Some((index, node)) => {
-                    available_nodes.remove(index);
+                    available_nodes.retain(|n| n.id.ne(&node.id));
                     services_with_nodes.push((service.clone(), node));
                 }
                 None => {
-                    return Err(format!("No available node for {}", service.name.as_str()));
+                    let mut requirements = service
+                        .node_selector
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value))
+                        .collect::<Vec<String>>();
+                    if let Some(placement) = &service.placement {
+                        requirements.push(format!(
+                            "{} over {}",
+                            placement.get_name(),
+                            placement.get_label()
+                        ));
+                    }
+                    if let Some(message) = describe_maintenance(storage, &service) {
+                        return Err(message);
+                    }
+                    if requirements.is_empty() {
+                        return Err(format!("No available node for {}", service.name.as_str()));
+                    }
+                    return Err(format!(
+                        "No available node for {} matching {}",
+                        service.name.as_str(),
+                        requirements.join(", ")
+                    ));
                 }
             }
         }
     }
-    Ok(services_with_nodes)
+    Ok(services_with_nodes
+        .into_iter()
+        .zip(rationales)
+        .map(|((service, node), rationale)| (service, node, rationale))
+        .collect())
+}
+
+/// Explains that the service could run on nodes which are in maintenance, be it its preferred node
+/// or nodes with its architecture and labels.
+fn describe_maintenance(storage: &dyn Storage, service: &Service) -> Option<String> {
+    let states = storage.get_node_states().ok()?;
+    let cordoned = storage
+        .get_all_nodes()
+        .into_iter()
+        .filter(|node| {
+            service
+                .preferred_node
+                .as_ref()
+                .map_or(true, |preferred| node.id.eq(preferred))
+                && service
+                    .architecture
+                    .as_ref()
+                    .map_or(true, |arch| node.architecture.eq(arch))
+                && node.matches_labels(&service.node_selector)
+        })
+        .filter_map(|node| {
+            let entry = states
+                .get(&node.id)
+                .filter(|entry| entry.state == NodeState::Maintenance)?;
+            let mut note = String::from(entry.reason.as_deref().unwrap_or("no note"));
+            if let Some(expected_return) = &entry.expected_return {
+                note.push_str(&format!(
+                    ", expected back at {}",
+                    expected_return.format("%Y-%m-%d %H:%M")
+                ));
+            }
+            Some((node.id, note))
+        })
+        .collect::<Vec<(String, String)>>();
+    match (&service.preferred_node, cordoned.first()) {
+        (_, None) => None,
+        (Some(node), Some((_, note))) => Some(format!(
+            "{} prefers {} which is in maintenance: {}",
+            service.name, node, note
+        )),
+        (None, Some(_)) => Some(format!(
+            "No available node for {}, in maintenance are {}",
+            service.name,
+            cordoned
+                .iter()
+                .map(|(node, note)| format!("{} ({})", node, note))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )),
+    }
 }
 
 #[allow(dead_code)]
-fn choose_node_for_service(service: &Service) -> Option<Node> {
+fn choose_node_for_service(storage: &dyn Storage, service: &Service, owner: &str) -> Option<Node> {
     let first_choice = match &service.preferred_node {
         Some(mac) => match get_node_by_id(mac, false) {
             Some(node) => {
@@ -195,7 +616,7 @@ fn choose_node_for_service(service: &Service) -> Option<Node> {
         },
         None => None,
     };
-    let available_nodes = get_idle_nodes().unwrap();
+    let available_nodes = storage.get_idle_nodes(owner).unwrap();
     if let Some(node) = first_choice {
         if available_nodes.contains(&node) {
             return Some(node);
@@ -208,6 +629,7 @@ fn choose_node_for_service(service: &Service) -> Option<Node> {
 }
 
 pub fn deploy_image(
+    storage: &dyn Storage,
     deployment: &mut Deployment,
     service: &Service,
     image_path: &str,
@@ -225,33 +647,51 @@ pub fn deploy_image(
         sandbox_name.green()
     ));
     print_information(&format!("chosen node is {}", node.id.green()));
-    print_message(
+    if !report(
         "create deploy sandbox",
         create_tmp_directory(&sandbox_name).is_ok(),
-    );
-    print_message(
+    ) {
+        return false;
+    }
+    let mut status = report(
         "unpack image in sandbox",
         unpack_image(&path_string, &sandbox_name),
     );
     let configuration = read_configuration(&sandbox_name);
     print_message("read configuration", configuration.is_some());
-    let config = configuration.unwrap();
-    if config.on_device {
-        deploy_image_on_local_storage(deployment, service, &sandbox_name, node);
-        if node.pxe {
-            print_message("write pxefile", write_pxe_file(&config, &node));
+    match configuration {
+        Some(config) if status => {
+            if config.on_device {
+                status = deploy_image_on_local_storage(
+                    storage,
+                    deployment,
+                    service,
+                    &sandbox_name,
+                    node,
+                );
+                if status && node.pxe {
+                    status = report("write pxefile", write_pxe_file(&config, &node));
+                }
+            } else {
+                status = deploy_image_for_netboot(&sandbox_name, config, node);
+            }
         }
-    } else {
-        deploy_image_for_netboot(&sandbox_name, config, node);
+        _ => status = false,
     }
     print_message(
         "destroy deploy sandbox",
         destroy_tmp_directory(sandbox_name.as_str()).is_ok(),
     );
-    true
+    status
+}
+
+fn report(message: &str, status: bool) -> bool {
+    print_message(message, status);
+    status
 }
 
 fn deploy_image_on_local_storage(
+    storage: &dyn Storage,
     deployment: &mut Deployment,
     service: &Service,
     sandbox_name: &str,
@@ -259,22 +699,35 @@ fn deploy_image_on_local_storage(
 ) -> bool {
     print_message("deploying image to be written on local storage", true);
     if let Some(default_os) = get_default_os_for(node) {
-        if deploy_image(deployment, service, &default_os, node) {
+        if deploy_image(storage, deployment, service, &default_os, node) {
+            let start = BootStart::capture(node);
             print_message("rebooting node", reboot_node(node));
-            print_message(
+            if !report(
                 "copying image to node",
                 move_image_to_root_home(sandbox_name, node),
-            );
+            ) {
+                return false;
+            }
             print_message(
                 "allow ssh key to connect to node",
                 allow_ssh_access_to_node(node),
             );
-            wait_for_rebooted_node(node);
-            let storage_device = get_storage_device_of(node);
-            print_message(
+            if !report(
+                "wait for node to boot",
+                wait_for_rebooted_node(storage, node, start),
+            ) {
+                return false;
+            }
+            let storage_device = match get_storage_device_of(node) {
+                Some(device) => device,
+                None => return report("read storage-device of node", false),
+            };
+            if !report(
                 "flashing image to node",
-                flash_image_to_node(node, &format!("/dev/{}", storage_device.unwrap())),
-            );
+                flash_image_to_node(node, &format!("/dev/{}", storage_device)),
+            ) {
+                return false;
+            }
             print_message("reboot via ssh", !execute_command_over_ssh(node, "reboot"));
             print_message(
                 "unmount tftpboot directory",
@@ -284,19 +737,23 @@ fn deploy_image_on_local_storage(
                 "remove filesystem",
                 remove_nfsroot(node.tftp_prefix.as_str(), false).is_ok(),
             );
+            return true;
         }
-        return true;
     }
     false
 }
 
-fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node: &Node) {
+fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node: &Node) -> bool {
+    let mut status = true;
     if config.architecture.get_name().eq(X86.get_name()) {
         print_message("convert vmdk to img", convert_vmdk_to_img(sandbox_name));
     }
     let loopdevice = get_loopdevice(sandbox_name);
     print_message("add new loopdevice", loopdevice.is_ok());
-    let mapper = loopdevice.unwrap();
+    let mapper = match loopdevice {
+        Ok(mapper) => mapper,
+        Err(_) => return false,
+    };
     print_information(&format!("loopdevice is: {}", mapper));
     let mut vg = String::new();
     if is_lvm(sandbox_name) {
@@ -304,7 +761,7 @@ fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node:
         vg = vg_ret.0;
         config.partitions = vg_ret.1;
         for (i, partition) in config.partitions.iter().enumerate() {
-            print_message(
+            status &= report(
                 format!("mount lvm-partition #{}", i + 1).as_str(),
                 mount_lvm_partition(sandbox_name, &vg, i + 1, &partition),
             );
@@ -319,20 +776,20 @@ fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node:
             }
         }
         for partition in &config.mountorder {
-            print_message(
+            status &= report(
                 format!("mount partition #{}", partition.partition_number).as_str(),
                 mount_partition(sandbox_name, mapper.as_str(), &partition),
             );
         }
     }
-    print_message(
+    status &= report(
         "create result directory",
         create_result_directory(sandbox_name).is_ok(),
     );
     if is_lvm(sandbox_name) {
         for (i, p) in config.partitions.iter().enumerate() {
             let partition = Mountpoint::new(i + 1, i + 1, p.get_mountpoint());
-            print_message(
+            status &= report(
                 format!("copy partition #{} to result", partition.partition_number).as_str(),
                 copy_partition_to_result(&partition, sandbox_name),
             );
@@ -348,7 +805,7 @@ fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node:
         deactivate_vgs(&vg);
     } else {
         for partition in &config.mountorder {
-            print_message(
+            status &= report(
                 format!("copy partition #{} to result", partition.partition_number).as_str(),
                 copy_partition_to_result(&partition, sandbox_name),
             );
@@ -363,7 +820,7 @@ fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node:
         }
     }
     print_message("remove loopdevice", remove_loopdevice(sandbox_name));
-    print_message(
+    status &= report(
         "resolve bootconfigs",
         resolve_bootconfigs(
             sandbox_name,
@@ -373,20 +830,21 @@ fn deploy_image_for_netboot(sandbox_name: &str, mut config: Configuration, node:
         ),
     );
     if config.pxe {
-        print_message("write pxefile", write_pxe_file(&config, &node));
+        status &= report("write pxefile", write_pxe_file(&config, &node));
     }
     print_message(
         "unmount old tftpboot directory",
         umount_tftp_directory(node.tftp_prefix.as_str()),
     );
-    print_message(
+    status &= report(
         "copy image result to nfsroot",
         move_result_to_nfs(sandbox_name, node.tftp_prefix.as_str()),
     );
-    print_message(
+    status &= report(
         "mount boot partition in tftpboot",
         mount_tftpboot(config.partitions, node.tftp_prefix.as_str()),
     );
+    status
 }
 
 fn create_tmp_directory(directory: &str) -> io::Result<()> {
@@ -517,10 +975,13 @@ fn resolve_bootconfigs(
 ) -> bool {
     let mut success = false;
     if !bootconfigs.get_files().is_empty() {
+        let config = get_config();
+        let (server_ip, log_server) = match (&config.server_ip, &config.log_server) {
+            (Some(server_ip), Some(log_server)) => (server_ip, log_server),
+            _ => return report("read server-ip and log-server from config.yml", false),
+        };
         success = true;
         let mut placeholders: Vec<(&str, &str)> = Vec::new();
-        let server_ip = get("server-ip").unwrap();
-        let log_server = get("log-server").unwrap();
         placeholders.push(("%SERVER_IP%", server_ip.as_str()));
         let nfs_root = format!("{}/{}", NFS_BASE_DIR, node);
         placeholders.push(("%NFS_ROOT%", nfs_root.as_str()));
@@ -551,7 +1012,7 @@ pub fn replace_placeholders(file: &str, placeholders: &[(&str, &str)]) -> bool {
     success
 }
 
-fn mount_tftpboot(partitions: Vec<Partition>, node: &str) -> bool {
+pub fn mount_tftpboot(partitions: Vec<Partition>, node: &str) -> bool {
     let boot_path = match partitions
         .into_iter()
         .filter(|p| p.get_name().eq("boot"))
@@ -583,21 +1044,31 @@ fn move_result_to_nfs(directory: &str, node: &str) -> bool {
     child.wait().unwrap().success()
 }
 
-fn execute_command_over_ssh(node: &Node, command: &str) -> bool {
-    Command::new(SSH)
-        .arg("-o")
+pub fn execute_command_over_ssh(node: &Node, command: &str) -> bool {
+    get_ssh_command(node, command)
+        .spawn()
+        .expect("failed to execute command over ssh")
+        .wait()
+        .unwrap()
+        .success()
+}
+
+/// Like `execute_command_over_ssh`, but captures stdout and stderr instead of printing them.
+pub fn execute_command_over_ssh_with_output(node: &Node, command: &str) -> io::Result<Output> {
+    get_ssh_command(node, command).output()
+}
+
+fn get_ssh_command(node: &Node, command: &str) -> Command {
+    let mut ssh = Command::new(SSH);
+    ssh.arg("-o")
         .arg("StrictHostKeyChecking=no")
         .arg("-o")
         .arg("UserKnownHostsFile=/dev/null")
         .arg("-i")
         .arg(format!("{}/deployer", BASE_DIR))
         .arg(format!("root@{}", node.ipv4_address))
-        .arg(command)
-        .spawn()
-        .expect("failed to execute command over ssh")
-        .wait()
-        .unwrap()
-        .success()
+        .arg(command);
+    ssh
 }
 
 fn flash_image_to_node(node: &Node, device: &str) -> bool {
@@ -657,24 +1128,8 @@ fn move_image_to_root_home(directory: &str, node: &Node) -> bool {
         .success()
 }
 
-fn wait_for_rebooted_node(node: &Node) {
-    println!("waiting for node to reboot");
-    let utc = Utc::now();
-    thread::sleep(time::Duration::from_millis(1000));
-    loop {
-        if crate::node_manager::is_up(&node.ipv4_address) {
-            println!(
-                "rebooting took {} seconds",
-                Utc::now().signed_duration_since(utc).num_seconds()
-            );
-            loop {
-                if execute_command_over_ssh(node, "echo 'waiting'") {
-                    break;
-                }
-            }
-            break;
-        }
-    }
+fn wait_for_rebooted_node(storage: &dyn Storage, node: &Node, start: BootStart) -> bool {
+    watch_boot(storage, node, BootPhase::SshReachable, start).is_ok()
 }
 
 fn reboot_node(node: &Node) -> bool {
@@ -688,20 +1143,26 @@ fn prune_sd_card(node: &Node) -> bool {
     )
 }
 
-pub fn clean_node(node: &Node) -> bool {
+pub fn clean_node(storage: &dyn Storage, node: &Node) -> bool {
     print_message(
         "deploying raspbian in order to wipe all storage on sd_card",
         true,
     );
-    let mut deployment = Deployment::new("cleaning");
+    let mut deployment = Deployment::new("cleaning", &get_current_user());
     let service = Service::new("cleaning", "raspbian", "cleaning");
-    if deploy_image(&mut deployment, &service, "raspbian", node) {
+    if deploy_image(storage, &mut deployment, &service, "raspbian", node) {
+        let start = BootStart::capture(node);
         print_message("rebooting node", reboot_node(node));
         print_message(
             "allow ssh key to connect to node",
             allow_ssh_access_to_node(node),
         );
-        wait_for_rebooted_node(node);
+        if !report(
+            "wait for node to boot",
+            wait_for_rebooted_node(storage, node, start),
+        ) {
+            return false;
+        }
         print_message("prune sd_card", prune_sd_card(node));
         print_message("reboot via ssh", execute_command_over_ssh(node, "reboot"));
         print_message(
@@ -919,7 +1380,10 @@ fn write_pxe_file(config: &Configuration, node: &Node) -> bool {
     } else {
         let mut placeholders: Vec<(&str, &str)> = Vec::new();
         let tftp_prefix = format!("{}/{}", TFTP_BASE_DIR, node.tftp_prefix);
-        let server_ip = get("server-ip").unwrap();
+        let server_ip = match get_config().server_ip.clone() {
+            Some(server_ip) => server_ip,
+            None => return report("read server-ip from config.yml", false),
+        };
         let nfs_root = format!("{}/{}", NFS_BASE_DIR, node.id);
         placeholders.push(("%SERVER_IP%", &server_ip));
         placeholders.push(("%NFS_ROOT%", &nfs_root));
@@ -964,21 +1428,52 @@ fn associate_architectures(deployment: &mut Deployment) -> bool {
     done
 }
 
-pub fn retrieve_local_logs(
+/// Netboots the default os of the node so its local storage can be accessed over ssh.
+fn boot_default_os(
+    storage: &dyn Storage,
     deployment: &mut Deployment,
     service: &Service,
     node: &Node,
-    mountpoint: &Mountpoint,
 ) -> bool {
-    print_message("deploying image to be written on local storage", true);
     if let Some(default_os) = get_default_os_for(node) {
-        if deploy_image(deployment, service, &default_os, node) {
+        if deploy_image(storage, deployment, service, &default_os, node) {
+            let start = BootStart::capture(node);
             print_message("rebooting node", reboot_node(node));
             print_message(
                 "allow ssh key to connect to node",
                 allow_ssh_access_to_node(node),
             );
-            wait_for_rebooted_node(node);
+            return report(
+                "wait for node to boot",
+                wait_for_rebooted_node(storage, node, start),
+            );
+        }
+    }
+    false
+}
+
+fn shutdown_default_os(node: &Node) {
+    print_message("reboot via ssh", !execute_command_over_ssh(node, "reboot"));
+    print_message(
+        "unmount tftpboot directory",
+        umount_tftp_directory(&node.tftp_prefix),
+    );
+    print_message(
+        "remove filesystem",
+        remove_nfsroot(node.tftp_prefix.as_str(), false).is_ok(),
+    );
+}
+
+pub fn retrieve_local_logs(
+    storage: &dyn Storage,
+    deployment: &mut Deployment,
+    service: &Service,
+    node: &Node,
+    mountpoint: &Mountpoint,
+) -> bool {
+    print_message("deploying image to be written on local storage", true);
+    if get_default_os_for(node).is_some() {
+        if boot_default_os(storage, deployment, service, node) {
             let storage_device = get_storage_device_of(node).unwrap();
             print_message(
                 "create mountdirectory on device",
@@ -999,17 +1494,140 @@ pub fn retrieve_local_logs(
                 execute_command_over_ssh(node, "mv /local/results /results"),
             );
             gather_logs(node);
-            print_message("reboot via ssh", !execute_command_over_ssh(node, "reboot"));
-            print_message(
-                "unmount tftpboot directory",
-                umount_tftp_directory(&node.tftp_prefix),
-            );
-            print_message(
-                "remove filesystem",
-                remove_nfsroot(node.tftp_prefix.as_str(), false).is_ok(),
-            );
+            shutdown_default_os(node);
         }
         return true;
     }
     false
 }
+
+/// Removes everything an `on_device` image left on the storage device of the node, `secure`
+/// overwrites the whole device instead of only deleting its partitions.
+pub fn clean_local_storage(
+    storage: &dyn Storage,
+    deployment: &mut Deployment,
+    service: &Service,
+    node: &Node,
+    secure: bool,
+) -> bool {
+    let storage_device = match get_storage_device_of(node) {
+        Some(device) => device,
+        None => return report("read storage-device of node", false),
+    };
+    if !report(
+        "boot default os to clean local storage",
+        boot_default_os(storage, deployment, service, node),
+    ) {
+        return false;
+    }
+    let status = if secure {
+        report(
+            "overwrite local storage with random data",
+            execute_command_over_ssh(
+                node,
+                &format!("shred --iterations=1 --zero /dev/{}", storage_device),
+            ),
+        )
+    } else {
+        report(
+            "delete partitions of local storage",
+            execute_command_over_ssh(node, &format!("wipefs --all /dev/{}", storage_device)),
+        )
+    };
+    shutdown_default_os(node);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::{get_test_node, MemoryStorage};
+    use chrono::{Duration, Utc};
+    use structs::architecture::Architecture::ARM64;
+    use structs::reservation::Reservation;
+
+    fn get_service(name: &str, replicas: i64) -> Service {
+        let mut service = Service::new(name, "raspbian", name);
+        service.replicas = replicas;
+        service.architecture = Some(ARM64);
+        service
+    }
+
+    fn get_assigned_nodes(
+        storage: &dyn Storage,
+        services: Vec<Service>,
+        owner: &str,
+    ) -> Vec<String> {
+        check_availability(storage, services, owner)
+            .unwrap()
+            .into_iter()
+            .map(|(_, node)| node.id)
+            .collect()
+    }
+
+    #[test]
+    fn assigns_every_replica_its_own_node_of_the_architecture() {
+        let storage = MemoryStorage::with_nodes(vec![
+            get_test_node("pc", X86),
+            get_test_node("pi-1", ARM64),
+            get_test_node("pi-2", ARM64),
+        ]);
+        assert_eq!(
+            get_assigned_nodes(&storage, vec![get_service("web", 2)], "alice"),
+            vec!["pi-1", "pi-2"]
+        );
+    }
+
+    #[test]
+    fn fails_without_enough_nodes() {
+        let storage = MemoryStorage::with_nodes(vec![get_test_node("pi-1", ARM64)]);
+        assert_eq!(
+            check_availability(&storage, vec![get_service("web", 2)], "alice").unwrap_err(),
+            "No available node for web"
+        );
+    }
+
+    #[test]
+    fn prefers_nodes_reserved_by_the_owner() {
+        let storage = MemoryStorage::with_nodes(vec![
+            get_test_node("pi-1", ARM64),
+            get_test_node("pi-2", ARM64),
+        ]);
+        let now = Utc::now().naive_utc();
+        storage
+            .insert_reservation(&Reservation::new(
+                "alice",
+                vec![String::from("pi-2")],
+                now - Duration::hours(1),
+                now + Duration::hours(1),
+            ))
+            .unwrap();
+        storage
+            .set_state_for_node("pi-2", &NodeState::Reserved, None)
+            .unwrap();
+        assert_eq!(
+            get_assigned_nodes(&storage, vec![get_service("web", 1)], "alice"),
+            vec!["pi-2"]
+        );
+        assert_eq!(
+            get_assigned_nodes(&storage, vec![get_service("web", 1)], "bob"),
+            vec!["pi-1"]
+        );
+        assert!(check_availability(&storage, vec![get_service("web", 2)], "bob").is_err());
+    }
+
+    #[test]
+    fn skips_nodes_in_maintenance() {
+        let storage = MemoryStorage::with_nodes(vec![
+            get_test_node("pi-1", ARM64),
+            get_test_node("pi-2", ARM64),
+        ]);
+        storage
+            .set_state_for_node("pi-1", &NodeState::Maintenance, Some("new power supply"))
+            .unwrap();
+        assert_eq!(
+            get_assigned_nodes(&storage, vec![get_service("web", 1)], "alice"),
+            vec!["pi-2"]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::config::get_config;
+use crate::installer::{DNSMASQ_LOG_FILE, TFTP_BASE_DIR};
+use crate::node_manager::define_node;
+use crate::storage::Storage;
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+use std::collections::HashSet;
+use std::fs;
+use std::net::Ipv4Addr;
+use structs::discovered_node::DiscoveredNode;
+use structs::node_definition::NodeDefinition;
+use structs::utils::{print_information, print_message};
+
+/// Prefixes of the MAC addresses assigned to the Raspberry Pi Foundation.
+const RASPBERRY_PI_PREFIXES: [&str; 6] = [
+    "b8:27:eb", "dc:a6:32", "e4:5f:01", "d8:3a:dd", "28:cd:c1", "2c:cf:67",
+];
+
+fn find_mac_address(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .find(|word| {
+            let parts = word.split(':').collect::<Vec<&str>>();
+            parts.len() == 6
+                && parts
+                    .iter()
+                    .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+        })
+        .map(str::to_lowercase)
+}
+
+/// A Raspberry Pi without an SD card fetches its boot files from a directory named after its
+/// serial number, e.g. `/tftpboot/1a2b3c4d/start4.elf`.
+fn find_serial_number(line: &str) -> Option<String> {
+    let prefix = format!("{}/", TFTP_BASE_DIR);
+    line.split_whitespace()
+        .find_map(|word| word.strip_prefix(prefix.as_str()))
+        .and_then(|path| path.split('/').next())
+        .filter(|serial| serial.len() == 8 && serial.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+}
+
+fn find_ipv4_address(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .find(|word| word.parse::<Ipv4Addr>().is_ok())
+        .map(String::from)
+}
+
+/// Everything the cluster already knows, devices matching one of these are not discovered.
+fn get_known_identifiers() -> HashSet<String> {
+    get_config()
+        .nodes
+        .values()
+        .flat_map(|node| {
+            vec![
+                node.mac_address.clone(),
+                node.serial_number.clone(),
+                node.tftp_prefix.clone(),
+            ]
+        })
+        .flatten()
+        .map(|identifier| identifier.to_lowercase())
+        .collect()
+}
+
+/// Goes through the dnsmasq log for devices which asked for an address or, like Raspberry Pis,
+/// for boot files of a serial number, but are not part of the cluster.
+pub fn discover_nodes() -> Vec<DiscoveredNode> {
+    let content = fs::read(DNSMASQ_LOG_FILE)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default();
+    merge_discoveries(content.lines(), &get_known_identifiers())
+}
+
+/// Merges the DHCP and TFTP requests of the same device, a TFTP request belongs to the device
+/// with the same serial number or otherwise to the one which got its address.
+fn merge_discoveries<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    known: &HashSet<String>,
+) -> Vec<DiscoveredNode> {
+    let mut discovered: Vec<DiscoveredNode> = Vec::new();
+    for line in lines {
+        let ipv4_address = find_ipv4_address(line);
+        let mut requested_serial_number = None;
+        let index = if line.contains("dnsmasq-dhcp") {
+            let mac_address = match find_mac_address(line) {
+                Some(mac_address) if !known.contains(&mac_address) => mac_address,
+                _ => continue,
+            };
+            discovered
+                .iter()
+                .position(|d| d.mac_address.as_ref() == Some(&mac_address))
+                .unwrap_or_else(|| {
+                    discovered.push(DiscoveredNode {
+                        raspberry_pi: RASPBERRY_PI_PREFIXES
+                            .iter()
+                            .any(|prefix| mac_address.starts_with(prefix)),
+                        mac_address: Some(mac_address),
+                        ..DiscoveredNode::default()
+                    });
+                    discovered.len() - 1
+                })
+        } else if line.contains("dnsmasq-tftp") {
+            let serial_number = match find_serial_number(line) {
+                Some(serial_number) if !known.contains(&serial_number) => serial_number,
+                _ => continue,
+            };
+            requested_serial_number = Some(serial_number.clone());
+            // the request comes from the address dnsmasq handed out to the device before
+            discovered
+                .iter()
+                .position(|d| {
+                    d.serial_number.as_ref() == Some(&serial_number)
+                        || (d.serial_number.is_none()
+                            && ipv4_address.is_some()
+                            && d.ipv4_address == ipv4_address)
+                })
+                .unwrap_or_else(|| {
+                    discovered.push(DiscoveredNode::default());
+                    discovered.len() - 1
+                })
+        } else {
+            continue;
+        };
+        let node = &mut discovered[index];
+        if requested_serial_number.is_some() {
+            node.serial_number = requested_serial_number;
+            node.raspberry_pi = true;
+        }
+        if ipv4_address.is_some() {
+            node.ipv4_address = ipv4_address;
+        }
+        node.last_seen = line
+            .split("dnsmasq")
+            .next()
+            .map(str::trim)
+            .filter(|timestamp| !timestamp.is_empty())
+            .map(String::from);
+    }
+    discovered
+}
+
+pub fn list_discovered_nodes() {
+    let discovered = discover_nodes();
+    if discovered.is_empty() {
+        print_information("no unknown devices in the dnsmasq log");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("MAC address"),
+            Cell::new("IPv4 address"),
+            Cell::new("serial number"),
+            Cell::new("Raspberry Pi"),
+            Cell::new("last seen"),
+        ]
+        .to_vec(),
+    ));
+    for node in &discovered {
+        table.add_row(Row::new(node.get_cells()));
+    }
+    table.printstd()
+}
+
+/// Adds the discovered device with the given MAC address or serial number to the cluster, what
+/// the operator passes takes precedence over what was discovered.
+pub fn adopt_node(
+    storage: &dyn Storage,
+    id: &str,
+    mac_address: Option<&str>,
+    serial_number: Option<&str>,
+    name: Option<&str>,
+    ipv4_address: Option<&str>,
+    architecture: Option<&str>,
+) -> bool {
+    let mac_address = mac_address.map(str::to_lowercase);
+    let serial_number = serial_number.map(str::to_lowercase);
+    let candidate = discover_nodes().into_iter().find(|d| {
+        (mac_address.is_some() && d.mac_address == mac_address)
+            || (serial_number.is_some() && d.serial_number == serial_number)
+    });
+    let candidate = match candidate {
+        Some(candidate) => candidate,
+        None => {
+            eprintln!("No such device in the dnsmasq log, see node discover");
+            return false;
+        }
+    };
+    let raspberry_pi = candidate.raspberry_pi;
+    let serial_number = serial_number.or(candidate.serial_number);
+    let mac_address = mac_address.or(candidate.mac_address);
+    let definition = NodeDefinition {
+        name: Some(String::from(name.unwrap_or(id))),
+        // Raspberry Pis look for their boot files in a directory named after the serial number
+        tftp_prefix: serial_number
+            .clone()
+            .or_else(|| mac_address.as_ref().map(|mac| mac.replace(':', ""))),
+        mac_address,
+        ipv4_address: ipv4_address.map(String::from).or(candidate.ipv4_address),
+        serial_number,
+        architecture: architecture
+            .map(String::from)
+            .or_else(|| raspberry_pi.then(|| String::from("ARM64"))),
+        pxe: !raspberry_pi,
+        ..NodeDefinition::default()
+    };
+    match define_node(storage, id, definition) {
+        Ok(status) => {
+            print_message(&format!("adopt {}", id), status);
+            if status {
+                // the server only reads the node definitions when its configuration is reloaded
+                print_information(&format!(
+                    "a running server knows {} after config reload or a SIGHUP",
+                    id
+                ));
+            }
+            status
+        }
+        Err(problems) => {
+            for problem in problems {
+                eprintln!("{}", problem);
+            }
+            print_message(&format!("adopt {}", id), false);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DHCP_LINE: &str = "Mar  3 10:15:02 dnsmasq-dhcp[812]: DHCPACK(eth0) 10.0.0.23 \
+                             DC:A6:32:01:02:03 raspberrypi";
+    const TFTP_LINE: &str =
+        "Mar  3 10:15:04 dnsmasq-tftp[812]: sent /tftpboot/1A2B3C4D/start4.elf \
+                             to 10.0.0.23";
+
+    #[test]
+    fn finds_the_mac_address_in_a_dhcp_line() {
+        assert_eq!(
+            find_mac_address(DHCP_LINE),
+            Some(String::from("dc:a6:32:01:02:03"))
+        );
+        assert_eq!(find_mac_address(TFTP_LINE), None);
+    }
+
+    #[test]
+    fn finds_the_serial_number_in_a_tftp_line() {
+        assert_eq!(
+            find_serial_number(TFTP_LINE),
+            Some(String::from("1a2b3c4d"))
+        );
+        assert_eq!(
+            find_serial_number("dnsmasq-tftp[812]: sent /tftpboot/pxelinux.0 to 10.0.0.24"),
+            None
+        );
+        assert_eq!(find_serial_number(DHCP_LINE), None);
+    }
+
+    #[test]
+    fn merges_the_requests_of_a_device_by_its_address() {
+        let discovered = merge_discoveries(vec![DHCP_LINE, TFTP_LINE].into_iter(), &HashSet::new());
+        assert_eq!(discovered.len(), 1);
+        let node = &discovered[0];
+        assert_eq!(node.mac_address, Some(String::from("dc:a6:32:01:02:03")));
+        assert_eq!(node.serial_number, Some(String::from("1a2b3c4d")));
+        assert_eq!(node.ipv4_address, Some(String::from("10.0.0.23")));
+        assert!(node.raspberry_pi);
+        assert_eq!(node.last_seen, Some(String::from("Mar  3 10:15:04")));
+    }
+
+    #[test]
+    fn keeps_devices_with_different_addresses_apart() {
+        let other = "Mar  3 10:16:00 dnsmasq-dhcp[812]: DHCPACK(eth0) 10.0.0.24 \
+                     52:54:00:aa:bb:cc";
+        let discovered = merge_discoveries(
+            vec![DHCP_LINE, other, TFTP_LINE].into_iter(),
+            &HashSet::new(),
+        );
+        assert_eq!(discovered.len(), 2);
+        assert_eq!(discovered[0].serial_number, Some(String::from("1a2b3c4d")));
+        assert_eq!(discovered[1].serial_number, None);
+        assert!(!discovered[1].raspberry_pi);
+    }
+
+    #[test]
+    fn skips_known_devices() {
+        let known = vec![String::from("dc:a6:32:01:02:03"), String::from("1a2b3c4d")]
+            .into_iter()
+            .collect();
+        let lines = vec![DHCP_LINE, TFTP_LINE, "Mar  3 10:15:05 something else"];
+        assert!(merge_discoveries(lines.into_iter(), &known).is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config::get_node_by_id;
+use crate::deployer::execute_command_over_ssh;
+use crate::power_manager::{power_off, reboot};
+use crate::storage::Storage;
+use std::{thread, time};
+use structs::fault::{Fault, FaultInjection};
+use structs::node::Node;
+use structs::service::Service;
+use structs::task::Task;
+use structs::utils::print_message;
+
+/// Seconds the nodes get beyond the duration of a network fault to remove the impairment.
+const RESTORE_GRACE: u64 = 5;
+
+#[must_use]
+pub fn get_fault_injection(task: &Task) -> Option<FaultInjection> {
+    serde_json::from_str::<FaultInjection>(&task.parameters).ok()
+}
+
+/// Injects the fault of the task into every replica of its service, each injection is recorded in
+/// the timeline of the deployment. Network faults are reverted after their duration.
+pub fn run_fault_task(storage: &dyn Storage, task: &Task, services: &[Service]) -> bool {
+    let injection = match get_fault_injection(task) {
+        Some(injection) => injection,
+        None => return false,
+    };
+    let id = task.id.unwrap();
+    storage.set_startdate_for_task(id).unwrap_or_default();
+    let mut injected = Vec::new();
+    let mut status = true;
+    for service in services.iter().filter(|s| s.name.eq(&injection.service)) {
+        if let Some(node) = service.node.as_ref().and_then(|n| get_node_by_id(n, false)) {
+            let description = describe(&injection.fault, &node);
+            let executed = inject(&injection.fault, &node);
+            print_message(&description, executed);
+            record(storage, service, &description, executed);
+            if executed {
+                injected.push((service, node));
+            }
+            status &= executed;
+        }
+    }
+    if let Fault::Network { duration, .. } = injection.fault {
+        if !injected.is_empty() {
+            // the nodes remove the impairment themselves, even if they cannot be reached anymore
+            thread::sleep(time::Duration::from_secs(
+                duration.max(0) as u64 + RESTORE_GRACE,
+            ));
+            for (service, node) in injected {
+                let description = if is_network_restored(&node) {
+                    format!("network of {} restored", node.id)
+                } else {
+                    format!("restoring the network of {} is unverified", node.id)
+                };
+                record(storage, service, &description, true);
+            }
+        }
+    }
+    storage.set_outcome_for_task(id, status).unwrap_or_default();
+    status
+}
+
+fn inject(fault: &Fault, node: &Node) -> bool {
+    match fault {
+        Fault::PowerCycle => reboot(node),
+        Fault::PowerOff => power_off(node),
+        Fault::KillProcess(process) => execute_command_over_ssh(
+            node,
+            &format!(
+                "pkill --signal KILL --exact '{}'",
+                process.replace('\'', "'\\''")
+            ),
+        ),
+        Fault::Network { duration, .. } => execute_command_over_ssh(
+            node,
+            &format!(
+                "INTERFACE=$(ip route show default | awk '{{print $5; exit}}'); \
+                tc qdisc replace dev $INTERFACE root netem {} || exit 1; \
+                nohup sh -c \"sleep {}; tc qdisc del dev $INTERFACE root netem\" > /dev/null 2>&1 &",
+                fault
+                    .get_netem_parameters()
+                    .split_whitespace()
+                    .map(|word| format!("'{}'", word.replace('\'', "'\\''")))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                duration
+            ),
+        ),
+    }
+}
+
+/// Whether the node is reachable again and has no netem qdisc left on its default interface.
+fn is_network_restored(node: &Node) -> bool {
+    execute_command_over_ssh(
+        node,
+        "INTERFACE=$(ip route show default | awk '{print $5; exit}'); \
+        ! tc qdisc show dev $INTERFACE root | grep --quiet netem",
+    )
+}
+
+fn describe(fault: &Fault, node: &Node) -> String {
+    match fault {
+        Fault::PowerCycle => format!("power cycle {}", node.id),
+        Fault::PowerOff => format!("power off {}", node.id),
+        Fault::KillProcess(process) => format!("kill {} on {}", process, node.id),
+        Fault::Network { duration, .. } => format!(
+            "impair network of {} with {} for {}s",
+            node.id,
+            fault.get_netem_parameters(),
+            duration
+        ),
+    }
+}
+
+fn record(storage: &dyn Storage, service: &Service, description: &str, executed: bool) {
+    let message = if executed {
+        String::from(description)
+    } else {
+        format!("{} failed", description)
+    };
+    storage
+        .insert_timeline_event(service.deployment.unwrap(), service.id, &message)
+        .unwrap_or_default();
+}
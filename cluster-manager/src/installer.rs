@@ -35,6 +35,8 @@ pub const OS_IMAGES_DIR: &str = "/etc/cluster-manager/os_images";
 pub const TMP_DIR: &str = "/etc/cluster-manager/tmp";
 pub const DNSMASQ_NODES_CONFIG_FILE: &str = "/etc/cluster-manager/nodes.dnsmasq";
 pub const DNSMASQ_CONFIG_FILE: &str = "/etc/cluster-manager/dnsmasq.conf";
+pub const DNSMASQ_LOG_FILE: &str = "/var/log/dnsmasq.log";
+pub const SYSLOG_FILE: &str = "/var/log/syslog";
 const DEFAULT_DNSMASQ_CONFIG_FILE: &str = "/etc/dnsmasq.conf";
 pub const RESULTS_DIR: &str = "/etc/cluster-manager/results";
 pub const TFTP_BASE_DIR: &str = "/tftpboot";
@@ -160,7 +162,7 @@ fn default_dnsmasq_conf_content() -> String {
     builder.append("enable-tftp\n");
     builder.append("tftp-root=/tftpboot\n");
     builder.append("pxe-service=0,\"Raspberry Pi Boot\"\n");
-    builder.append("log-facility=/var/log/dnsmasq.log\n");
+    builder.append(format!("log-facility={}\n", DNSMASQ_LOG_FILE));
     builder.append("local=/cluster/\n");
     builder.append("domain=cluster\n");
     builder.append("conf-file=/etc/cluster-manager/nodes.dnsmasq\n");
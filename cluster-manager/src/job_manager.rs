@@ -0,0 +1,58 @@
+use crate::audit::set_actor;
+use crate::database::{insert_job, insert_job_step, set_finished_for_job, Error};
+use crate::deployer::deploy;
+use crate::storage::Storage;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use structs::deployment::Deployment;
+use structs::utils::{print_information, set_step_sink};
+
+/// Deployments compete for the same idle nodes, so only one of them may run at a time.
+static DEPLOYMENT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Runs `work` on its own thread and records everything it prints as steps of a new job.
+///
+/// # Errors
+///
+/// Will return `Err` if the job could not be added to the database
+pub fn start_job<F>(kind: &str, owner: &str, work: F) -> Result<i64, Error>
+where
+    F: FnOnce() -> (bool, Option<i64>) + Send + 'static,
+{
+    let id = insert_job(kind, owner)?;
+    let owner = String::from(owner);
+    thread::spawn(move || {
+        set_actor(Some(owner));
+        set_step_sink(Some(Arc::new(move |message, status| {
+            insert_job_step(id, message, status).unwrap_or_default();
+        })));
+        let (success, deployment) = catch_unwind(AssertUnwindSafe(work)).unwrap_or((false, None));
+        set_step_sink(None);
+        set_finished_for_job(id, success, deployment).unwrap_or_default();
+    });
+    Ok(id)
+}
+
+/// # Errors
+///
+/// Will return `Err` if the job could not be added to the database
+pub fn start_deployment_job(
+    storage: Arc<dyn Storage>,
+    mut deployment: Deployment,
+) -> Result<i64, Error> {
+    let owner = deployment.owner.clone();
+    start_job("deploy", &owner, move || {
+        let _guard = match DEPLOYMENT_LOCK.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                print_information("waiting for other deployments to finish");
+                DEPLOYMENT_LOCK
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+            }
+        };
+        let status = deploy(&*storage, &mut deployment);
+        (status, deployment.id)
+    })
+}
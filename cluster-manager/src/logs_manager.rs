@@ -1,5 +1,7 @@
-use crate::config::{get, get_log_sources_of, get_node_by_id};
-use crate::installer::{COPY, MOVE, NFS_BASE_DIR, RESULTS_DIR, ZIP};
+use crate::config::{get_config, get_log_sources_of, get_node_by_id};
+use crate::installer::{COPY, MOVE, NFS_BASE_DIR, RESULTS_DIR, RSYNC, ZIP};
+use crate::storage::Storage;
+use chrono::Utc;
 use linemux::MuxedLines;
 use serde_json::Value;
 use std::collections::HashMap;
@@ -10,13 +12,15 @@ use std::process::Command;
 use structs::deployment::Deployment;
 use structs::logsource::LogSourceTypes::HOST;
 use structs::logsource::{LogSource, LogSourceTypes};
+use structs::manifest::{Manifest, ServiceManifest, TaskManifest};
 use structs::node::Node;
 use structs::service::Service;
-use structs::utils::get_lines_from_file;
+use structs::task::Task;
+use structs::utils::{get_lines_from_file, print_message};
 use tokio::sync::mpsc::Sender;
 
 fn get_log_base_directory() -> Option<String> {
-    if let Some(config_value) = get("logstash-base-directory") {
+    if let Some(config_value) = get_config().logstash_base_directory.clone() {
         let logstash_directory = if config_value.ends_with('/') {
             config_value
         } else {
@@ -27,7 +31,13 @@ fn get_log_base_directory() -> Option<String> {
     None
 }
 
-pub fn collect_deployment_logs(id: i64, services: Vec<Service>) -> bool {
+pub fn collect_deployment_logs(
+    storage: &dyn Storage,
+    deployment: &Deployment,
+    tasks: &[Task],
+    services: Vec<Service>,
+) -> bool {
+    let id = deployment.id.unwrap();
     let deployment_dir = format!("{}/{}/", RESULTS_DIR, id);
     fs::create_dir_all(&deployment_dir).unwrap();
     for service in services {
@@ -44,6 +54,10 @@ pub fn collect_deployment_logs(id: i64, services: Vec<Service>) -> bool {
                 .success();
         }
     }
+    print_message(
+        "write manifest",
+        write_manifest(storage, deployment, tasks, &deployment_dir),
+    );
     let mut child = Command::new(ZIP)
         .current_dir(format!("{}/", RESULTS_DIR))
         .arg("-r")
@@ -54,6 +68,70 @@ pub fn collect_deployment_logs(id: i64, services: Vec<Service>) -> bool {
     child.wait().unwrap().success()
 }
 
+fn write_manifest(
+    storage: &dyn Storage,
+    deployment: &Deployment,
+    tasks: &[Task],
+    directory: &str,
+) -> bool {
+    let id = deployment.id.unwrap();
+    let mut manifest = Manifest::new(
+        deployment,
+        env!("CARGO_PKG_VERSION"),
+        Utc::now().naive_utc(),
+    );
+    manifest.spec = storage
+        .get_spec_of_deployment(id)
+        .unwrap_or(None)
+        .and_then(|spec| serde_json::from_str(&spec).ok());
+    manifest.services = storage
+        .get_services_by_deployment(id)
+        .unwrap_or_default()
+        .iter()
+        .map(|s| {
+            let node = s.node.as_ref().and_then(|n| get_node_by_id(n, true));
+            ServiceManifest::new(s, node.as_ref())
+        })
+        .collect();
+    manifest.tasks = tasks.iter().map(TaskManifest::new).collect();
+    manifest.transitions = storage
+        .get_state_transitions_of_deployment(id)
+        .unwrap_or_default();
+    manifest.timeline = storage.get_timeline_of_deployment(id).unwrap_or_default();
+    match serde_json::to_string_pretty(&manifest) {
+        Ok(json) => fs::write(format!("{}manifest.json", directory), json).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Copies what the services of a running deployment wrote to their results directory so far into
+/// the results of the deployment, only changed files are transferred. Files are never deleted from
+/// a snapshot, a wiped nfsroot does not take the snapshot with it.
+pub fn snapshot_results(deployment: i64, services: &[Service]) -> bool {
+    let mut status = true;
+    for node in services.iter().filter_map(|s| s.node.as_ref()) {
+        let nfs_dir = format!("{}/{}/results/", NFS_BASE_DIR, node);
+        if !Path::new(&nfs_dir).exists() {
+            continue;
+        }
+        let snapshot_dir = format!("{}/{}/snapshots/{}", RESULTS_DIR, deployment, node);
+        if fs::create_dir_all(&snapshot_dir).is_err() {
+            status = false;
+            continue;
+        }
+        status &= Command::new(RSYNC)
+            .arg("-a")
+            .arg(nfs_dir)
+            .arg(snapshot_dir)
+            .spawn()
+            .expect("failed to snapshot results")
+            .wait()
+            .unwrap()
+            .success();
+    }
+    status
+}
+
 pub fn gather_logs(node: &Node) -> bool {
     let log_sources = get_log_sources_of(&node);
     let nfs_dir = format!("{}/{}/results/", NFS_BASE_DIR, node.id);
@@ -98,14 +176,15 @@ pub fn gather_logs(node: &Node) -> bool {
 fn get_log_sources(log_sources: Vec<LogSource>) -> (Vec<String>, HashMap<String, LogSourceTypes>) {
     let mut files = Vec::new();
     let mut file_to_types: HashMap<String, LogSourceTypes> = HashMap::new();
+    // without logstash-base-directory logstash writes no host logs the cluster-manager knows of
+    let base_directory = match get_log_base_directory() {
+        Some(base_directory) => base_directory,
+        None => return (files, file_to_types),
+    };
     for log_source in log_sources {
         match log_source.source {
             LogSourceTypes::HOST => {
-                let file = format!(
-                    "{}logs/{}/logs",
-                    get_log_base_directory().unwrap(),
-                    log_source.path
-                );
+                let file = format!("{}logs/{}/logs", base_directory, log_source.path);
                 file_to_types.insert(file.clone(), LogSourceTypes::HOST);
                 files.push(file);
             }
@@ -115,43 +194,31 @@ fn get_log_sources(log_sources: Vec<LogSource>) -> (Vec<String>, HashMap<String,
     (files, file_to_types)
 }
 
-pub fn get_logs_of_deployment(
-    deployment: &Deployment,
-    timestamp: bool,
-) -> Vec<(String, String, String)> {
+/// Returns the files logstash writes the host logs of the deployment's nodes to.
+pub fn get_host_log_files(deployment: &Deployment) -> Vec<String> {
     let mut vec = Vec::new();
     for service in &deployment.services {
         if let Some(node) = get_node_by_id(service.node.as_ref().unwrap(), false) {
-            let log_sources = get_log_sources_of(&node);
-            let (files, file_to_types) = get_log_sources(log_sources);
-            for file in files {
-                if let Ok(file_content) = get_lines_from_file(&file) {
-                    for line in file_content {
-                        match file_to_types.get(&file).unwrap() {
-                            HOST => {
-                                let json: Value = serde_json::from_str(&line).unwrap();
-                                let time = json.get("@timestamp").unwrap().as_str().unwrap();
-                                let message = json.get("message").unwrap().as_str().unwrap();
-                                if timestamp {
-                                    vec.push((
-                                        node.id.clone(),
-                                        file.clone(),
-                                        format!("{}: {}", time, message),
-                                    ));
-                                } else {
-                                    vec.push((node.id.clone(), file.clone(), message.to_string()));
-                                }
-                            }
-                            LogSourceTypes::SERIAL => {}
-                        }
-                    }
-                }
-            }
+            vec.extend(get_host_log_files_of(&node));
         }
     }
     vec
 }
 
+/// Returns the files logstash writes the host logs of the node to.
+pub fn get_host_log_files_of(node: &Node) -> Vec<String> {
+    let (files, _) = get_log_sources(get_log_sources_of(node));
+    files
+}
+
+/// Extracts the message of a line logstash wrote.
+pub fn get_message_of_line(line: &str) -> Option<String> {
+    let json: Value = serde_json::from_str(line).ok()?;
+    json.get("message")
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
 pub async fn watch_logs(
     node: &Node,
     tx: Sender<(String, String)>,
@@ -1,25 +1,50 @@
 use crate::config::get_node_by_id;
 use crate::database::check;
-use crate::deployer::deploy_single_image;
+use crate::deployer::{
+    deploy, deploy_single_image, plan, prepare_rerun, print_plan, read_deployment,
+};
 use crate::manager::list_images;
+use crate::storage::{SqliteStorage, Storage};
+use chrono::NaiveDateTime;
 use clap::{App, Arg, ArgMatches};
 use std::collections::HashMap;
+use structs::audit_entry::AuditFilter;
+use structs::deployment::Deployment;
+use structs::node::Node;
+use structs::rerun_request::{parse_override, RerunRequest};
+use structs::reservation_request::{
+    add_reservation_subcommand, parse_end_argument, parse_reservation_arguments,
+};
+use structs::state::{State, STATES};
+use structs::user::User;
+use structs::utils::{get_current_user, parse_local_datetime, print_message};
 
+mod audit;
+mod authentication;
+mod boot_watchdog;
 mod config;
 mod database;
 mod deployer;
+mod discovery;
+mod fault_injector;
 mod installer;
+mod job_manager;
 mod logs_manager;
 mod manager;
 mod node_manager;
 mod power_manager;
+mod remote_commands;
+mod reservation_manager;
+mod rollback;
+mod scheduler;
 mod server;
+mod storage;
+mod user_manager;
 mod watcher;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     sudo::escalate_if_needed().unwrap();
-    check();
     let matches = App::new("cluster-manager")
         .version("0.1")
         .author("Andreas Resch <andreas@resch.io>")
@@ -31,9 +56,22 @@ async fn main() -> std::io::Result<()> {
         .subcommand(add_deploy_subcommand())
         .subcommand(add_service_subcommand())
         .subcommand(add_deployment_subcommand())
+        .subcommand(add_reservation_subcommand())
         .subcommand(add_watch_subcommand())
         .subcommand(add_server_subcommand())
+        .subcommand(add_user_subcommand())
+        .subcommand(add_db_subcommand())
+        .subcommand(add_audit_subcommand())
+        .subcommand(add_config_subcommand())
         .get_matches();
+    // the database is created or migrated by these, config.yml can be checked without it
+    if !matches!(
+        matches.subcommand_name(),
+        Some("install") | Some("db") | Some("config")
+    ) && !check()
+    {
+        std::process::exit(1);
+    }
 
     handle_subcommands(&matches).await;
     Ok(())
@@ -94,6 +132,137 @@ fn add_node_subcommand() -> App<'static, 'static> {
                     .takes_value(true),
             ),
         )
+        .subcommand(
+            App::new("clear-suspect")
+                .about("use a node which did not boot for deployments again")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("Configured identifier in config.yml")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("cordon")
+                .about("keep new services off a node for maintenance")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("Configured identifier in config.yml")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("note")
+                        .long("note")
+                        .help("why the node is in maintenance")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .help("when the node is expected back (YYYY-MM-DD HH:MM)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("drain")
+                .about("stop the services running on a node and cordon it")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("Configured identifier in config.yml")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("note")
+                        .long("note")
+                        .help("why the node is in maintenance")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .help("when the node is expected back (YYYY-MM-DD HH:MM)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("prune")
+                        .long("prune")
+                        .help("remove the images of the stopped services"),
+                ),
+        )
+        .subcommand(
+            App::new("uncordon")
+                .about("use a node in maintenance for deployments again")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("Configured identifier in config.yml")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("discover")
+                .about("list devices from the dnsmasq log which are not part of the cluster"),
+        )
+        .subcommand(
+            App::new("adopt")
+                .about("add a discovered device to the cluster")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("identifier the node gets in the cluster")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("mac-address")
+                        .long("mac-address")
+                        .help("MAC address of the discovered device")
+                        .required_unless("serial-number")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("serial-number")
+                        .long("serial-number")
+                        .help("serial number of the discovered device")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .help("hostname of the node, defaults to the id")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("ipv4-address")
+                        .long("ipv4-address")
+                        .help("address of the node, defaults to the one it was offered")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("architecture")
+                        .long("architecture")
+                        .help("architecture of the node, Raspberry Pis default to ARM64")
+                        .possible_values(&["ARM32", "ARM64", "X86"])
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("history")
+                .about("show the state transitions of the node with the given id")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("Configured identifier in config.yml")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
 }
 
 fn add_deploy_subcommand() -> App<'static, 'static> {
@@ -120,6 +289,12 @@ fn add_deploy_subcommand() -> App<'static, 'static> {
                 .help("yaml file to deploy several nodes at once")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("only show where the deployment would land, without touching any node")
+                .takes_value(false),
+        )
 }
 
 fn add_service_subcommand() -> App<'static, 'static> {
@@ -141,6 +316,19 @@ fn add_service_subcommand() -> App<'static, 'static> {
                         .long("group")
                         .help("group services")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("owner")
+                        .long("owner")
+                        .help("only show services of deployments owned by this user")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .long("state")
+                        .help("only show services in this state")
+                        .possible_values(&STATES)
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -174,6 +362,19 @@ fn add_deployment_subcommand() -> App<'static, 'static> {
                         .long("all")
                         .help("show all deployments, stopped included")
                         .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("owner")
+                        .long("owner")
+                        .help("only show deployments owned by this user")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .long("state")
+                        .help("only show deployments in this state")
+                        .possible_values(&STATES)
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -193,6 +394,56 @@ fn add_deployment_subcommand() -> App<'static, 'static> {
                         .takes_value(false),
                 ),
         )
+        .subcommand(
+            App::new("rerun")
+                .about("deploy the deployment with the given id again")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the deployment which should be deployed again")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .help("name of the new deployment, defaults to the original one")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("image")
+                        .long("image")
+                        .help("use another image for a service, e.g. web=debian-11")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("node")
+                        .long("node")
+                        .help("prefer another node for a service, e.g. web=pi-04")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("only show where the services would be placed")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            App::new("history")
+                .about("show the state transitions of the deployment with the given id")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the deployment")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
 }
 
 fn add_watch_subcommand() -> App<'static, 'static> {
@@ -218,6 +469,91 @@ fn add_watch_subcommand() -> App<'static, 'static> {
         )
 }
 
+fn add_db_subcommand() -> App<'static, 'static> {
+    App::new("db")
+        .about("manage the schema of the database")
+        .subcommand(App::new("migrate").about("apply all pending migrations"))
+        .subcommand(App::new("status").about("show the schema version and its migrations"))
+}
+
+fn add_config_subcommand() -> App<'static, 'static> {
+    App::new("config").about("manage config.yml").subcommand(
+        App::new("check").about("report missing fields and conflicts between the nodes"),
+    )
+}
+
+fn add_audit_subcommand() -> App<'static, 'static> {
+    App::new("audit")
+        .about("show who changed what on the testbed")
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .help("only show actions of this user")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("node")
+                .long("node")
+                .help("only show actions on this node")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .help("only show actions since this time (YYYY-MM-DD HH:MM)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .help("only show actions until this time (YYYY-MM-DD HH:MM)")
+                .takes_value(true),
+        )
+}
+
+fn add_user_subcommand() -> App<'static, 'static> {
+    App::new("user")
+        .about("manage the users and tokens of the remote management api")
+        .subcommand(App::new("list").about("list all users"))
+        .subcommand(
+            App::new("add")
+                .about("add a new user and print its token")
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .help("name of the user")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("admin")
+                        .long("admin")
+                        .help("allow the user to manage deployments of others")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            App::new("token")
+                .about("replace the token of a user and print the new one")
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .help("name of the user")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("del").about("remove a user").arg(
+                Arg::with_name("name")
+                    .long("name")
+                    .help("name of the user")
+                    .required(true)
+                    .takes_value(true),
+            ),
+        )
+}
+
 fn add_server_subcommand() -> App<'static, 'static> {
     App::new("server")
         .about("start server for remote management")
@@ -244,6 +580,11 @@ async fn handle_subcommands(matches: &ArgMatches<'_>) {
     subcommands.insert("service", handle_service_subcommand);
     subcommands.insert("deployment", handle_deployment_subcommand);
     subcommands.insert("image", handle_image_subcommand);
+    subcommands.insert("reservation", handle_reservation_subcommand);
+    subcommands.insert("user", handle_user_subcommand);
+    subcommands.insert("db", handle_db_subcommand);
+    subcommands.insert("audit", handle_audit_subcommand);
+    subcommands.insert("config", handle_config_subcommand);
     for (command, function) in &subcommands {
         if let Some(matches) = matches.subcommand_matches(command) {
             function(matches);
@@ -268,16 +609,38 @@ fn handle_check_subcommand(_matches: &ArgMatches) {
 
 fn handle_install_subcommand(_matches: &ArgMatches) {
     installer::install();
+    self::manager::migrate_database();
+}
+
+fn handle_db_subcommand(matches: &ArgMatches) {
+    if matches.subcommand_matches("migrate").is_some() {
+        self::manager::migrate_database();
+    }
+    if matches.subcommand_matches("status").is_some() {
+        self::manager::print_database_status();
+    }
+}
+
+fn handle_config_subcommand(matches: &ArgMatches) {
+    if matches.subcommand_matches("check").is_some() && !config::check_config() {
+        std::process::exit(1);
+    }
 }
 
 fn handle_service_subcommand(matches: &ArgMatches) {
     if let Some(submatches) = matches.subcommand_matches("list") {
-        self::manager::list_services(submatches.is_present("all"), submatches.is_present("group"));
+        self::manager::list_services(
+            &SqliteStorage,
+            submatches.is_present("all"),
+            submatches.is_present("group"),
+            submatches.value_of("owner"),
+            get_state_filter(submatches).as_ref(),
+        );
     }
     if let Some(matches) = matches.subcommand_matches("stop") {
         if let Some(param) = matches.value_of("id") {
             if let Ok(id) = param.parse::<i64>() {
-                self::manager::stop_service(id, matches.is_present("prune"));
+                self::manager::stop_service(&SqliteStorage, id, matches.is_present("prune"));
             } else {
                 eprintln!("Please provide a valid id")
             }
@@ -289,12 +652,17 @@ fn handle_service_subcommand(matches: &ArgMatches) {
 
 fn handle_deployment_subcommand(matches: &ArgMatches) {
     if let Some(submatches) = matches.subcommand_matches("list") {
-        self::manager::list_deployments(submatches.is_present("all"));
+        self::manager::list_deployments(
+            &SqliteStorage,
+            submatches.is_present("all"),
+            submatches.value_of("owner"),
+            get_state_filter(submatches).as_ref(),
+        );
     }
     if let Some(matches) = matches.subcommand_matches("stop") {
         if let Some(param) = matches.value_of("id") {
             if let Ok(id) = param.parse::<i64>() {
-                self::manager::stop_deployment(id, matches.is_present("prune"));
+                self::manager::stop_deployment(&SqliteStorage, id, matches.is_present("prune"));
             } else {
                 eprintln!("Please provide a valid id")
             }
@@ -302,20 +670,175 @@ fn handle_deployment_subcommand(matches: &ArgMatches) {
             eprintln!("Please provide an id")
         }
     }
+    if let Some(matches) = matches.subcommand_matches("rerun") {
+        let id = match matches.value_of("id").unwrap().parse::<i64>() {
+            Ok(id) => id,
+            Err(_) => {
+                eprintln!("Please provide a valid id");
+                return;
+            }
+        };
+        let deployment = get_rerun_request(matches)
+            .and_then(|request| prepare_rerun(&SqliteStorage, id, &get_current_user(), &request));
+        match deployment {
+            Ok(mut deployment) => {
+                if matches.is_present("dry-run") {
+                    print_plan(&plan(&SqliteStorage, &mut deployment));
+                } else {
+                    deploy(&SqliteStorage, &mut deployment);
+                }
+            }
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("history") {
+        if let Ok(id) = matches.value_of("id").unwrap().parse::<i64>() {
+            self::manager::list_state_transitions(&SqliteStorage, id);
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+}
+
+fn get_rerun_request(matches: &ArgMatches) -> Result<RerunRequest, String> {
+    let mut request = RerunRequest {
+        name: matches.value_of("name").map(String::from),
+        ..RerunRequest::default()
+    };
+    for assignment in matches.values_of("image").into_iter().flatten() {
+        let (service, image) = parse_override(assignment)?;
+        request.images.insert(service, image);
+    }
+    for assignment in matches.values_of("node").into_iter().flatten() {
+        let (service, node) = parse_override(assignment)?;
+        request.nodes.insert(service, node);
+    }
+    Ok(request)
+}
+
+fn get_state_filter(matches: &ArgMatches) -> Option<State> {
+    matches
+        .value_of("state")
+        .and_then(|name| State::parse(name).ok())
+}
+
+fn handle_audit_subcommand(matches: &ArgMatches) {
+    match get_audit_filter(matches) {
+        Ok(filter) => self::audit::list_audit_entries(&filter),
+        Err(message) => eprintln!("{}", message),
+    }
+}
+
+fn get_audit_filter(matches: &ArgMatches) -> Result<AuditFilter, String> {
+    Ok(AuditFilter {
+        user: matches.value_of("user").map(String::from),
+        node: matches.value_of("node").map(String::from),
+        since: matches
+            .value_of("since")
+            .map(parse_local_datetime)
+            .transpose()?,
+        until: matches
+            .value_of("until")
+            .map(parse_local_datetime)
+            .transpose()?,
+    })
+}
+
+fn handle_user_subcommand(matches: &ArgMatches) {
+    if let Some(_submatches) = matches.subcommand_matches("list") {
+        self::user_manager::list_users();
+    }
+    if let Some(matches) = matches.subcommand_matches("add") {
+        self::user_manager::add_user(
+            matches.value_of("name").unwrap(),
+            matches.is_present("admin"),
+        );
+    }
+    if let Some(matches) = matches.subcommand_matches("token") {
+        self::user_manager::renew_token(matches.value_of("name").unwrap());
+    }
+    if let Some(matches) = matches.subcommand_matches("del") {
+        self::user_manager::remove_user(matches.value_of("name").unwrap());
+    }
+}
+
+fn handle_reservation_subcommand(matches: &ArgMatches) {
+    if let Some(submatches) = matches.subcommand_matches("list") {
+        self::reservation_manager::list_reservations(&SqliteStorage, submatches.is_present("all"));
+    }
+    if let Some(matches) = matches.subcommand_matches("add") {
+        match parse_reservation_arguments(matches) {
+            Ok(request) => {
+                self::reservation_manager::create_reservation(&SqliteStorage, &request);
+            }
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("extend") {
+        if let Ok(id) = matches.value_of("id").unwrap().parse::<i64>() {
+            let reservation = match SqliteStorage.get_reservation_by_id(id) {
+                Ok(reservation) => reservation,
+                Err(_) => {
+                    eprintln!("No such reservation found");
+                    return;
+                }
+            };
+            match parse_end_argument(matches, &reservation.end) {
+                Ok(end) => {
+                    match self::reservation_manager::extend_reservation(
+                        &SqliteStorage,
+                        id,
+                        &get_local_user(),
+                        &end,
+                    ) {
+                        Ok(()) => print_message("extend reservation", true),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            print_message("extend reservation", false)
+                        }
+                    }
+                }
+                Err(message) => eprintln!("{}", message),
+            }
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("release") {
+        if let Ok(id) = matches.value_of("id").unwrap().parse::<i64>() {
+            match self::reservation_manager::release_reservation(
+                &SqliteStorage,
+                id,
+                &get_local_user(),
+            ) {
+                Ok(()) => print_message("release reservation", true),
+                Err(message) => {
+                    eprintln!("{}", message);
+                    print_message("release reservation", false)
+                }
+            }
+        } else {
+            eprintln!("Please provide a valid id")
+        }
+    }
+}
+
+fn get_local_user() -> User {
+    User::new(&get_current_user(), true)
 }
 
 fn handle_node_subcommand(matches: &ArgMatches) {
     if let Some(submatches) = matches.subcommand_matches("list") {
-        self::node_manager::list_nodes(submatches.is_present("all"));
+        self::node_manager::list_nodes(&SqliteStorage, submatches.is_present("all"));
     }
     if let Some(matches) = matches.subcommand_matches("add") {
         let identifier = parse_node_arguments(matches);
-        self::node_manager::add_node(&identifier);
+        self::node_manager::add_node(&SqliteStorage, &identifier);
     }
     if let Some(matches) = matches.subcommand_matches("del") {
         if let Some(id) = matches.value_of("id") {
             if let Some(node) = get_node_by_id(id, false) {
-                self::node_manager::remove_node(&node);
+                self::node_manager::remove_node(&SqliteStorage, &node);
             } else {
                 eprintln!("Please provide a valid id")
             }
@@ -326,7 +849,12 @@ fn handle_node_subcommand(matches: &ArgMatches) {
     if let Some(matches) = matches.subcommand_matches("stop") {
         if let Some(id) = matches.value_of("id") {
             if let Some(node) = get_node_by_id(id, false) {
-                self::node_manager::stop_node(&node, matches.is_present("prune"), false);
+                self::node_manager::stop_node(
+                    &SqliteStorage,
+                    &node,
+                    matches.is_present("prune"),
+                    false,
+                );
             } else {
                 eprintln!("Please provide a valid id")
             }
@@ -334,6 +862,90 @@ fn handle_node_subcommand(matches: &ArgMatches) {
             eprintln!("Please provide an id")
         }
     }
+    if let Some(matches) = matches.subcommand_matches("clear-suspect") {
+        if let Some(id) = matches.value_of("id") {
+            if let Some(node) = get_node_by_id(id, false) {
+                self::node_manager::clear_suspect_node(&SqliteStorage, &node);
+            } else {
+                eprintln!("Please provide a valid id")
+            }
+        } else {
+            eprintln!("Please provide an id")
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("cordon") {
+        if let Some((node, until)) = get_maintenance_arguments(matches) {
+            self::node_manager::cordon_node(
+                &SqliteStorage,
+                &node,
+                matches.value_of("note"),
+                until.as_ref(),
+            );
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("drain") {
+        if let Some((node, until)) = get_maintenance_arguments(matches) {
+            self::node_manager::drain_node(
+                &SqliteStorage,
+                &node,
+                matches.value_of("note"),
+                until.as_ref(),
+                matches.is_present("prune"),
+            );
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("uncordon") {
+        if let Some(id) = matches.value_of("id") {
+            if let Some(node) = get_node_by_id(id, false) {
+                self::node_manager::uncordon_node(&SqliteStorage, &node);
+            } else {
+                eprintln!("Please provide a valid id")
+            }
+        } else {
+            eprintln!("Please provide an id")
+        }
+    }
+    if matches.subcommand_matches("discover").is_some() {
+        self::discovery::list_discovered_nodes();
+    }
+    if let Some(matches) = matches.subcommand_matches("adopt") {
+        self::discovery::adopt_node(
+            &SqliteStorage,
+            matches.value_of("id").unwrap(),
+            matches.value_of("mac-address"),
+            matches.value_of("serial-number"),
+            matches.value_of("name"),
+            matches.value_of("ipv4-address"),
+            matches.value_of("architecture"),
+        );
+    }
+    if let Some(matches) = matches.subcommand_matches("history") {
+        self::node_manager::list_node_state_transitions(
+            &SqliteStorage,
+            matches.value_of("id").unwrap(),
+        );
+    }
+}
+
+fn get_maintenance_arguments(matches: &ArgMatches) -> Option<(Node, Option<NaiveDateTime>)> {
+    let node = match matches.value_of("id").map(|id| get_node_by_id(id, false)) {
+        Some(Some(node)) => node,
+        _ => {
+            eprintln!("Please provide a valid id");
+            return None;
+        }
+    };
+    match matches
+        .value_of("until")
+        .map(parse_local_datetime)
+        .transpose()
+    {
+        Ok(until) => Some((node, until)),
+        Err(error) => {
+            eprintln!("{}", error);
+            None
+        }
+    }
 }
 
 fn handle_deploy_subcommand(matches: &ArgMatches) {
@@ -347,9 +959,21 @@ fn handle_deploy_subcommand(matches: &ArgMatches) {
                 return;
             }
         }
-        deploy_single_image(image, node);
+        if matches.is_present("dry-run") {
+            let mut deployment =
+                Deployment::for_image(image, node.map(|n| n.id), &get_current_user());
+            print_plan(&plan(&SqliteStorage, &mut deployment));
+        } else {
+            deploy_single_image(&SqliteStorage, image, node, &get_current_user());
+        }
     } else if let Some(file) = matches.value_of("file") {
-        self::deployer::deploy_deployment(file);
+        if matches.is_present("dry-run") {
+            if let Some(mut deployment) = read_deployment(file) {
+                print_plan(&plan(&SqliteStorage, &mut deployment));
+            }
+        } else {
+            self::deployer::deploy_deployment(&SqliteStorage, file);
+        }
     }
 }
 
@@ -364,7 +988,9 @@ async fn handle_watch_subcommand(matches: &ArgMatches<'_>) {
     if let Some(matches) = matches.subcommand_matches("service") {
         if let Some(param) = matches.value_of("id") {
             if let Ok(id) = param.parse::<i64>() {
-                self::manager::watch_service_logs_of(id).await.unwrap();
+                self::manager::watch_service_logs_of(&SqliteStorage, id)
+                    .await
+                    .unwrap();
             } else {
                 eprintln!("Please provide a valid id")
             }
@@ -1,13 +1,14 @@
+use crate::audit::{record, record_for_nodes};
 use crate::config::get_node_by_id;
-use crate::database::{
-    get_deployment_by_id, get_deployments, get_running_deployments, get_running_services,
-    get_service_by_id, get_services, get_services_by_deployment, get_tasks_by_deployment,
-    set_enddate_for_deployment, set_enddate_for_service,
+use crate::database::migrations::{
+    get_latest_version, get_migrations, get_schema_version, migrate,
 };
-use crate::deployer::{extract_configuration, retrieve_local_logs};
+use crate::deployer::{clean_local_storage, extract_configuration, retrieve_local_logs};
 use crate::installer::OS_IMAGES_DIR;
-use crate::logs_manager::{collect_deployment_logs, watch_logs};
-use crate::node_manager::stop_node;
+use crate::logs_manager::{collect_deployment_logs, snapshot_results, watch_logs};
+use crate::node_manager::{refresh_node_state, stop_node};
+use crate::remote_commands::run_command_task;
+use crate::storage::Storage;
 use colored::Colorize;
 use prettytable::format;
 use prettytable::{Cell, Row, Table};
@@ -22,11 +23,22 @@ use structs::mountpoint::Mountpoint;
 use structs::node::Node;
 use structs::service::Service;
 use structs::service_row::ServiceRow;
+use structs::state::State;
+use structs::state_transition_row::StateTransitionRow;
 use structs::task::Task;
-use structs::task::Type::GetResults;
-use structs::utils::print_message;
+use structs::task::Type::{
+    DeleteLocalStorage, GetResults, InjectFault, NoOp, PurgeLocalStorage, RunCommand,
+    SnapshotResults, StopIfTrue,
+};
+use structs::utils::{print_information, print_message};
 
-pub fn list_services(all: bool, group: bool) {
+pub fn list_services(
+    storage: &dyn Storage,
+    all: bool,
+    group: bool,
+    owner: Option<&str>,
+    state: Option<&State>,
+) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     table.set_titles(Row::new(
@@ -41,33 +53,58 @@ pub fn list_services(all: bool, group: bool) {
             Cell::new("started"),
             Cell::new("ended"),
             Cell::new("replicas"),
+            Cell::new("state"),
         ]
         .to_vec(),
     ));
 
-    for row in get_service_rows(all, group) {
+    for row in get_service_rows(storage, all, group, owner, state) {
         table.add_row(Row::new(row.get_cells()));
     }
     table.printstd();
 }
 
-pub fn get_service_rows(all: bool, group: bool) -> Vec<ServiceRow> {
+pub fn get_service_rows(
+    storage: &dyn Storage,
+    all: bool,
+    group: bool,
+    owner: Option<&str>,
+    state: Option<&State>,
+) -> Vec<ServiceRow> {
     let mut rows = Vec::new();
-    if let Ok(mut services) = if all {
-        get_services()
+    if let Ok(mut services) = if all || state.is_some() {
+        storage.get_services()
     } else {
-        get_running_services()
+        storage.get_running_services()
     } {
+        if let Some(owner) = owner {
+            let owned = storage
+                .get_deployments_by_owner(owner)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|d| d.id)
+                .collect::<Vec<i64>>();
+            services.retain(|s| s.deployment.map_or(false, |d| owned.contains(&d)));
+        }
+        if let Some(state) = state {
+            services.retain(|s| s.state.as_ref().eq(&Some(state)));
+        }
         if services.is_empty() {
             return Vec::new();
         }
         if group {
             services = Service::group_services(services);
         }
+        let names = storage.get_deployment_names().unwrap_or_default();
         let mut handles: Vec<JoinHandle<()>> = Vec::new();
         let (tx, rx) = mpsc::channel();
         for service in services {
-            handles.push(get_service_line_handle(service.clone(), tx.clone()));
+            let deployment = service
+                .deployment
+                .and_then(|d| names.get(&d))
+                .cloned()
+                .unwrap_or_default();
+            handles.push(get_service_line_handle(service, deployment, tx.clone()));
         }
         for handle in handles {
             handle.join().unwrap();
@@ -81,56 +118,151 @@ pub fn get_service_rows(all: bool, group: bool) -> Vec<ServiceRow> {
     rows
 }
 
-pub fn stop_service(id: i64, prune: bool) {
-    if let Ok(service) = get_service_by_id(id, true) {
+pub fn stop_service(storage: &dyn Storage, id: i64, prune: bool) -> bool {
+    if let Ok(service) = storage.get_service_by_id(id, true) {
         let node = get_node_by_id(&service.node.unwrap(), false).unwrap();
-        let stopped = stop_node(&node, prune, false);
+        storage
+            .set_state_for_service(id, &State::CollectingResults, None)
+            .unwrap_or_default();
+        let stopped = stop_node(storage, &node, prune, false);
         print_message("stop node", stopped);
+        let mut status = false;
         if stopped {
-            print_message("stop service", set_enddate_for_service(id).is_ok());
+            status = storage.set_enddate_for_service(id).is_ok();
+            storage
+                .set_state_for_service(id, &State::Finished, None)
+                .unwrap_or_default();
+            refresh_node_state(storage, &node);
+            print_message("stop service", status);
         }
-    } else {
-        eprintln!("No such service found");
+        return record(
+            "stop service",
+            &format!("service {}", id),
+            Some(&node.id),
+            status,
+        );
+    }
+    eprintln!("No such service found");
+    record("stop service", &format!("service {}", id), None, false)
+}
+
+pub fn get_owner_of_service(storage: &dyn Storage, id: i64) -> Option<String> {
+    match storage.get_service_by_id(id, false) {
+        Ok(service) => service
+            .deployment
+            .and_then(|d| get_owner_of_deployment(storage, d)),
+        Err(_) => None,
     }
 }
 
-pub fn stop_deployment(id: i64, prune: bool) {
-    if let Ok(mut deployment) = get_deployment_by_id(id, true) {
-        let tasks = match get_tasks_by_deployment(id) {
+pub fn get_owner_of_deployment(storage: &dyn Storage, id: i64) -> Option<String> {
+    match storage.get_deployment_by_id(id, false) {
+        Ok(deployment) => Some(deployment.owner),
+        Err(_) => None,
+    }
+}
+
+pub fn stop_deployment(storage: &dyn Storage, id: i64, prune: bool) -> bool {
+    if let Ok(mut deployment) = storage.get_deployment_by_id(id, true) {
+        let tasks = match storage.get_tasks_by_deployment(id) {
             Ok(t) => t,
             _ => Vec::new(),
         };
-        if let Ok(services) = get_services_by_deployment(deployment.id.unwrap()) {
+        if let Ok(services) = storage.get_services_by_deployment(deployment.id.unwrap()) {
+            storage
+                .set_state_for_deployment(id, &State::CollectingResults, None)
+                .unwrap_or_default();
+            for task in tasks
+                .iter()
+                .filter(|t| t.task_type.eq(&RunCommand) && !t.during_deployment)
+            {
+                run_command_task(storage, task, &services);
+            }
+            for task in tasks.iter().filter(|t| t.task_type.eq(&SnapshotResults)) {
+                let status = snapshot_results(id, &services);
+                print_message("snapshot results", status);
+                if let Some(task_id) = task.id {
+                    storage
+                        .set_outcome_for_task(task_id, status)
+                        .unwrap_or_default();
+                }
+            }
             for service in &services {
                 for task in tasks
                     .iter()
-                    .filter_map(|t| {
-                        if t.task_type.eq(&GetResults)
-                            && t.service.as_ref().unwrap().id.unwrap() == service.id.unwrap()
-                        {
-                            return Some(t.to_owned());
-                        }
-                        None
+                    .filter(|t| {
+                        !t.during_deployment
+                            && t.service.as_ref().and_then(|s| s.id).eq(&service.id)
                     })
-                    .collect::<Vec<Task>>()
+                    .collect::<Vec<&Task>>()
                 {
-                    let mountpoint: Mountpoint = serde_json::from_str(&task.parameters).unwrap();
-                    let node = get_node_by_id(&service.node.as_ref().unwrap(), false).unwrap();
-                    retrieve_local_logs(&mut deployment, &service, &node, &mountpoint);
+                    run_task_of_service(storage, &mut deployment, service, task);
                 }
-                stop_service(service.id.unwrap(), prune);
+                stop_service(storage, service.id.unwrap(), prune);
             }
-            collect_deployment_logs(id, services);
-            print_message("stop deployment", set_enddate_for_deployment(id).is_ok());
+            let nodes = services
+                .iter()
+                .filter_map(|s| s.node.clone())
+                .collect::<Vec<String>>();
+            let tasks = storage.get_tasks_by_deployment(id).unwrap_or(tasks);
+            collect_deployment_logs(storage, &deployment, &tasks, services);
+            let status = storage.set_enddate_for_deployment(id).is_ok();
+            storage
+                .set_state_for_deployment(id, &State::Finished, None)
+                .unwrap_or_default();
+            print_message("stop deployment", status);
+            return record_for_nodes(
+                "stop deployment",
+                &format!("deployment {}", id),
+                &nodes,
+                status,
+            );
         } else {
             eprintln!("No services for this deployment found");
         }
     } else {
         eprintln!("No such deployment found");
     }
+    record(
+        "stop deployment",
+        &format!("deployment {}", id),
+        None,
+        false,
+    )
 }
 
-pub fn list_deployments(all: bool) {
+/// Runs a task which is due when its service stops and records when it started and ended.
+fn run_task_of_service(
+    storage: &dyn Storage,
+    deployment: &mut Deployment,
+    service: &Service,
+    task: &Task,
+) -> bool {
+    let node = get_node_by_id(service.node.as_ref().unwrap(), false).unwrap();
+    if let Some(id) = task.id {
+        storage.set_startdate_for_task(id).unwrap_or_default();
+    }
+    let status = match task.task_type {
+        GetResults => {
+            let mountpoint: Mountpoint = serde_json::from_str(&task.parameters).unwrap();
+            retrieve_local_logs(storage, deployment, service, &node, &mountpoint)
+        }
+        PurgeLocalStorage => clean_local_storage(storage, deployment, service, &node, true),
+        DeleteLocalStorage => clean_local_storage(storage, deployment, service, &node, false),
+        NoOp | StopIfTrue | RunCommand | InjectFault | SnapshotResults => true,
+    };
+    if let Some(id) = task.id {
+        storage.set_outcome_for_task(id, status).unwrap_or_default();
+    }
+    status
+}
+
+pub fn list_deployments(
+    storage: &dyn Storage,
+    all: bool,
+    owner: Option<&str>,
+    state: Option<&State>,
+) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     table.set_titles(Row::new(
@@ -141,36 +273,47 @@ pub fn list_deployments(all: bool) {
             Cell::new("end"),
             Cell::new("owner"),
             Cell::new("#services"),
+            Cell::new("state"),
+            Cell::new("failure"),
+            Cell::new("rerun of"),
         ]
         .to_vec(),
     ));
 
-    for row in get_deployment_rows(all) {
+    for row in get_deployment_rows(storage, all, owner, state) {
         table.add_row(Row::new(row.get_cells()));
     }
 
     table.printstd()
 }
 
-pub fn get_deployment_rows(all: bool) -> Vec<DeploymentRow> {
+pub fn get_deployment_rows(
+    storage: &dyn Storage,
+    all: bool,
+    owner: Option<&str>,
+    state: Option<&State>,
+) -> Vec<DeploymentRow> {
     let mut rows = Vec::new();
-    if let Ok(deployments) = if all {
-        get_deployments()
+    if let Ok(mut deployments) = if all || state.is_some() {
+        storage.get_deployments()
     } else {
-        get_running_deployments()
+        storage.get_running_deployments()
     } {
+        if let Some(owner) = owner {
+            deployments.retain(|d| d.owner.eq(owner));
+        }
+        if let Some(state) = state {
+            deployments.retain(|d| d.state.as_ref().eq(&Some(state)));
+        }
         if deployments.is_empty() {
             return Vec::new();
         }
-        let mut handles: Vec<JoinHandle<()>> = Vec::new();
-        let (tx, rx) = mpsc::channel();
+        let counts = storage.get_service_counts().ok();
         for deployment in deployments {
-            handles.push(get_deployment_line_handle(deployment.clone(), tx.clone()));
-        }
-        for handle in handles {
-            handle.join().unwrap();
-            let row = rx.recv().unwrap();
-            rows.push(row);
+            let services = counts
+                .as_ref()
+                .map(|c| *c.get(&deployment.id.unwrap()).unwrap_or(&0));
+            rows.push(DeploymentRow::new(deployment, services));
         }
     } else {
         eprintln!("Problem with reading deployments from database");
@@ -178,14 +321,110 @@ pub fn get_deployment_rows(all: bool) -> Vec<DeploymentRow> {
     rows
 }
 
-fn get_service_line_handle(service: Service, tx: Sender<Option<ServiceRow>>) -> JoinHandle<()> {
+pub fn migrate_database() -> bool {
+    match migrate() {
+        Ok(applied) => {
+            if applied.is_empty() {
+                print_information("database schema is up to date");
+            }
+            for migration in applied {
+                print_message(
+                    &format!("migration {}: {}", migration.version, migration.description),
+                    true,
+                );
+            }
+            true
+        }
+        Err(e) => {
+            print_message(&e, false);
+            false
+        }
+    }
+}
+
+pub fn print_database_status() {
+    let version = match get_schema_version() {
+        Ok(version) => version,
+        Err(e) => {
+            eprintln!("could not read schema version: {}", e);
+            return;
+        }
+    };
+    println!(
+        "The database schema is at version {}, this cluster-manager knows version {}.",
+        version,
+        get_latest_version()
+    );
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("version"),
+            Cell::new("description"),
+            Cell::new("applied"),
+        ]
+        .to_vec(),
+    ));
+    for migration in get_migrations() {
+        table.add_row(Row::new(
+            [
+                Cell::new(&migration.version.to_string()),
+                Cell::new(migration.description),
+                Cell::new(if migration.version <= version {
+                    "yes"
+                } else {
+                    "no"
+                }),
+            ]
+            .to_vec(),
+        ));
+    }
+    table.printstd()
+}
+
+pub fn list_state_transitions(storage: &dyn Storage, id: i64) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("timestamp"),
+            Cell::new("entity"),
+            Cell::new("from"),
+            Cell::new("to"),
+            Cell::new("reason"),
+        ]
+        .to_vec(),
+    ));
+
+    for row in get_state_transition_rows(storage, id) {
+        table.add_row(Row::new(row.get_cells()));
+    }
+
+    table.printstd()
+}
+
+pub fn get_state_transition_rows(storage: &dyn Storage, id: i64) -> Vec<StateTransitionRow> {
+    match storage.get_state_transitions_of_deployment(id) {
+        Ok(transitions) => transitions
+            .into_iter()
+            .map(StateTransitionRow::new)
+            .collect(),
+        Err(_) => {
+            eprintln!("Problem with reading state transitions from database");
+            Vec::new()
+        }
+    }
+}
+
+fn get_service_line_handle(
+    service: Service,
+    deployment: String,
+    tx: Sender<Option<ServiceRow>>,
+) -> JoinHandle<()> {
     thread::spawn(move || {
         get_node_by_id(&service.clone().node.unwrap(), true).map_or_else(
             || tx.send(None).unwrap(),
             |node| {
-                let deployment = get_deployment_by_id(service.deployment.unwrap(), false)
-                    .unwrap()
-                    .name;
                 let row = ServiceRow::new(&service, node.name, deployment);
                 tx.send(Some(row)).unwrap()
             },
@@ -193,19 +432,8 @@ fn get_service_line_handle(service: Service, tx: Sender<Option<ServiceRow>>) ->
     })
 }
 
-fn get_deployment_line_handle(deployment: Deployment, tx: Sender<DeploymentRow>) -> JoinHandle<()> {
-    thread::spawn(move || {
-        let services = match get_services_by_deployment(deployment.id.unwrap()) {
-            Ok(vec) => Some(vec.len()),
-            Err(_) => None,
-        };
-        let row = DeploymentRow::new(deployment, services);
-        tx.send(row).unwrap()
-    })
-}
-
-pub async fn watch_service_logs_of(id: i64) -> std::io::Result<()> {
-    if let Ok(service) = get_service_by_id(id, true) {
+pub async fn watch_service_logs_of(storage: &dyn Storage, id: i64) -> std::io::Result<()> {
+    if let Ok(service) = storage.get_service_by_id(id, true) {
         let node = get_node_by_id(&service.node.unwrap(), false).unwrap();
         if watch_logs_of(node, true, true, true).await.is_err() {
             eprintln!("{}", format!("{}", "No logs available".red()));
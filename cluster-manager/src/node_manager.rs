@@ -1,15 +1,22 @@
 extern crate clap;
-use crate::config::{get_all_nodes, get_log_sources_of, get_node_by_id};
-use crate::database::get_running_services;
+use crate::audit::record;
+use crate::config::{
+    get_all_nodes, get_log_sources_of, get_node_by_id, is_managed_node, remove_node_definition,
+    save_node_definition,
+};
 use crate::deployer::clean_node;
 use crate::installer::{
     DNSMASQ, DNSMASQ_NODES_CONFIG_FILE, NFS_BASE_DIR, NFS_SERVICE, PING, SCREEN, SERVICE,
     TFTP_BASE_DIR, UMOUNT,
 };
 use crate::logs_manager::gather_logs;
+use crate::manager::stop_service;
+use crate::storage::Storage;
+use chrono::NaiveDateTime;
 use colored::Colorize;
 use prettytable::format;
 use prettytable::{Cell, Row, Table};
+use std::collections::HashSet;
 use std::path::Path;
 use std::process::Command;
 use std::sync::mpsc;
@@ -19,7 +26,10 @@ use std::thread::JoinHandle;
 use std::{fs, io};
 use structs::logsource::LogSourceTypes;
 use structs::node::Node;
+use structs::node_definition::NodeDefinition;
 use structs::node_row::NodeRow;
+use structs::node_state::{NodeState, NodeStateEntry};
+use structs::node_state_transition_row::NodeStateTransitionRow;
 use structs::service::Service;
 use structs::utils::{
     append_to_file, filter_lines_by_substring, get_lines_from_file, print_information,
@@ -28,25 +38,98 @@ use structs::utils::{
 
 const NFS_CONFIGFILE: &str = "/etc/exports";
 
-pub fn add_node(identifier: &str) -> bool {
+pub fn add_node(storage: &dyn Storage, identifier: &str) -> bool {
     if let Some(node) = get_node_by_id(identifier, true) {
         add_nfs(&node);
         add_dnsmasq(&node.ipv4_address, &node);
         add_tftp(&node);
         restart_services();
-        true
+        refresh_node_state(storage, &node);
+        record("add node", identifier, Some(identifier), true)
     } else {
         eprintln!("Add node to configuration first");
-        false
+        record("add node", identifier, Some(identifier), false)
     }
 }
 
-pub fn remove_node(node: &Node) -> bool {
-    stop_node(&node, false, true);
+pub fn remove_node(storage: &dyn Storage, node: &Node) -> bool {
+    stop_node(storage, &node, false, true);
     remove_nfs(&node);
     remove_tftp(&node);
+    storage
+        .set_state_for_node(
+            &node.id,
+            &NodeState::Unknown,
+            Some("removed from the cluster"),
+        )
+        .unwrap_or_default();
     print_information("This node can now safely be removed from the configuration");
-    true
+    record("remove node", &node.id, Some(&node.id), true)
+}
+
+/// Adds a node which is not defined yet and provisions it.
+///
+/// # Errors
+///
+/// Will return `Err` with the problems of the definition
+pub fn define_node(
+    storage: &dyn Storage,
+    id: &str,
+    definition: NodeDefinition,
+) -> Result<bool, Vec<String>> {
+    save_node_definition(id, definition, true)?;
+    Ok(add_node(storage, id))
+}
+
+/// Changes the definition of a node. A node whose name or addresses change is provisioned again,
+/// which is only done while no service runs on it.
+///
+/// # Errors
+///
+/// Will return `Err` with the problems of the definition
+pub fn redefine_node(
+    storage: &dyn Storage,
+    node: &Node,
+    definition: NodeDefinition,
+) -> Result<bool, Vec<String>> {
+    let updated = definition.to_node(&node.id).map_err(|e| vec![e])?;
+    let reprovision = !(node.eq(&updated)
+        && node.ipv4_address.eq(&updated.ipv4_address)
+        && node.pxe == updated.pxe);
+    let busy = storage
+        .get_running_services()
+        .unwrap_or_default()
+        .iter()
+        .any(|s| s.node.as_deref() == Some(node.id.as_str()));
+    if reprovision && busy {
+        return Err(vec![format!("{} runs services, drain it first", node.id)]);
+    }
+    save_node_definition(&node.id, definition, false)?;
+    let status = !reprovision || {
+        remove_nfs(node);
+        remove_tftp(node);
+        remove_dnsmasq(node);
+        add_node(storage, &node.id)
+    };
+    Ok(record("edit node", &node.id, Some(&node.id), status))
+}
+
+/// Removes a node which was added through the API from the cluster and forgets its definition.
+///
+/// # Errors
+///
+/// Will return `Err` if the node is defined in config.yml
+pub fn undefine_node(storage: &dyn Storage, node: &Node) -> Result<bool, String> {
+    if !is_managed_node(&node.id) {
+        return Err(format!(
+            "{} is defined in config.yml, remove it there",
+            node.id
+        ));
+    }
+    let status = remove_node(storage, node);
+    remove_dnsmasq(node);
+    remove_node_definition(&node.id)?;
+    Ok(status)
 }
 
 pub fn restart_services() {
@@ -171,6 +254,21 @@ fn remove_dnsmasq_entry(node: &Node) -> io::Result<()> {
     remove_line_with_substring_from_file(DNSMASQ_NODES_CONFIG_FILE, &line)
 }
 
+pub fn get_dnsmasq_entries(node: &Node) -> Vec<String> {
+    match get_lines_from_file(DNSMASQ_NODES_CONFIG_FILE) {
+        Ok(content) => filter_lines_by_substring(&content, &node.mac_address),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn restore_dnsmasq_entries(node: &Node, entries: &[String]) -> bool {
+    let mut status = remove_dnsmasq_entry(&node).is_ok();
+    for entry in entries {
+        status &= append_to_file(DNSMASQ_NODES_CONFIG_FILE, entry.to_string()).is_ok();
+    }
+    status
+}
+
 pub fn change_hostname(node: &mut Node, hostname: &str) -> bool {
     if let Ok(content) = get_lines_from_file(DNSMASQ_NODES_CONFIG_FILE) {
         let lines = filter_lines_by_substring(&content, &node.mac_address);
@@ -210,7 +308,7 @@ pub fn change_ipv4address(node: &mut Node, ipv4_address: &str) -> bool {
     true
 }
 
-pub fn list_nodes(show_all: bool) {
+pub fn list_nodes(storage: &dyn Storage, show_all: bool) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     table.set_titles(Row::new(
@@ -223,11 +321,14 @@ pub fn list_nodes(show_all: bool) {
             Cell::new("status"),
             Cell::new("hostname"),
             Cell::new("IPv4-address"),
-            Cell::new("usable"),
+            Cell::new("labels"),
+            Cell::new("state"),
+            Cell::new("reason"),
+            Cell::new("expected return"),
         ]
         .to_vec(),
     ));
-    let rows = get_nodes_rows(show_all);
+    let rows = get_nodes_rows(storage, show_all);
     for row in &rows {
         table.add_row(Row::new(row.get_cells()));
     }
@@ -247,6 +348,7 @@ fn get_node_line_handle(
     services: Vec<Service>,
     tx: Sender<Option<NodeRow>>,
     node: Node,
+    state: Option<NodeStateEntry>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         let (hostname, ipv4_address) = match line
@@ -274,7 +376,7 @@ fn get_node_line_handle(
             },
             hostname,
             ipv4_address,
-            verify_node_usability(&node),
+            state,
         );
         if show_all || row.status.unwrap_or(false) {
             tx.send(Some(row)).unwrap();
@@ -295,12 +397,186 @@ pub fn is_up(ipv4_address: &str) -> bool {
         .success()
 }
 
-pub fn stop_node(node: &Node, prune: bool, hard_delete: bool) -> bool {
+/// Brings the states of all configured nodes in line with their services and reservations.
+pub fn refresh_node_states(storage: &dyn Storage) {
+    refresh(storage, &storage.get_all_nodes());
+}
+
+pub fn refresh_node_state(storage: &dyn Storage, node: &Node) {
+    refresh(storage, std::slice::from_ref(node));
+}
+
+fn refresh(storage: &dyn Storage, nodes: &[Node]) {
+    let states = match storage.get_node_states() {
+        Ok(states) => states,
+        Err(_) => return,
+    };
+    let (busy, reserved) = get_busy_and_reserved_nodes(storage);
+    for node in nodes {
+        let current = states.get(&node.id).map(|entry| &entry.state);
+        if current.map_or(false, NodeState::is_held) {
+            continue;
+        }
+        let (state, reason) = get_expected_state(node, current, &busy, &reserved);
+        storage
+            .set_state_for_node(&node.id, &state, Some(reason))
+            .unwrap_or_default();
+    }
+}
+
+fn get_busy_and_reserved_nodes(storage: &dyn Storage) -> (HashSet<String>, HashSet<String>) {
+    let busy = storage
+        .get_running_services()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|s| s.node)
+        .collect();
+    let reserved = storage
+        .get_active_reservations()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|r| r.nodes)
+        .collect();
+    (busy, reserved)
+}
+
+/// Returns the state a node which is neither failed nor in maintenance should be in. Whether a
+/// node without a known state was added to the cluster is read from its directories.
+fn get_expected_state(
+    node: &Node,
+    current: Option<&NodeState>,
+    busy: &HashSet<String>,
+    reserved: &HashSet<String>,
+) -> (NodeState, &'static str) {
+    if busy.contains(&node.id) {
+        (NodeState::Busy, "a service runs on the node")
+    } else if matches!(current, None | Some(NodeState::Unknown)) && !verify_node_usability(node) {
+        (NodeState::Unknown, "the node was not added to the cluster")
+    } else if reserved.contains(&node.id) {
+        (NodeState::Reserved, "the node is reserved")
+    } else {
+        (NodeState::Available, "the node is idle")
+    }
+}
+
+/// Moves a node which is in `held` into the state it would be in otherwise.
+fn release_node(storage: &dyn Storage, node: &Node, held: &NodeState) -> bool {
+    let is_held = storage
+        .get_node_states()
+        .unwrap_or_default()
+        .get(&node.id)
+        .map_or(false, |entry| entry.state.eq(held));
+    is_held && {
+        let (busy, reserved) = get_busy_and_reserved_nodes(storage);
+        let (state, reason) = get_expected_state(node, None, &busy, &reserved);
+        storage
+            .set_state_for_node(&node.id, &state, Some(reason))
+            .unwrap_or(false)
+    }
+}
+
+pub fn clear_suspect_node(storage: &dyn Storage, node: &Node) -> bool {
+    let status = release_node(storage, node, &NodeState::Failed);
+    print_message(&format!("clear suspect state of {}", node.id), status);
+    record("clear suspect node", &node.id, Some(&node.id), status)
+}
+
+/// Puts the node into maintenance, no new services are placed on it but running ones keep running.
+pub fn cordon_node(
+    storage: &dyn Storage,
+    node: &Node,
+    note: Option<&str>,
+    expected_return: Option<&NaiveDateTime>,
+) -> bool {
+    let status = match storage.set_state_for_node(
+        &node.id,
+        &NodeState::Maintenance,
+        Some(note.unwrap_or("cordoned")),
+    ) {
+        Ok(true) => storage
+            .set_expected_return_for_node(&node.id, expected_return)
+            .is_ok(),
+        Ok(false) => {
+            eprintln!("{} already is in maintenance", node.id);
+            false
+        }
+        Err(_) => false,
+    };
+    print_message(&format!("cordon {}", node.id), status);
+    record("cordon node", &node.id, Some(&node.id), status)
+}
+
+/// Changes the note and expected return of a node which already is in maintenance, what is not
+/// given stays as it is.
+fn update_maintenance(
+    storage: &dyn Storage,
+    node: &Node,
+    note: Option<&str>,
+    expected_return: Option<&NaiveDateTime>,
+) -> bool {
+    let noted = match note {
+        Some(note) => storage.set_reason_for_node(&node.id, note).is_ok(),
+        None => true,
+    };
+    let dated = match expected_return {
+        Some(_) => storage
+            .set_expected_return_for_node(&node.id, expected_return)
+            .is_ok(),
+        None => true,
+    };
+    noted && dated
+}
+
+/// Stops the services running on the node and leaves it in maintenance. The node is cordoned
+/// first, so no deployment picks it up while its services stop.
+pub fn drain_node(
+    storage: &dyn Storage,
+    node: &Node,
+    note: Option<&str>,
+    expected_return: Option<&NaiveDateTime>,
+    prune: bool,
+) -> bool {
+    let in_maintenance = storage
+        .get_node_states()
+        .unwrap_or_default()
+        .get(&node.id)
+        .map_or(false, |entry| entry.state == NodeState::Maintenance);
+    let mut status = if in_maintenance {
+        update_maintenance(storage, node, note, expected_return)
+    } else {
+        cordon_node(
+            storage,
+            node,
+            Some(note.unwrap_or("drained")),
+            expected_return,
+        )
+    };
+    if status {
+        for service in storage
+            .get_running_services()
+            .unwrap_or_default()
+            .iter()
+            .filter(|s| s.node.as_deref() == Some(node.id.as_str()))
+        {
+            status &= stop_service(storage, service.id.unwrap(), prune);
+        }
+    }
+    print_message(&format!("drain {}", node.id), status);
+    record("drain node", &node.id, Some(&node.id), status)
+}
+
+pub fn uncordon_node(storage: &dyn Storage, node: &Node) -> bool {
+    let status = release_node(storage, node, &NodeState::Maintenance);
+    print_message(&format!("uncordon {}", node.id), status);
+    record("uncordon node", &node.id, Some(&node.id), status)
+}
+
+pub fn stop_node(storage: &dyn Storage, node: &Node, prune: bool, hard_delete: bool) -> bool {
     print_information("stopping logging from serial inputs");
     close_screens_for_serial_logging(&node);
     gather_logs(node);
     if prune {
-        print_message("clean node", clean_node(node));
+        print_message("clean node", clean_node(storage, node));
     }
     print_message(
         "unmount tftpboot directory",
@@ -320,7 +596,7 @@ pub fn stop_node(node: &Node, prune: bool, hard_delete: bool) -> bool {
         .is_ok(),
     );
     restart_services();
-    true
+    record("stop node", &node.id, Some(&node.id), true)
 }
 
 pub fn remove_nfsroot(node_mac: &str, hard_delete: bool) -> io::Result<()> {
@@ -331,7 +607,7 @@ pub fn remove_nfsroot(node_mac: &str, hard_delete: bool) -> io::Result<()> {
     result
 }
 
-fn close_screens_for_serial_logging(node: &Node) {
+pub fn close_screens_for_serial_logging(node: &Node) {
     for (index, _serial_device) in get_log_sources_of(&node)
         .iter()
         .filter(|l| l.source.eq(&LogSourceTypes::SERIAL))
@@ -363,8 +639,9 @@ pub fn verify_node_usability(node: &Node) -> bool {
     nfs_path && tftp_path && dnsmasq_entry
 }
 
-pub fn get_nodes_rows(show_all: bool) -> Vec<NodeRow> {
-    let services = match get_running_services() {
+pub fn get_nodes_rows(storage: &dyn Storage, show_all: bool) -> Vec<NodeRow> {
+    refresh_node_states(storage);
+    let services = match storage.get_running_services() {
         Ok(s) => s,
         Err(_) => Vec::new(),
     };
@@ -391,6 +668,7 @@ pub fn get_nodes_rows(show_all: bool) -> Vec<NodeRow> {
             .collect::<Vec<(String, String, String)>>(),
         Err(_) => Vec::new(),
     };
+    let states = storage.get_node_states().unwrap_or_default();
     let mut rows = Vec::new();
     if let Ok(nodes) = get_all_nodes() {
         let mut handles: Vec<JoinHandle<()>> = Vec::new();
@@ -402,6 +680,7 @@ pub fn get_nodes_rows(show_all: bool) -> Vec<NodeRow> {
                 services.clone(),
                 tx.clone(),
                 n.clone(),
+                states.get(&n.id).cloned(),
             ));
         }
         for handle in handles {
@@ -413,3 +692,105 @@ pub fn get_nodes_rows(show_all: bool) -> Vec<NodeRow> {
     }
     rows
 }
+
+pub fn list_node_state_transitions(storage: &dyn Storage, node: &str) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("timestamp"),
+            Cell::new("from"),
+            Cell::new("to"),
+            Cell::new("reason"),
+        ]
+        .to_vec(),
+    ));
+
+    for row in get_node_state_transition_rows(storage, node) {
+        table.add_row(Row::new(row.get_cells()));
+    }
+
+    table.printstd()
+}
+
+pub fn get_node_state_transition_rows(
+    storage: &dyn Storage,
+    node: &str,
+) -> Vec<NodeStateTransitionRow> {
+    match storage.get_state_transitions_of_node(node) {
+        Ok(transitions) => transitions
+            .into_iter()
+            .map(NodeStateTransitionRow::new)
+            .collect(),
+        Err(_) => {
+            eprintln!("Problem with reading node states from database");
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::{get_test_node, MemoryStorage};
+    use chrono::{Duration, Utc};
+    use structs::architecture::Architecture::ARM64;
+    use structs::reservation::Reservation;
+
+    fn get_state(storage: &dyn Storage, node: &str) -> NodeState {
+        storage.get_node_states().unwrap()[node].state.clone()
+    }
+
+    #[test]
+    fn nodes_follow_their_services_and_reservations() {
+        let nodes = vec![get_test_node("pi-1", ARM64), get_test_node("pi-2", ARM64)];
+        let storage = MemoryStorage::with_nodes(nodes.clone());
+        let mut service = Service::new("web", "raspbian", "web");
+        service.node = Some(String::from("pi-1"));
+        let service = storage.insert_service(&service).unwrap();
+        let now = Utc::now().naive_utc();
+        storage
+            .insert_reservation(&Reservation::new(
+                "alice",
+                vec![String::from("pi-2")],
+                now - Duration::hours(1),
+                now + Duration::hours(1),
+            ))
+            .unwrap();
+        refresh(&storage, &nodes);
+        assert_eq!(get_state(&storage, "pi-1"), NodeState::Busy);
+        assert_eq!(get_state(&storage, "pi-2"), NodeState::Reserved);
+        storage.set_enddate_for_service(service).unwrap();
+        refresh(&storage, &nodes);
+        assert_eq!(get_state(&storage, "pi-1"), NodeState::Available);
+        let transitions = storage.get_state_transitions_of_node("pi-1").unwrap();
+        assert_eq!(
+            transitions
+                .iter()
+                .map(|t| t.to.clone())
+                .collect::<Vec<NodeState>>(),
+            vec![NodeState::Busy, NodeState::Available]
+        );
+    }
+
+    #[test]
+    fn held_nodes_keep_their_state() {
+        let nodes = vec![get_test_node("pi-1", ARM64)];
+        let storage = MemoryStorage::with_nodes(nodes.clone());
+        assert!(storage
+            .set_state_for_node("pi-1", &NodeState::Maintenance, Some("new fan"))
+            .unwrap());
+        let mut service = Service::new("web", "raspbian", "web");
+        service.node = Some(String::from("pi-1"));
+        storage.insert_service(&service).unwrap();
+        refresh(&storage, &nodes);
+        assert_eq!(get_state(&storage, "pi-1"), NodeState::Maintenance);
+        assert!(!storage
+            .set_state_for_node("pi-1", &NodeState::Maintenance, Some("new fan"))
+            .unwrap());
+        assert_eq!(
+            storage.get_state_transitions_of_node("pi-1").unwrap().len(),
+            1
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::audit::record;
 use crate::config::get_power_commands_of;
 use structs::node::Node;
 use structs::power_action::Type;
@@ -14,16 +15,18 @@ fn execute(powerset: PowerActionSet, action: &Type) -> bool {
     }
 }
 
-#[allow(dead_code)]
 pub fn power_off(node: &Node) -> bool {
-    execute(get_power_commands_of(node), &OFF)
+    let status = execute(get_power_commands_of(node), &OFF);
+    record("power off", &node.id, Some(&node.id), status)
 }
 
 #[allow(dead_code)]
 pub fn power_on(node: &Node) -> bool {
-    execute(get_power_commands_of(node), &ON)
+    let status = execute(get_power_commands_of(node), &ON);
+    record("power on", &node.id, Some(&node.id), status)
 }
 
 pub fn reboot(node: &Node) -> bool {
-    execute(get_power_commands_of(node), &REBOOT)
+    let status = execute(get_power_commands_of(node), &REBOOT);
+    record("reboot", &node.id, Some(&node.id), status)
 }
@@ -0,0 +1,64 @@
+use crate::config::get_node_by_id;
+use crate::deployer::execute_command_over_ssh_with_output;
+use crate::installer::RESULTS_DIR;
+use crate::storage::Storage;
+use std::fs;
+use structs::node::Node;
+use structs::remote_command::RemoteCommand;
+use structs::service::Service;
+use structs::task::Task;
+use structs::utils::print_message;
+
+#[must_use]
+pub fn get_remote_command(task: &Task) -> Option<RemoteCommand> {
+    serde_json::from_str::<RemoteCommand>(&task.parameters).ok()
+}
+
+/// Runs the command of the task on every replica of its service and records when it started and
+/// ended.
+pub fn run_command_task(storage: &dyn Storage, task: &Task, services: &[Service]) -> bool {
+    let command = match get_remote_command(task) {
+        Some(command) => command,
+        None => return false,
+    };
+    let id = task.id.unwrap();
+    storage.set_startdate_for_task(id).unwrap_or_default();
+    let mut status = true;
+    for service in services.iter().filter(|s| s.name.eq(&command.service)) {
+        if let Some(node) = service.node.as_ref().and_then(|n| get_node_by_id(n, false)) {
+            let executed = run_command(id, &node, &command.command);
+            print_message(&format!("run {} on {}", command.command, node.id), executed);
+            status &= executed;
+        }
+    }
+    storage.set_outcome_for_task(id, status).unwrap_or_default();
+    status
+}
+
+/// Stores stdout, stderr and the exit status next to the logs of the node, so they end up in the
+/// results of the deployment.
+fn run_command(task: i64, node: &Node, command: &str) -> bool {
+    let directory = format!("{}/{}/commands", RESULTS_DIR, node.id);
+    if fs::create_dir_all(&directory).is_err() {
+        return false;
+    }
+    let prefix = format!("{}/{}", directory, task);
+    let status = match execute_command_over_ssh_with_output(node, command) {
+        Ok(output) => {
+            fs::write(format!("{}.stdout", prefix), &output.stdout).unwrap_or_default();
+            fs::write(format!("{}.stderr", prefix), &output.stderr).unwrap_or_default();
+            output.status.code()
+        }
+        Err(e) => {
+            fs::write(format!("{}.stderr", prefix), e.to_string()).unwrap_or_default();
+            None
+        }
+    };
+    let exit = status.map_or_else(|| String::from("none"), |code| code.to_string());
+    fs::write(
+        format!("{}.exit", prefix),
+        format!("{}\n{}\n", command, exit),
+    )
+    .unwrap_or_default();
+    status == Some(0)
+}
@@ -0,0 +1,237 @@
+use crate::audit::record;
+use crate::config::{get_node_by_id, get_nodes};
+use crate::storage::Storage;
+use chrono::{NaiveDateTime, Utc};
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+use structs::reservation::Reservation;
+use structs::reservation_request::ReservationRequest;
+use structs::reservation_row::ReservationRow;
+use structs::user::User;
+use structs::utils::{print_information, print_message, vec_to_string};
+
+pub fn list_reservations(storage: &dyn Storage, all: bool) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [
+            Cell::new("id"),
+            Cell::new("owner"),
+            Cell::new("nodes"),
+            Cell::new("start"),
+            Cell::new("end"),
+            Cell::new("released"),
+        ]
+        .to_vec(),
+    ));
+
+    for row in get_reservation_rows(storage, all) {
+        table.add_row(Row::new(row.get_cells()));
+    }
+
+    table.printstd()
+}
+
+pub fn get_reservation_rows(storage: &dyn Storage, all: bool) -> Vec<ReservationRow> {
+    match if all {
+        storage.get_reservations()
+    } else {
+        storage.get_upcoming_reservations()
+    } {
+        Ok(reservations) => reservations.into_iter().map(ReservationRow::new).collect(),
+        Err(_) => {
+            eprintln!("Problem with reading reservations from database");
+            Vec::new()
+        }
+    }
+}
+
+pub fn create_reservation(storage: &dyn Storage, request: &ReservationRequest) -> bool {
+    match reserve(storage, request) {
+        Ok(reservation) => {
+            print_information(&format!(
+                "reserved {} for {} until {}",
+                vec_to_string(&reservation.nodes, false),
+                reservation.owner,
+                reservation.end.format("%Y-%m-%d %H:%M")
+            ));
+            print_message("add reservation", true);
+            true
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            print_message("add reservation", false);
+            false
+        }
+    }
+}
+
+pub fn reserve(storage: &dyn Storage, request: &ReservationRequest) -> Result<Reservation, String> {
+    let result = reserve_nodes(storage, request);
+    let target = match &result {
+        Ok(reservation) => format!("reservation {}", reservation.id.unwrap()),
+        Err(_) => String::from("reservation"),
+    };
+    record("reserve nodes", &target, None, result.is_ok());
+    result
+}
+
+fn reserve_nodes(
+    storage: &dyn Storage,
+    request: &ReservationRequest,
+) -> Result<Reservation, String> {
+    if request.end.le(&request.start) {
+        return Err(String::from("a reservation has to end after it starts"));
+    }
+    let conflicting = match storage.get_overlapping_reservations(&request.start, &request.end) {
+        Ok(reservations) => reservations,
+        Err(_) => {
+            return Err(String::from(
+                "Problem with reading reservations from database",
+            ))
+        }
+    };
+    let is_reserved = |id: &str| conflicting.iter().any(|r| r.contains_node(id));
+    let mut nodes = Vec::new();
+    if let Some(count) = request.count {
+        let busy = if request.start.le(&Utc::now().naive_utc()) {
+            storage
+                .get_running_services()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|s| s.node)
+                .collect::<Vec<String>>()
+        } else {
+            Vec::new()
+        };
+        let candidates = get_nodes()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|n| match &request.architecture {
+                Some(architecture) => n.architecture.eq(architecture),
+                None => true,
+            })
+            .filter(|n| !is_reserved(&n.id) && !busy.contains(&n.id))
+            .map(|n| n.id)
+            .collect::<Vec<String>>();
+        if candidates.len() < count {
+            return Err(format!(
+                "only {} matching nodes are free during this time window",
+                candidates.len()
+            ));
+        }
+        nodes = candidates.into_iter().take(count).collect();
+    } else {
+        for id in &request.nodes {
+            if get_node_by_id(id, false).is_none() {
+                return Err(format!("{} is not a usable node", id));
+            }
+            if is_reserved(id) {
+                return Err(format!(
+                    "{} is already reserved during this time window",
+                    id
+                ));
+            }
+            nodes.push(id.to_string());
+        }
+    }
+    if nodes.is_empty() {
+        return Err(String::from("please provide nodes or a count to reserve"));
+    }
+    let mut reservation = Reservation::new(&request.owner, nodes, request.start, request.end);
+    match storage.insert_reservation(&reservation) {
+        Ok(Some(id)) => {
+            reservation.id = Some(id);
+            Ok(reservation)
+        }
+        Ok(None) => Err(format!(
+            "{} got reserved in the meantime, please try again",
+            vec_to_string(&reservation.nodes, false)
+        )),
+        Err(_) => Err(String::from("Problem with writing reservation to database")),
+    }
+}
+
+pub fn extend_reservation(
+    storage: &dyn Storage,
+    id: i64,
+    user: &User,
+    end: &NaiveDateTime,
+) -> Result<(), String> {
+    let result = extend_owned_reservation(storage, id, user, end);
+    record(
+        "extend reservation",
+        &format!("reservation {}", id),
+        None,
+        result.is_ok(),
+    );
+    result
+}
+
+fn extend_owned_reservation(
+    storage: &dyn Storage,
+    id: i64,
+    user: &User,
+    end: &NaiveDateTime,
+) -> Result<(), String> {
+    let reservation = get_owned_reservation(storage, id, user)?;
+    if end.le(&reservation.end) {
+        return Err(String::from("a reservation can only be extended"));
+    }
+    let conflicting = storage
+        .get_overlapping_reservations(&reservation.end, end)
+        .map_err(|_| String::from("Problem with reading reservations from database"))?;
+    for node in &reservation.nodes {
+        if conflicting
+            .iter()
+            .any(|r| r.id.ne(&reservation.id) && r.contains_node(node))
+        {
+            return Err(format!(
+                "{} is already reserved after this reservation",
+                node
+            ));
+        }
+    }
+    storage
+        .set_enddate_for_reservation(id, end)
+        .map(|_| ())
+        .map_err(|_| String::from("Problem with writing reservation to database"))
+}
+
+pub fn release_reservation(storage: &dyn Storage, id: i64, user: &User) -> Result<(), String> {
+    let result = get_owned_reservation(storage, id, user).and_then(|_| {
+        storage
+            .set_released_for_reservation(id)
+            .map(|_| ())
+            .map_err(|_| String::from("Problem with writing reservation to database"))
+    });
+    record(
+        "release reservation",
+        &format!("reservation {}", id),
+        None,
+        result.is_ok(),
+    );
+    result
+}
+
+fn get_owned_reservation(
+    storage: &dyn Storage,
+    id: i64,
+    user: &User,
+) -> Result<Reservation, String> {
+    match storage.get_reservation_by_id(id) {
+        Ok(reservation) => {
+            if !user.may_manage(&reservation.owner) {
+                return Err(format!(
+                    "reservation {} belongs to {}",
+                    id, reservation.owner
+                ));
+            }
+            if reservation.released.is_some() {
+                return Err(format!("reservation {} was already released", id));
+            }
+            Ok(reservation)
+        }
+        Err(_) => Err(String::from("No such reservation found")),
+    }
+}
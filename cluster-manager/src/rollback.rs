@@ -0,0 +1,167 @@
+use crate::deployer::mount_tftpboot;
+use crate::installer::{NFS_BASE_DIR, TFTP_BASE_DIR};
+use crate::node_manager::{
+    close_screens_for_serial_logging, get_dnsmasq_entries, refresh_node_state,
+    restore_dnsmasq_entries, umount_tftp_directory,
+};
+use crate::storage::Storage;
+use std::fs;
+use std::path::Path;
+use structs::node::Node;
+use structs::state::State;
+use structs::utils::print_message;
+
+/// A change to the cluster made while deploying a service which can be undone.
+pub enum SideEffect {
+    /// Without a backup the nfsroot did not exist before and is removed again.
+    Nfsroot {
+        node: Node,
+        backup: Option<String>,
+    },
+    PxeFile {
+        path: String,
+        previous: Option<String>,
+    },
+    Dnsmasq {
+        node: Node,
+        previous: Vec<String>,
+    },
+    Screens {
+        node: Node,
+    },
+    ServiceRow {
+        id: i64,
+        node: Node,
+    },
+}
+
+/// Side effects of a deployment in the order they happened.
+#[derive(Default)]
+pub struct Rollback {
+    effects: Vec<SideEffect>,
+}
+
+impl Rollback {
+    pub fn record(&mut self, effect: SideEffect) {
+        self.effects.push(effect);
+    }
+
+    /// Moves the current nfsroot of the node aside and leaves an empty one to deploy into, a node
+    /// without an nfsroot gets one which is removed again on undo.
+    pub fn backup_nfsroot(&mut self, node: &Node) -> bool {
+        let nfsroot = get_nfsroot(node);
+        if !Path::new(&nfsroot).exists() {
+            if fs::create_dir_all(&nfsroot).is_err() {
+                return false;
+            }
+            self.record(SideEffect::Nfsroot {
+                node: node.clone(),
+                backup: None,
+            });
+            return true;
+        }
+        let backup = format!("{}/.{}.rollback", NFS_BASE_DIR, node.tftp_prefix);
+        if Path::new(&backup).exists() && fs::remove_dir_all(&backup).is_err() {
+            return false;
+        }
+        if fs::rename(&nfsroot, &backup).is_err() || fs::create_dir_all(&nfsroot).is_err() {
+            return false;
+        }
+        self.record(SideEffect::Nfsroot {
+            node: node.clone(),
+            backup: Some(backup),
+        });
+        true
+    }
+
+    pub fn backup_pxe_file(&mut self, node: &Node) {
+        let path = format!(
+            "{}/pxelinux.cfg/01-{}",
+            TFTP_BASE_DIR,
+            node.mac_address.replace(":", "-")
+        );
+        let previous = fs::read_to_string(&path).ok();
+        self.record(SideEffect::PxeFile { path, previous });
+    }
+
+    pub fn backup_dnsmasq(&mut self, node: &Node) {
+        self.record(SideEffect::Dnsmasq {
+            node: node.clone(),
+            previous: get_dnsmasq_entries(node),
+        });
+    }
+
+    /// Keeps all changes and throws away the backups.
+    pub fn commit(self) {
+        for effect in self.effects {
+            if let SideEffect::Nfsroot {
+                backup: Some(backup),
+                ..
+            } = effect
+            {
+                print_message("remove nfsroot backup", fs::remove_dir_all(&backup).is_ok());
+            }
+        }
+    }
+
+    /// Undoes all changes, the most recent first.
+    pub fn undo(self, storage: &dyn Storage) -> bool {
+        let mut status = true;
+        for effect in self.effects.into_iter().rev() {
+            let undone = match &effect {
+                SideEffect::Nfsroot { node, backup } => {
+                    umount_tftp_directory(&node.tftp_prefix);
+                    let nfsroot = get_nfsroot(node);
+                    let removed = fs::remove_dir_all(&nfsroot).is_ok();
+                    match backup {
+                        Some(backup) => {
+                            let restored = removed && fs::rename(backup, &nfsroot).is_ok();
+                            if restored && Path::new(&format!("{}/boot", nfsroot)).exists() {
+                                mount_tftpboot(Vec::new(), &node.tftp_prefix)
+                            } else {
+                                restored
+                            }
+                        }
+                        None => removed,
+                    }
+                }
+                SideEffect::PxeFile { path, previous } => match previous {
+                    Some(content) => fs::write(path, content).is_ok(),
+                    None => !Path::new(path).exists() || fs::remove_file(path).is_ok(),
+                },
+                SideEffect::Dnsmasq { node, previous } => restore_dnsmasq_entries(node, previous),
+                SideEffect::Screens { node } => {
+                    close_screens_for_serial_logging(node);
+                    true
+                }
+                SideEffect::ServiceRow { id, node } => {
+                    let ended = storage
+                        .set_state_for_service(*id, &State::Failed, Some("rolled back"))
+                        .is_ok()
+                        && storage.set_enddate_for_service(*id).is_ok();
+                    refresh_node_state(storage, node);
+                    ended
+                }
+            };
+            print_message(&format!("roll back {}", effect.describe()), undone);
+            status &= undone;
+        }
+        status
+    }
+}
+
+impl SideEffect {
+    fn describe(&self) -> String {
+        match self {
+            SideEffect::Nfsroot { node, .. } => format!("nfsroot of {}", node.id),
+            SideEffect::PxeFile { path, .. } => format!("pxefile {}", path),
+            SideEffect::Dnsmasq { node, .. } => format!("dnsmasq entry of {}", node.id),
+            SideEffect::Screens { node } => format!("serial logging of {}", node.id),
+            SideEffect::ServiceRow { id, .. } => format!("service {}", id),
+        }
+    }
+}
+
+fn get_nfsroot(node: &Node) -> String {
+    format!("{}/{}", NFS_BASE_DIR, node.tftp_prefix)
+}
@@ -0,0 +1,228 @@
+use structs::node::Node;
+use structs::placement::Placement;
+use structs::service::Service;
+
+pub struct PlacementContext<'a> {
+    /// replicas already assigned within the current deployment
+    pub placed: &'a [(Service, Node)],
+    /// services that are currently running on the cluster
+    pub running: &'a [(Service, Node)],
+}
+
+pub trait Scheduler {
+    /// Scores `node` for the next replica of `service`, higher is better and `None` rules it out.
+    fn score(&self, service: &Service, node: &Node, context: &PlacementContext) -> Option<i64>;
+    fn explain(&self, service: &Service, node: &Node, context: &PlacementContext) -> String;
+}
+
+pub struct DefaultScheduler {}
+
+pub struct SpreadScheduler {
+    label: String,
+}
+
+pub struct AntiAffinityScheduler {
+    service: String,
+    label: String,
+}
+
+pub struct PackScheduler {
+    label: String,
+}
+
+#[must_use]
+pub fn get_scheduler(placement: &Option<Placement>) -> Box<dyn Scheduler> {
+    match placement {
+        None => Box::new(DefaultScheduler {}),
+        Some(Placement::Spread { label }) => Box::new(SpreadScheduler {
+            label: label.clone(),
+        }),
+        Some(Placement::AntiAffinity { service, label }) => Box::new(AntiAffinityScheduler {
+            service: service.clone(),
+            label: label.clone(),
+        }),
+        Some(Placement::Pack { label }) => Box::new(PackScheduler {
+            label: label.clone(),
+        }),
+    }
+}
+
+/// Picks the candidate with the best score of the service's scheduler, soft preferences break
+/// ties and the earliest candidate wins if those are equal as well.
+pub fn schedule(
+    service: &Service,
+    candidates: &[Node],
+    context: &PlacementContext,
+) -> Option<(usize, String)> {
+    let scheduler = get_scheduler(&service.placement);
+    let mut best: Option<(usize, (i64, usize))> = None;
+    for (index, node) in candidates.iter().enumerate() {
+        if let Some(score) = scheduler.score(service, node, context) {
+            let key = (score, node.count_matching_labels(&service.preferences));
+            match best {
+                Some((_, best_key)) if best_key >= key => {}
+                _ => best = Some((index, key)),
+            }
+        }
+    }
+    best.map(|(index, (_, preferences))| {
+        let node = &candidates[index];
+        let mut rationale = format!(
+            "{} on {}: {}",
+            service.name,
+            node.id,
+            scheduler.explain(service, node, context)
+        );
+        if !service.preferences.is_empty() {
+            rationale.push_str(&format!(
+                ", matches {} of {} preferences",
+                preferences,
+                service.preferences.len()
+            ));
+        }
+        (index, rationale)
+    })
+}
+
+/// Counts the replicas of `name` in the domain of `node`, a node without the label is a domain of
+/// its own.
+fn count_in_domain(name: &str, label: &str, node: &Node, services: &[(Service, Node)]) -> usize {
+    let domain = node.labels.get(label);
+    services
+        .iter()
+        .filter(|(s, n)| {
+            s.name.eq(name)
+                && n.labels.get(label).eq(&domain)
+                && (domain.is_some() || n.id.eq(&node.id))
+        })
+        .count()
+}
+
+fn describe_domain(label: &str, node: &Node) -> String {
+    match node.labels.get(label) {
+        Some(value) => format!("{}={}", label, value),
+        None => format!("{} without a {} label", node.id, label),
+    }
+}
+
+impl Scheduler for DefaultScheduler {
+    fn score(&self, _service: &Service, _node: &Node, _context: &PlacementContext) -> Option<i64> {
+        Some(0)
+    }
+
+    fn explain(&self, _service: &Service, _node: &Node, _context: &PlacementContext) -> String {
+        String::from("first idle node matching the requirements")
+    }
+}
+
+impl Scheduler for SpreadScheduler {
+    fn score(&self, service: &Service, node: &Node, context: &PlacementContext) -> Option<i64> {
+        let count = count_in_domain(&service.name, &self.label, node, context.placed);
+        Some(-(count as i64))
+    }
+
+    fn explain(&self, service: &Service, node: &Node, context: &PlacementContext) -> String {
+        format!(
+            "spread over {}, {} held {} replicas before",
+            self.label,
+            describe_domain(&self.label, node),
+            count_in_domain(&service.name, &self.label, node, context.placed)
+        )
+    }
+}
+
+impl Scheduler for AntiAffinityScheduler {
+    fn score(&self, _service: &Service, node: &Node, context: &PlacementContext) -> Option<i64> {
+        let count = count_in_domain(&self.service, &self.label, node, context.placed)
+            + count_in_domain(&self.service, &self.label, node, context.running);
+        if count > 0 {
+            None
+        } else {
+            Some(0)
+        }
+    }
+
+    fn explain(&self, _service: &Service, node: &Node, _context: &PlacementContext) -> String {
+        format!(
+            "anti-affinity with {} over {}, {} holds none of its replicas",
+            self.service,
+            self.label,
+            describe_domain(&self.label, node)
+        )
+    }
+}
+
+impl Scheduler for PackScheduler {
+    fn score(&self, service: &Service, node: &Node, context: &PlacementContext) -> Option<i64> {
+        let count = count_in_domain(&service.name, &self.label, node, context.placed);
+        Some(count as i64)
+    }
+
+    fn explain(&self, service: &Service, node: &Node, context: &PlacementContext) -> String {
+        format!(
+            "pack into {}, {} held {} replicas before",
+            self.label,
+            describe_domain(&self.label, node),
+            count_in_domain(&service.name, &self.label, node, context.placed)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::get_test_node;
+    use structs::architecture::Architecture::ARM64;
+
+    fn get_labeled_node(id: &str, zone: Option<&str>) -> Node {
+        let mut node = get_test_node(id, ARM64);
+        if let Some(zone) = zone {
+            node.labels.insert(String::from("zone"), String::from(zone));
+        }
+        node
+    }
+
+    fn score_anti_affinity(node: &Node, running: &[(Service, Node)]) -> Option<i64> {
+        let scheduler = AntiAffinityScheduler {
+            service: String::from("db"),
+            label: String::from("zone"),
+        };
+        let context = PlacementContext {
+            placed: &[],
+            running,
+        };
+        scheduler.score(&Service::new("web", "raspbian", "web"), node, &context)
+    }
+
+    #[test]
+    fn anti_affinity_rules_out_the_domain_of_the_other_service() {
+        let running = vec![(
+            Service::new("db", "raspbian", "db"),
+            get_labeled_node("pi-1", Some("a")),
+        )];
+        assert_eq!(
+            score_anti_affinity(&get_labeled_node("pi-2", Some("a")), &running),
+            None
+        );
+        assert_eq!(
+            score_anti_affinity(&get_labeled_node("pi-3", Some("b")), &running),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn anti_affinity_treats_unlabeled_nodes_as_their_own_domain() {
+        let running = vec![(
+            Service::new("db", "raspbian", "db"),
+            get_labeled_node("pi-1", None),
+        )];
+        assert_eq!(
+            score_anti_affinity(&get_labeled_node("pi-1", None), &running),
+            None
+        );
+        assert_eq!(
+            score_anti_affinity(&get_labeled_node("pi-2", None), &running),
+            Some(0)
+        );
+    }
+}
@@ -1,61 +1,279 @@
-use crate::config::get_node_by_id;
-use crate::deployer::{deploy, deploy_single_image};
+use crate::audit::{as_actor, get_audit_rows, record};
+use crate::authentication::AuthenticatedUser;
+use crate::config::{get_node_by_id, get_node_definition, reload_config};
+use crate::database::{fail_unfinished_jobs, get_job_by_id};
+use crate::deployer::{plan, prepare_rerun};
 use crate::installer::{MOVE, OS_IMAGES_DIR, RESULTS_DIR};
-use crate::manager::{get_deployment_rows, get_images_rows, get_service_rows};
-use crate::node_manager::get_nodes_rows;
+use crate::job_manager::start_deployment_job;
+use crate::manager;
+use crate::manager::{
+    get_deployment_rows, get_images_rows, get_owner_of_deployment, get_owner_of_service,
+    get_service_rows, get_state_transition_rows,
+};
+use crate::node_manager::{
+    define_node, get_node_state_transition_rows, get_nodes_rows, redefine_node, undefine_node,
+};
+use crate::reservation_manager;
+use crate::reservation_manager::{get_reservation_rows, reserve};
+use crate::storage::SqliteStorage;
 use crate::watcher::watch;
 use actix_multipart::Multipart;
 use actix_web::body::Body;
+use actix_web::error::ErrorInternalServerError;
 use actix_web::http::StatusCode;
 use actix_web::middleware::Logger;
-use actix_web::{get, post, put, web, App, Error, HttpResponse, HttpServer, Responder};
+use actix_web::{delete, get, patch, post, put, web, App, Error, HttpResponse, HttpServer};
+use chrono::NaiveDateTime;
 use env_logger::Env;
 use futures::StreamExt;
+use serde::Deserialize;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
 use std::process::Command;
+use std::sync::Arc;
+use structs::audit_entry::AuditFilter;
 use structs::deployment::Deployment;
 use structs::node::Node;
+use structs::node_definition::NodeDefinition;
+use structs::rerun_request::RerunRequest;
+use structs::reservation_request::ReservationRequest;
+use structs::state::State;
+use structs::user::User;
 use structs::utils::sha256sum_matches;
+use tokio::signal::unix::{signal, SignalKind};
+
+#[derive(Deserialize)]
+struct StateFilter {
+    state: Option<String>,
+}
+
+/// Only steps with a larger id are returned, so polling clients receive each step once.
+#[derive(Deserialize)]
+struct StepFilter {
+    after: Option<i64>,
+}
+
+/// Runs work which blocks, e.g. database queries, on the blocking thread pool so the executor keeps
+/// serving other requests.
+pub async fn blocking<F, R>(work: F) -> Result<R, Error>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    web::block(move || Ok::<R, ()>(work()))
+        .await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))
+}
+
+fn get_owner_filter(user: &User) -> Option<&str> {
+    if user.admin {
+        None
+    } else {
+        Some(user.name.as_str())
+    }
+}
+
+fn get_state_filter(filter: &StateFilter) -> Result<Option<State>, &'static str> {
+    match &filter.state {
+        Some(name) => State::parse(name).map(Some),
+        None => Ok(None),
+    }
+}
 
 #[get("/service/list/{active}/{group}")]
-async fn list_services(web::Path((all, group)): web::Path<(bool, bool)>) -> impl Responder {
-    let results = get_service_rows(all, group);
-    serde_json::to_string(&results).unwrap()
+async fn list_services(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path((all, group)): web::Path<(bool, bool)>,
+    filter: web::Query<StateFilter>,
+) -> Result<HttpResponse, Error> {
+    match get_state_filter(&filter) {
+        Ok(state) => {
+            let rows = blocking(move || {
+                get_service_rows(
+                    &SqliteStorage,
+                    all,
+                    group,
+                    get_owner_filter(&user),
+                    state.as_ref(),
+                )
+            })
+            .await?;
+            Ok(HttpResponse::Ok().json(&rows))
+        }
+        Err(message) => Ok(HttpResponse::BadRequest().body(message)),
+    }
+}
+
+#[put("/service/stop/{id}/{prune}")]
+async fn stop_service(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path((id, prune)): web::Path<(i64, bool)>,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || get_owner_of_service(&SqliteStorage, id)).await? {
+        Some(owner) => {
+            if !user.may_manage(&owner) {
+                return Ok(HttpResponse::Forbidden().body(Body::None));
+            }
+            let stopped = blocking(move || {
+                as_actor(&user.name, || {
+                    manager::stop_service(&SqliteStorage, id, prune)
+                })
+            })
+            .await?;
+            Ok(HttpResponse::Ok().json(&stopped))
+        }
+        None => Ok(HttpResponse::NotFound().body(Body::None)),
+    }
 }
 
 #[get("/node/list/{all}")]
-async fn list_nodes(web::Path(all): web::Path<bool>) -> impl Responder {
-    let results = get_nodes_rows(all);
-    serde_json::to_string(&results).unwrap()
+async fn list_nodes(
+    _user: AuthenticatedUser,
+    web::Path(all): web::Path<bool>,
+) -> Result<HttpResponse, Error> {
+    let results = blocking(move || get_nodes_rows(&SqliteStorage, all)).await?;
+    Ok(HttpResponse::Ok().json(&results))
 }
 
 #[get("/node/get/{id}/{all}")]
-async fn get_node(web::Path((id, all)): web::Path<(String, bool)>) -> HttpResponse {
-    match get_node_by_id(&id, all) {
-        Some(node) => HttpResponse::new(StatusCode::OK)
-            .set_body(Body::from(serde_json::to_string(&node).unwrap())),
-        None => HttpResponse::new(StatusCode::NOT_FOUND).set_body(Body::Empty),
+async fn get_node(
+    _user: AuthenticatedUser,
+    web::Path((id, all)): web::Path<(String, bool)>,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || get_node_by_id(&id, all)).await? {
+        Some(node) => Ok(HttpResponse::new(StatusCode::OK)
+            .set_body(Body::from(serde_json::to_string(&node).unwrap()))),
+        None => Ok(HttpResponse::new(StatusCode::NOT_FOUND).set_body(Body::Empty)),
+    }
+}
+
+#[get("/node/history/{id}")]
+async fn get_node_history(
+    _user: AuthenticatedUser,
+    web::Path(id): web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let rows = blocking(move || get_node_state_transition_rows(&SqliteStorage, &id)).await?;
+    Ok(HttpResponse::Ok().json(&rows))
+}
+
+#[post("/node")]
+async fn add_node(
+    AuthenticatedUser(user): AuthenticatedUser,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    if !user.admin {
+        return Ok(HttpResponse::Forbidden().body(Body::None));
+    }
+    let result =
+        serde_json::from_str::<(String, NodeDefinition)>(std::str::from_utf8(&body).unwrap());
+    match result {
+        Ok((id, definition)) => match blocking(move || {
+            as_actor(&user.name, || define_node(&SqliteStorage, &id, definition))
+        })
+        .await?
+        {
+            Ok(added) => Ok(HttpResponse::Ok().json(&added)),
+            Err(problems) => Ok(HttpResponse::UnprocessableEntity().json(&problems)),
+        },
+        Err(e) => Ok(HttpResponse::BadRequest().body(e.to_string())),
+    }
+}
+
+/// Applies a JSON merge patch, `null` removes a field and objects are merged recursively.
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge_patch(target.entry(key).or_insert(serde_json::Value::Null), value);
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+#[patch("/node/{id}")]
+async fn edit_node(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    if !user.admin {
+        return Ok(HttpResponse::Forbidden().body(Body::None));
+    }
+    let patch = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(patch) => patch,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e.to_string())),
+    };
+    let lookup = id.clone();
+    let found = blocking(move || {
+        Some((
+            get_node_by_id(&lookup, true)?,
+            get_node_definition(&lookup)?,
+        ))
+    })
+    .await?;
+    let (node, definition) = match found {
+        Some(found) => found,
+        None => return Ok(HttpResponse::NotFound().body(Body::None)),
+    };
+    let mut value = serde_json::to_value(&definition).map_err(ErrorInternalServerError)?;
+    merge_patch(&mut value, patch);
+    let definition = match serde_json::from_value::<NodeDefinition>(value) {
+        Ok(definition) => definition,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e.to_string())),
+    };
+    match blocking(move || {
+        as_actor(&user.name, || {
+            redefine_node(&SqliteStorage, &node, definition)
+        })
+    })
+    .await?
+    {
+        Ok(edited) => Ok(HttpResponse::Ok().json(&edited)),
+        Err(problems) => Ok(HttpResponse::UnprocessableEntity().json(&problems)),
+    }
+}
+
+#[delete("/node/{id}")]
+async fn delete_node(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if !user.admin {
+        return Ok(HttpResponse::Forbidden().body(Body::None));
+    }
+    let node = match blocking(move || get_node_by_id(&id, true)).await? {
+        Some(node) => node,
+        None => return Ok(HttpResponse::NotFound().body(Body::None)),
+    };
+    match blocking(move || as_actor(&user.name, || undefine_node(&SqliteStorage, &node))).await? {
+        Ok(removed) => Ok(HttpResponse::Ok().json(&removed)),
+        Err(message) => Ok(HttpResponse::Conflict().body(message)),
     }
 }
 
 #[get("/image/list")]
-async fn list_images() -> impl Responder {
-    let vec = get_images_rows();
-    serde_json::to_string(&vec).unwrap()
+async fn list_images(_user: AuthenticatedUser) -> Result<HttpResponse, Error> {
+    let vec = blocking(get_images_rows).await?;
+    Ok(HttpResponse::Ok().json(&vec))
 }
 
 #[put("/deploy/image")]
-async fn deploy_image(body: web::Bytes) -> Result<HttpResponse, Error> {
+async fn deploy_image(
+    AuthenticatedUser(user): AuthenticatedUser,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
     let result =
         serde_json::from_str::<(String, Option<Node>)>(std::str::from_utf8(&body).unwrap());
     match result {
         Ok((image, node)) => {
-            let status = deploy_single_image(&image, node);
-            Ok(HttpResponse::Ok()
-                .content_type("application/json")
-                .body(serde_json::to_string(&status).unwrap()))
+            let deployment = Deployment::for_image(&image, node.map(|n| n.id), &user.name);
+            start_job_response(deployment).await
         }
         Err(e) => {
             eprintln!("{}", e.to_string());
@@ -65,14 +283,62 @@ async fn deploy_image(body: web::Bytes) -> Result<HttpResponse, Error> {
 }
 
 #[put("/deploy/file")]
-async fn deploy_file(body: web::Bytes) -> Result<HttpResponse, Error> {
+async fn deploy_file(
+    AuthenticatedUser(user): AuthenticatedUser,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let result = serde_json::from_str::<Deployment>(std::str::from_utf8(&body).unwrap());
+    match result {
+        Ok(mut deployment) => {
+            deployment.owner = user.name;
+            start_job_response(deployment).await
+        }
+        Err(e) => {
+            eprintln!("{}", e.to_string());
+            Ok(HttpResponse::NotFound().body(Body::None))
+        }
+    }
+}
+
+async fn start_job_response(deployment: Deployment) -> Result<HttpResponse, Error> {
+    match blocking(move || start_deployment_job(Arc::new(SqliteStorage), deployment)).await? {
+        Ok(id) => Ok(HttpResponse::Accepted().json(&id)),
+        Err(e) => {
+            eprintln!("{}", e.to_string());
+            Ok(HttpResponse::InternalServerError().body(Body::None))
+        }
+    }
+}
+
+#[get("/jobs/{id}")]
+async fn get_job(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<i64>,
+    filter: web::Query<StepFilter>,
+) -> Result<HttpResponse, Error> {
+    let after = filter.after.unwrap_or(0);
+    match blocking(move || get_job_by_id(id, after)).await? {
+        Ok(job) => {
+            if !user.may_manage(&job.owner) {
+                return Ok(HttpResponse::Forbidden().body(Body::None));
+            }
+            Ok(HttpResponse::Ok().json(&job))
+        }
+        Err(_) => Ok(HttpResponse::NotFound().body(Body::None)),
+    }
+}
+
+#[put("/deploy/plan")]
+async fn plan_deployment(
+    AuthenticatedUser(user): AuthenticatedUser,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
     let result = serde_json::from_str::<Deployment>(std::str::from_utf8(&body).unwrap());
     match result {
         Ok(mut deployment) => {
-            let status = deploy(&mut deployment);
-            Ok(HttpResponse::Ok()
-                .content_type("application/json")
-                .body(serde_json::to_string(&status).unwrap()))
+            deployment.owner = user.name;
+            let plan = blocking(move || plan(&SqliteStorage, &mut deployment)).await?;
+            Ok(HttpResponse::Ok().json(&plan))
         }
         Err(e) => {
             eprintln!("{}", e.to_string());
@@ -82,13 +348,99 @@ async fn deploy_file(body: web::Bytes) -> Result<HttpResponse, Error> {
 }
 
 #[get("/deployment/list/{all}")]
-async fn list_deployments(web::Path(all): web::Path<bool>) -> impl Responder {
-    let results = get_deployment_rows(all);
-    serde_json::to_string(&results).unwrap()
+async fn list_deployments(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(all): web::Path<bool>,
+    filter: web::Query<StateFilter>,
+) -> Result<HttpResponse, Error> {
+    match get_state_filter(&filter) {
+        Ok(state) => {
+            let rows = blocking(move || {
+                get_deployment_rows(&SqliteStorage, all, get_owner_filter(&user), state.as_ref())
+            })
+            .await?;
+            Ok(HttpResponse::Ok().json(&rows))
+        }
+        Err(message) => Ok(HttpResponse::BadRequest().body(message)),
+    }
+}
+
+#[put("/deployment/stop/{id}/{prune}")]
+async fn stop_deployment(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path((id, prune)): web::Path<(i64, bool)>,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || get_owner_of_deployment(&SqliteStorage, id)).await? {
+        Some(owner) => {
+            if !user.may_manage(&owner) {
+                return Ok(HttpResponse::Forbidden().body(Body::None));
+            }
+            let stopped = blocking(move || {
+                as_actor(&user.name, || {
+                    manager::stop_deployment(&SqliteStorage, id, prune)
+                })
+            })
+            .await?;
+            Ok(HttpResponse::Ok().json(&stopped))
+        }
+        None => Ok(HttpResponse::NotFound().body(Body::None)),
+    }
+}
+
+#[put("/deployment/rerun/{id}")]
+async fn rerun_deployment(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<i64>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || get_owner_of_deployment(&SqliteStorage, id)).await? {
+        Some(owner) => {
+            if !user.may_manage(&owner) {
+                return Ok(HttpResponse::Forbidden().body(Body::None));
+            }
+        }
+        None => return Ok(HttpResponse::NotFound().body(Body::None)),
+    }
+    let request = match serde_json::from_str::<RerunRequest>(std::str::from_utf8(&body).unwrap()) {
+        Ok(request) => request,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e.to_string())),
+    };
+    match blocking(move || prepare_rerun(&SqliteStorage, id, &user.name, &request)).await? {
+        Ok(deployment) => start_job_response(deployment).await,
+        Err(message) => Ok(HttpResponse::BadRequest().body(message)),
+    }
+}
+
+#[get("/deployment/history/{id}")]
+async fn get_deployment_history(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<i64>,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || get_owner_of_deployment(&SqliteStorage, id)).await? {
+        Some(owner) => {
+            if !user.may_manage(&owner) {
+                return Ok(HttpResponse::Forbidden().body(Body::None));
+            }
+            let rows = blocking(move || get_state_transition_rows(&SqliteStorage, id)).await?;
+            Ok(HttpResponse::Ok().json(&rows))
+        }
+        None => Ok(HttpResponse::NotFound().body(Body::None)),
+    }
 }
 
 #[get("/deployment/logs/{id}")]
-async fn get_deployment_logs(web::Path(id): web::Path<i64>) -> Result<HttpResponse, Error> {
+async fn get_deployment_logs(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<i64>,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || get_owner_of_deployment(&SqliteStorage, id)).await? {
+        Some(owner) => {
+            if !user.may_manage(&owner) {
+                return Ok(HttpResponse::Forbidden().body(Body::None));
+            }
+        }
+        None => return Ok(HttpResponse::NotFound().body(Body::None)),
+    }
     let filename = format!("{}.zip", id);
     let path = format!("{}/{}", RESULTS_DIR, filename);
     if !Path::new(path.as_str()).exists() {
@@ -103,10 +455,135 @@ async fn get_deployment_logs(web::Path(id): web::Path<i64>) -> Result<HttpRespon
         .body(data))
 }
 
+#[get("/reservation/list/{all}")]
+async fn list_reservations(
+    _user: AuthenticatedUser,
+    web::Path(all): web::Path<bool>,
+) -> Result<HttpResponse, Error> {
+    let results = blocking(move || get_reservation_rows(&SqliteStorage, all)).await?;
+    Ok(HttpResponse::Ok().json(&results))
+}
+
+#[put("/reservation")]
+async fn create_reservation(
+    AuthenticatedUser(user): AuthenticatedUser,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let result = serde_json::from_str::<ReservationRequest>(std::str::from_utf8(&body).unwrap());
+    match result {
+        Ok(mut request) => {
+            request.owner = user.name;
+            match blocking(move || {
+                as_actor(&request.owner.clone(), || reserve(&SqliteStorage, &request))
+            })
+            .await?
+            {
+                Ok(reservation) => Ok(HttpResponse::Ok().json(&reservation)),
+                Err(message) => Ok(HttpResponse::Conflict().body(message)),
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e.to_string());
+            Ok(HttpResponse::NotFound().body(Body::None))
+        }
+    }
+}
+
+#[put("/reservation/extend/{id}")]
+async fn extend_reservation(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<i64>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let result = serde_json::from_str::<NaiveDateTime>(std::str::from_utf8(&body).unwrap());
+    match result {
+        Ok(end) => match blocking(move || {
+            as_actor(&user.name, || {
+                reservation_manager::extend_reservation(&SqliteStorage, id, &user, &end)
+            })
+        })
+        .await?
+        {
+            Ok(()) => Ok(HttpResponse::Ok().json(&true)),
+            Err(message) => Ok(HttpResponse::Conflict().body(message)),
+        },
+        Err(e) => {
+            eprintln!("{}", e.to_string());
+            Ok(HttpResponse::NotFound().body(Body::None))
+        }
+    }
+}
+
+#[put("/reservation/release/{id}")]
+async fn release_reservation(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Path(id): web::Path<i64>,
+) -> Result<HttpResponse, Error> {
+    match blocking(move || {
+        as_actor(&user.name, || {
+            reservation_manager::release_reservation(&SqliteStorage, id, &user)
+        })
+    })
+    .await?
+    {
+        Ok(()) => Ok(HttpResponse::Ok().json(&true)),
+        Err(message) => Ok(HttpResponse::Conflict().body(message)),
+    }
+}
+
+#[get("/audit")]
+async fn list_audit_entries(
+    AuthenticatedUser(user): AuthenticatedUser,
+    web::Query(mut filter): web::Query<AuditFilter>,
+) -> Result<HttpResponse, Error> {
+    if !user.admin {
+        filter.user = Some(user.name);
+    }
+    let rows = blocking(move || get_audit_rows(&filter)).await?;
+    Ok(HttpResponse::Ok().json(&rows))
+}
+
+#[post("/config/reload")]
+async fn reload_configuration(
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<HttpResponse, Error> {
+    if !user.admin {
+        return Ok(HttpResponse::Forbidden().body(Body::None));
+    }
+    match blocking(move || as_actor(&user.name, reload_config)).await? {
+        Ok(()) => Ok(HttpResponse::Ok().json(&true)),
+        Err(problems) => Ok(HttpResponse::UnprocessableEntity().json(&problems)),
+    }
+}
+
+/// Reloads config.yml whenever the server receives SIGHUP.
+async fn reload_on_hangup() {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            eprintln!("Could not listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match blocking(reload_config).await {
+            Ok(Ok(())) => println!("Reloaded config.yml"),
+            Ok(Err(problems)) => {
+                eprintln!("Kept the previous configuration, config.yml has problems:");
+                for problem in problems {
+                    eprintln!("{}", problem);
+                }
+            }
+            Err(e) => eprintln!("Could not reload config.yml: {}", e),
+        }
+    }
+}
+
 const UPLOAD_PATH: &str = "/tmp/rest-api/upload";
 
 #[post("/image/upload/{name}/{checksum}")]
 async fn upload_image(
+    AuthenticatedUser(user): AuthenticatedUser,
     web::Path((filename, checksum)): web::Path<(String, String)>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, Error> {
@@ -138,9 +615,10 @@ async fn upload_image(
             }
         }
     }
-    return if sha256sum_matches(&format!("{}/{}", UPLOAD_PATH, &filename), &checksum) {
+    let target = format!("image {}", filename);
+    let response = if sha256sum_matches(&format!("{}/{}", UPLOAD_PATH, &filename), &checksum) {
         let move_path = filepath.clone();
-        return match web::block(move || {
+        match web::block(move || {
             Command::new(MOVE)
                 .arg(&move_path)
                 .arg(format!("{}/{}", OS_IMAGES_DIR, filename))
@@ -152,27 +630,38 @@ async fn upload_image(
         {
             Ok(status) => {
                 if status.success() {
-                    Ok(HttpResponse::Ok().json(&true))
+                    HttpResponse::Ok().json(&true)
                 } else {
-                    Ok(HttpResponse::Conflict().body(Body::None))
+                    HttpResponse::Conflict().body(Body::None)
                 }
             }
             Err(e) => {
                 eprintln!("{:?}", e);
-                Ok(HttpResponse::Conflict().body("error on file creation"))
+                HttpResponse::Conflict().body("error on file creation")
             }
-        };
+        }
     } else {
         let delete_path = filepath.clone();
         web::block(move || fs::remove_file(&delete_path))
             .await
             .unwrap();
-        Ok(HttpResponse::Conflict().body(Body::None))
+        HttpResponse::Conflict().body(Body::None)
     };
+    let success = response.status().is_success();
+    blocking(move || {
+        as_actor(&user.name, || {
+            record("upload image", &target, None, success)
+        })
+    })
+    .await?;
+    Ok(response)
 }
 
 #[get("/image/download/{name}")]
-async fn download_image(web::Path(name): web::Path<String>) -> Result<HttpResponse, Error> {
+async fn download_image(
+    _user: AuthenticatedUser,
+    web::Path(name): web::Path<String>,
+) -> Result<HttpResponse, Error> {
     let filename = format!("{}.zip", name);
     let path = format!("{}/{}", OS_IMAGES_DIR, filename);
     if !Path::new(path.as_str()).exists() {
@@ -190,22 +679,43 @@ async fn download_image(web::Path(name): web::Path<String>) -> Result<HttpRespon
 #[actix_web::main]
 pub(crate) async fn start(ip_address: String, port: String) -> std::io::Result<()> {
     let addr = format!("{}:{}", ip_address, port);
-    let _handle = tokio::spawn(async move { watch().await });
+    blocking(fail_unfinished_jobs)
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
+        .unwrap_or_default();
+    let _handle = tokio::spawn(async move { watch(Arc::new(SqliteStorage)).await });
+    let _reload_handle = tokio::spawn(reload_on_hangup());
     println!("Listening on {}", addr);
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     HttpServer::new(|| {
         App::new()
             .wrap(Logger::default())
             .service(list_services)
+            .service(stop_service)
             .service(list_nodes)
             .service(get_node)
+            .service(get_node_history)
+            .service(add_node)
+            .service(edit_node)
+            .service(delete_node)
             .service(list_deployments)
+            .service(stop_deployment)
             .service(list_images)
             .service(deploy_image)
             .service(deploy_file)
+            .service(plan_deployment)
+            .service(get_job)
             .service(upload_image)
             .service(download_image)
             .service(get_deployment_logs)
+            .service(get_deployment_history)
+            .service(rerun_deployment)
+            .service(list_reservations)
+            .service(create_reservation)
+            .service(extend_reservation)
+            .service(release_reservation)
+            .service(list_audit_entries)
+            .service(reload_configuration)
     })
     .bind(addr)?
     .run()
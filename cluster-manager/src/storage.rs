@@ -0,0 +1,322 @@
+use crate::database::Error;
+use crate::{config, database};
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+use structs::deployment::Deployment;
+use structs::node::Node;
+use structs::node_state::{NodeState, NodeStateEntry};
+use structs::node_state_transition::NodeStateTransition;
+use structs::reservation::Reservation;
+use structs::service::Service;
+use structs::state::State;
+use structs::state_transition::StateTransition;
+use structs::task::Task;
+use structs::timeline_event::TimelineEvent;
+
+#[cfg(test)]
+pub mod memory;
+
+/// Everything the deployer, manager and watcher store about deployments, their services and tasks
+/// and the nodes they run on.
+pub trait Storage: Send + Sync {
+    // deployments
+    fn insert_deployment(&self, deployment: &Deployment) -> Result<i64, Error>;
+    fn get_deployment_by_id(&self, id: i64, only_active: bool) -> Result<Deployment, Error>;
+    fn get_deployments(&self) -> Result<Vec<Deployment>, Error>;
+    fn get_deployments_by_owner(&self, owner: &str) -> Result<Vec<Deployment>, Error>;
+    /// Returns the deployments which have not ended yet, together with their tasks.
+    fn get_running_deployments(&self) -> Result<Vec<Deployment>, Error>;
+    fn get_deployment_names(&self) -> Result<HashMap<i64, String>, Error>;
+    fn get_spec_of_deployment(&self, id: i64) -> Result<Option<String>, Error>;
+    /// Returns `false` if the deployment may not move into `state`.
+    fn set_state_for_deployment(
+        &self,
+        id: i64,
+        state: &State,
+        reason: Option<&str>,
+    ) -> Result<bool, Error>;
+    fn set_failure_for_deployment(&self, id: i64, reason: &str) -> Result<usize, Error>;
+    fn set_enddate_for_deployment(&self, id: i64) -> Result<usize, Error>;
+    fn get_running_since(&self, id: i64) -> Result<Option<NaiveDateTime>, Error>;
+    fn get_state_transitions_of_deployment(&self, id: i64) -> Result<Vec<StateTransition>, Error>;
+    fn insert_timeline_event(
+        &self,
+        deployment: i64,
+        service: Option<i64>,
+        message: &str,
+    ) -> Result<i64, Error>;
+    fn get_timeline_of_deployment(&self, id: i64) -> Result<Vec<TimelineEvent>, Error>;
+    fn get_log_offsets(&self, deployment: i64) -> Result<HashMap<String, u64>, Error>;
+    fn set_log_offset(&self, deployment: i64, file: &str, offset: u64) -> Result<usize, Error>;
+
+    // services
+    fn insert_service(&self, service: &Service) -> Result<i64, Error>;
+    fn get_service_by_id(&self, id: i64, only_active: bool) -> Result<Service, Error>;
+    fn get_services(&self) -> Result<Vec<Service>, Error>;
+    fn get_running_services(&self) -> Result<Vec<Service>, Error>;
+    fn get_services_by_deployment(&self, id: i64) -> Result<Vec<Service>, Error>;
+    fn get_service_counts(&self) -> Result<HashMap<i64, usize>, Error>;
+    /// Returns `false` if the service may not move into `state`.
+    fn set_state_for_service(
+        &self,
+        id: i64,
+        state: &State,
+        reason: Option<&str>,
+    ) -> Result<bool, Error>;
+    fn set_enddate_for_service(&self, id: i64) -> Result<usize, Error>;
+
+    // tasks
+    fn insert_task(&self, task: &Task, deployment_id: i64) -> Result<i64, Error>;
+    fn get_tasks_by_deployment(&self, id: i64) -> Result<Vec<Task>, Error>;
+    fn set_startdate_for_task(&self, id: i64) -> Result<usize, Error>;
+    /// Ends the task and records whether it succeeded.
+    fn set_outcome_for_task(&self, id: i64, success: bool) -> Result<usize, Error>;
+    fn set_hits_for_task(&self, id: i64, hits: i64) -> Result<usize, Error>;
+
+    // nodes
+    /// Returns every node of the cluster, whether it is usable or not.
+    fn get_all_nodes(&self) -> Vec<Node>;
+    /// Returns the available nodes, the ones reserved for `owner` first.
+    fn get_idle_nodes(&self, owner: &str) -> Result<Vec<Node>, Error>;
+    fn get_node_states(&self) -> Result<HashMap<String, NodeStateEntry>, Error>;
+    /// Returns `false` if the node already is in `state`.
+    fn set_state_for_node(
+        &self,
+        node: &str,
+        state: &NodeState,
+        reason: Option<&str>,
+    ) -> Result<bool, Error>;
+    fn set_expected_return_for_node(
+        &self,
+        node: &str,
+        expected_return: Option<&NaiveDateTime>,
+    ) -> Result<usize, Error>;
+    fn set_reason_for_node(&self, node: &str, reason: &str) -> Result<usize, Error>;
+    fn get_state_transitions_of_node(&self, node: &str) -> Result<Vec<NodeStateTransition>, Error>;
+
+    // reservations
+    /// Adds the reservation unless one of its nodes is reserved during its time window, which
+    /// returns `None`.
+    fn insert_reservation(&self, reservation: &Reservation) -> Result<Option<i64>, Error>;
+    fn get_reservations(&self) -> Result<Vec<Reservation>, Error>;
+    fn get_active_reservations(&self) -> Result<Vec<Reservation>, Error>;
+    fn get_upcoming_reservations(&self) -> Result<Vec<Reservation>, Error>;
+    fn get_overlapping_reservations(
+        &self,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Vec<Reservation>, Error>;
+    fn get_reservation_by_id(&self, id: i64) -> Result<Reservation, Error>;
+    fn set_enddate_for_reservation(&self, id: i64, end: &NaiveDateTime) -> Result<usize, Error>;
+    fn set_released_for_reservation(&self, id: i64) -> Result<usize, Error>;
+}
+
+/// The database in `BASE_DIR` every installation uses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SqliteStorage;
+
+impl Storage for SqliteStorage {
+    fn insert_deployment(&self, deployment: &Deployment) -> Result<i64, Error> {
+        database::insert_deployment(deployment)
+    }
+
+    fn get_deployment_by_id(&self, id: i64, only_active: bool) -> Result<Deployment, Error> {
+        database::get_deployment_by_id(id, only_active)
+    }
+
+    fn get_deployments(&self) -> Result<Vec<Deployment>, Error> {
+        database::get_deployments()
+    }
+
+    fn get_deployments_by_owner(&self, owner: &str) -> Result<Vec<Deployment>, Error> {
+        database::get_deployments_by_owner(owner)
+    }
+
+    fn get_running_deployments(&self) -> Result<Vec<Deployment>, Error> {
+        database::get_running_deployments()
+    }
+
+    fn get_deployment_names(&self) -> Result<HashMap<i64, String>, Error> {
+        database::get_deployment_names()
+    }
+
+    fn get_spec_of_deployment(&self, id: i64) -> Result<Option<String>, Error> {
+        database::get_spec_of_deployment(id)
+    }
+
+    fn set_state_for_deployment(
+        &self,
+        id: i64,
+        state: &State,
+        reason: Option<&str>,
+    ) -> Result<bool, Error> {
+        database::set_state_for_deployment(id, state, reason)
+    }
+
+    fn set_failure_for_deployment(&self, id: i64, reason: &str) -> Result<usize, Error> {
+        database::set_failure_for_deployment(id, reason)
+    }
+
+    fn set_enddate_for_deployment(&self, id: i64) -> Result<usize, Error> {
+        database::set_enddate_for_deployment(id)
+    }
+
+    fn get_running_since(&self, id: i64) -> Result<Option<NaiveDateTime>, Error> {
+        database::get_running_since(id)
+    }
+
+    fn get_state_transitions_of_deployment(&self, id: i64) -> Result<Vec<StateTransition>, Error> {
+        database::get_state_transitions_of_deployment(id)
+    }
+
+    fn insert_timeline_event(
+        &self,
+        deployment: i64,
+        service: Option<i64>,
+        message: &str,
+    ) -> Result<i64, Error> {
+        database::insert_timeline_event(deployment, service, message)
+    }
+
+    fn get_timeline_of_deployment(&self, id: i64) -> Result<Vec<TimelineEvent>, Error> {
+        database::get_timeline_of_deployment(id)
+    }
+
+    fn get_log_offsets(&self, deployment: i64) -> Result<HashMap<String, u64>, Error> {
+        database::get_log_offsets(deployment)
+    }
+
+    fn set_log_offset(&self, deployment: i64, file: &str, offset: u64) -> Result<usize, Error> {
+        database::set_log_offset(deployment, file, offset)
+    }
+
+    fn insert_service(&self, service: &Service) -> Result<i64, Error> {
+        database::insert_service(service)
+    }
+
+    fn get_service_by_id(&self, id: i64, only_active: bool) -> Result<Service, Error> {
+        database::get_service_by_id(id, only_active)
+    }
+
+    fn get_services(&self) -> Result<Vec<Service>, Error> {
+        database::get_services()
+    }
+
+    fn get_running_services(&self) -> Result<Vec<Service>, Error> {
+        database::get_running_services()
+    }
+
+    fn get_services_by_deployment(&self, id: i64) -> Result<Vec<Service>, Error> {
+        database::get_services_by_deployment(id)
+    }
+
+    fn get_service_counts(&self) -> Result<HashMap<i64, usize>, Error> {
+        database::get_service_counts()
+    }
+
+    fn set_state_for_service(
+        &self,
+        id: i64,
+        state: &State,
+        reason: Option<&str>,
+    ) -> Result<bool, Error> {
+        database::set_state_for_service(id, state, reason)
+    }
+
+    fn set_enddate_for_service(&self, id: i64) -> Result<usize, Error> {
+        database::set_enddate_for_service(id)
+    }
+
+    fn insert_task(&self, task: &Task, deployment_id: i64) -> Result<i64, Error> {
+        database::insert_task(task, deployment_id)
+    }
+
+    fn get_tasks_by_deployment(&self, id: i64) -> Result<Vec<Task>, Error> {
+        database::get_tasks_by_deployment(id)
+    }
+
+    fn set_startdate_for_task(&self, id: i64) -> Result<usize, Error> {
+        database::set_startdate_for_task(id)
+    }
+
+    fn set_outcome_for_task(&self, id: i64, success: bool) -> Result<usize, Error> {
+        database::set_outcome_for_task(id, success)
+    }
+
+    fn set_hits_for_task(&self, id: i64, hits: i64) -> Result<usize, Error> {
+        database::set_hits_for_task(id, hits)
+    }
+
+    fn get_all_nodes(&self) -> Vec<Node> {
+        config::get_all_nodes().unwrap_or_default()
+    }
+
+    fn get_idle_nodes(&self, owner: &str) -> Result<Vec<Node>, Error> {
+        database::get_idle_nodes(owner)
+    }
+
+    fn get_node_states(&self) -> Result<HashMap<String, NodeStateEntry>, Error> {
+        database::get_node_states()
+    }
+
+    fn set_state_for_node(
+        &self,
+        node: &str,
+        state: &NodeState,
+        reason: Option<&str>,
+    ) -> Result<bool, Error> {
+        database::set_state_for_node(node, state, reason)
+    }
+
+    fn set_expected_return_for_node(
+        &self,
+        node: &str,
+        expected_return: Option<&NaiveDateTime>,
+    ) -> Result<usize, Error> {
+        database::set_expected_return_for_node(node, expected_return)
+    }
+
+    fn set_reason_for_node(&self, node: &str, reason: &str) -> Result<usize, Error> {
+        database::set_reason_for_node(node, reason)
+    }
+
+    fn get_state_transitions_of_node(&self, node: &str) -> Result<Vec<NodeStateTransition>, Error> {
+        database::get_state_transitions_of_node(node)
+    }
+
+    fn insert_reservation(&self, reservation: &Reservation) -> Result<Option<i64>, Error> {
+        database::insert_reservation(reservation)
+    }
+
+    fn get_reservations(&self) -> Result<Vec<Reservation>, Error> {
+        database::get_reservations()
+    }
+
+    fn get_active_reservations(&self) -> Result<Vec<Reservation>, Error> {
+        database::get_active_reservations()
+    }
+
+    fn get_upcoming_reservations(&self) -> Result<Vec<Reservation>, Error> {
+        database::get_upcoming_reservations()
+    }
+
+    fn get_overlapping_reservations(
+        &self,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Vec<Reservation>, Error> {
+        database::get_overlapping_reservations(start, end)
+    }
+
+    fn get_reservation_by_id(&self, id: i64) -> Result<Reservation, Error> {
+        database::get_reservation_by_id(id)
+    }
+
+    fn set_enddate_for_reservation(&self, id: i64, end: &NaiveDateTime) -> Result<usize, Error> {
+        database::set_enddate_for_reservation(id, end)
+    }
+
+    fn set_released_for_reservation(&self, id: i64) -> Result<usize, Error> {
+        database::set_released_for_reservation(id)
+    }
+}
@@ -0,0 +1,627 @@
+use super::Storage;
+use crate::database::Error;
+use chrono::{NaiveDateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use structs::architecture::Architecture;
+use structs::deployment::Deployment;
+use structs::node::Node;
+use structs::node_state::{NodeState, NodeStateEntry};
+use structs::node_state_transition::NodeStateTransition;
+use structs::reservation::Reservation;
+use structs::service::Service;
+use structs::state::State;
+use structs::state_transition::StateTransition;
+use structs::task::Task;
+use structs::timeline_event::TimelineEvent;
+
+const DEPLOYMENT: &str = "deployment";
+const SERVICE: &str = "service";
+
+#[derive(Default)]
+struct Content {
+    nodes: Vec<Node>,
+    deployments: Vec<Deployment>,
+    specs: HashMap<i64, String>,
+    services: Vec<Service>,
+    tasks: Vec<Task>,
+    transitions: Vec<StateTransition>,
+    timeline: Vec<TimelineEvent>,
+    offsets: HashMap<(i64, String), u64>,
+    node_states: HashMap<String, NodeStateEntry>,
+    node_transitions: Vec<NodeStateTransition>,
+    reservations: Vec<Reservation>,
+}
+
+/// Keeps everything in memory, so the scheduling logic can run without root, `/etc` or a
+/// database. Nodes start out available.
+#[derive(Default)]
+pub struct MemoryStorage {
+    content: Mutex<Content>,
+}
+
+impl MemoryStorage {
+    #[must_use]
+    pub fn with_nodes(nodes: Vec<Node>) -> Self {
+        let node_states = nodes
+            .iter()
+            .map(|n| {
+                (
+                    n.id.clone(),
+                    NodeStateEntry::new(NodeState::Available, None),
+                )
+            })
+            .collect();
+        MemoryStorage {
+            content: Mutex::new(Content {
+                nodes,
+                node_states,
+                ..Content::default()
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Content> {
+        self.content.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A node which only exists for the scheduler, nothing about it is on disk.
+#[must_use]
+pub fn get_test_node(id: &str, architecture: Architecture) -> Node {
+    Node {
+        id: String::from(id),
+        name: String::from(id),
+        tftp_prefix: String::from(id),
+        mac_address: String::new(),
+        serial_number: String::new(),
+        ipv4_address: String::new(),
+        log_inputs: Vec::new(),
+        architecture,
+        pxe: true,
+        labels: BTreeMap::new(),
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+impl Content {
+    fn deployment(&mut self, id: i64) -> Result<&mut Deployment, Error> {
+        self.deployments
+            .iter_mut()
+            .find(|d| d.id == Some(id))
+            .ok_or(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows))
+    }
+
+    fn service(&mut self, id: i64) -> Result<&mut Service, Error> {
+        self.services
+            .iter_mut()
+            .find(|s| s.id == Some(id))
+            .ok_or(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows))
+    }
+
+    fn reservation(&mut self, id: i64) -> Result<&mut Reservation, Error> {
+        self.reservations
+            .iter_mut()
+            .find(|r| r.id == Some(id))
+            .ok_or(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows))
+    }
+
+    /// Like the database, released reservations never overlap.
+    fn get_overlapping_reservations(
+        &self,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Vec<Reservation> {
+        self.reservations
+            .iter()
+            .filter(|r| r.released.is_none() && r.start < *end && r.end > *start)
+            .cloned()
+            .collect()
+    }
+
+    fn task(&mut self, id: i64) -> Result<&mut Task, Error> {
+        self.tasks
+            .iter_mut()
+            .find(|t| t.id == Some(id))
+            .ok_or(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows))
+    }
+
+    fn insert_task(&mut self, task: &Task, deployment_id: i64) -> i64 {
+        let id = self.tasks.len() as i64 + 1;
+        let mut task = task.clone();
+        task.id = Some(id);
+        task.deployment = self
+            .deployments
+            .iter()
+            .find(|d| d.id == Some(deployment_id))
+            .cloned();
+        self.tasks.push(task);
+        id
+    }
+
+    fn record_transition(
+        &mut self,
+        entity: &str,
+        id: i64,
+        from: Option<State>,
+        to: &State,
+        reason: Option<&str>,
+    ) {
+        self.transitions.push(StateTransition {
+            entity: String::from(entity),
+            entity_id: id,
+            from,
+            to: to.clone(),
+            timestamp: now(),
+            reason: reason.map(String::from),
+        });
+    }
+
+    fn running_services(&self) -> Vec<Service> {
+        self.services
+            .iter()
+            .filter(|s| s.end.is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn insert_deployment(&self, deployment: &Deployment) -> Result<i64, Error> {
+        let mut content = self.lock();
+        let id = content.deployments.len() as i64 + 1;
+        let mut stored = deployment.clone();
+        stored.id = Some(id);
+        stored.start = now();
+        stored.end = None;
+        stored.state = Some(State::Provisioning);
+        stored.services = Vec::new();
+        stored.tasks = Vec::new();
+        content.deployments.push(stored);
+        if let Ok(spec) = serde_json::to_string(deployment) {
+            content.specs.insert(id, spec);
+        }
+        content.record_transition(DEPLOYMENT, id, None, &State::Provisioning, None);
+        for task in &deployment.tasks {
+            content.insert_task(task, id);
+        }
+        Ok(id)
+    }
+
+    fn get_deployment_by_id(&self, id: i64, only_active: bool) -> Result<Deployment, Error> {
+        let mut content = self.lock();
+        let deployment = content.deployment(id)?;
+        if only_active && deployment.end.is_some() {
+            return Err(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows));
+        }
+        Ok(deployment.clone())
+    }
+
+    fn get_deployments(&self) -> Result<Vec<Deployment>, Error> {
+        Ok(self.lock().deployments.clone())
+    }
+
+    fn get_deployments_by_owner(&self, owner: &str) -> Result<Vec<Deployment>, Error> {
+        Ok(self
+            .lock()
+            .deployments
+            .iter()
+            .filter(|d| d.owner.eq(owner))
+            .cloned()
+            .collect())
+    }
+
+    fn get_running_deployments(&self) -> Result<Vec<Deployment>, Error> {
+        let running = self
+            .lock()
+            .deployments
+            .iter()
+            .filter(|d| d.end.is_none())
+            .cloned()
+            .collect::<Vec<Deployment>>();
+        let mut with_tasks = Vec::new();
+        for mut deployment in running {
+            deployment.tasks = self.get_tasks_by_deployment(deployment.id.unwrap())?;
+            with_tasks.push(deployment);
+        }
+        Ok(with_tasks)
+    }
+
+    fn get_deployment_names(&self) -> Result<HashMap<i64, String>, Error> {
+        Ok(self
+            .lock()
+            .deployments
+            .iter()
+            .map(|d| (d.id.unwrap(), d.name.clone()))
+            .collect())
+    }
+
+    fn get_spec_of_deployment(&self, id: i64) -> Result<Option<String>, Error> {
+        let mut content = self.lock();
+        content.deployment(id)?;
+        Ok(content.specs.get(&id).cloned())
+    }
+
+    fn set_state_for_deployment(
+        &self,
+        id: i64,
+        state: &State,
+        reason: Option<&str>,
+    ) -> Result<bool, Error> {
+        let mut content = self.lock();
+        let deployment = content.deployment(id)?;
+        let current = deployment.state.clone();
+        if let Some(current) = &current {
+            if !current.can_transition_to(state) {
+                return Ok(false);
+            }
+        }
+        deployment.state = Some(state.clone());
+        content.record_transition(DEPLOYMENT, id, current, state, reason);
+        Ok(true)
+    }
+
+    fn set_failure_for_deployment(&self, id: i64, reason: &str) -> Result<usize, Error> {
+        self.set_state_for_deployment(id, &State::Failed, Some(reason))?;
+        let mut content = self.lock();
+        let deployment = content.deployment(id)?;
+        deployment.end = Some(now());
+        deployment.failure = Some(String::from(reason));
+        Ok(1)
+    }
+
+    fn set_enddate_for_deployment(&self, id: i64) -> Result<usize, Error> {
+        self.lock().deployment(id)?.end = Some(now());
+        Ok(1)
+    }
+
+    fn get_running_since(&self, id: i64) -> Result<Option<NaiveDateTime>, Error> {
+        Ok(self
+            .lock()
+            .transitions
+            .iter()
+            .filter(|t| t.entity.eq(DEPLOYMENT) && t.entity_id == id && t.to == State::Running)
+            .map(|t| t.timestamp)
+            .min())
+    }
+
+    fn get_state_transitions_of_deployment(&self, id: i64) -> Result<Vec<StateTransition>, Error> {
+        let content = self.lock();
+        let services = content
+            .services
+            .iter()
+            .filter(|s| s.deployment == Some(id))
+            .filter_map(|s| s.id)
+            .collect::<Vec<i64>>();
+        Ok(content
+            .transitions
+            .iter()
+            .filter(|t| {
+                (t.entity.eq(DEPLOYMENT) && t.entity_id == id)
+                    || (t.entity.eq(SERVICE) && services.contains(&t.entity_id))
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn insert_timeline_event(
+        &self,
+        deployment: i64,
+        service: Option<i64>,
+        message: &str,
+    ) -> Result<i64, Error> {
+        let mut content = self.lock();
+        content.timeline.push(TimelineEvent {
+            deployment,
+            service,
+            timestamp: now(),
+            message: String::from(message),
+        });
+        Ok(content.timeline.len() as i64)
+    }
+
+    fn get_timeline_of_deployment(&self, id: i64) -> Result<Vec<TimelineEvent>, Error> {
+        Ok(self
+            .lock()
+            .timeline
+            .iter()
+            .filter(|e| e.deployment == id)
+            .cloned()
+            .collect())
+    }
+
+    fn get_log_offsets(&self, deployment: i64) -> Result<HashMap<String, u64>, Error> {
+        Ok(self
+            .lock()
+            .offsets
+            .iter()
+            .filter(|((id, _), _)| *id == deployment)
+            .map(|((_, file), offset)| (file.clone(), *offset))
+            .collect())
+    }
+
+    fn set_log_offset(&self, deployment: i64, file: &str, offset: u64) -> Result<usize, Error> {
+        self.lock()
+            .offsets
+            .insert((deployment, String::from(file)), offset);
+        Ok(1)
+    }
+
+    fn insert_service(&self, service: &Service) -> Result<i64, Error> {
+        let mut content = self.lock();
+        let id = content.services.len() as i64 + 1;
+        let mut stored = service.clone();
+        stored.id = Some(id);
+        stored.start = now();
+        stored.end = None;
+        stored.state = Some(State::Provisioning);
+        content.services.push(stored);
+        content.record_transition(SERVICE, id, None, &State::Provisioning, None);
+        Ok(id)
+    }
+
+    fn get_service_by_id(&self, id: i64, only_active: bool) -> Result<Service, Error> {
+        let mut content = self.lock();
+        let service = content.service(id)?;
+        if only_active && service.end.is_some() {
+            return Err(Error::Sqlite(rusqlite::Error::QueryReturnedNoRows));
+        }
+        Ok(service.clone())
+    }
+
+    fn get_services(&self) -> Result<Vec<Service>, Error> {
+        Ok(self.lock().services.clone())
+    }
+
+    fn get_running_services(&self) -> Result<Vec<Service>, Error> {
+        Ok(self.lock().running_services())
+    }
+
+    fn get_services_by_deployment(&self, id: i64) -> Result<Vec<Service>, Error> {
+        Ok(self
+            .lock()
+            .services
+            .iter()
+            .filter(|s| s.deployment == Some(id))
+            .cloned()
+            .collect())
+    }
+
+    fn get_service_counts(&self) -> Result<HashMap<i64, usize>, Error> {
+        let mut counts = HashMap::new();
+        for deployment in self.lock().services.iter().filter_map(|s| s.deployment) {
+            *counts.entry(deployment).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    fn set_state_for_service(
+        &self,
+        id: i64,
+        state: &State,
+        reason: Option<&str>,
+    ) -> Result<bool, Error> {
+        let mut content = self.lock();
+        let service = content.service(id)?;
+        let current = service.state.clone();
+        if let Some(current) = &current {
+            if !current.can_transition_to(state) {
+                return Ok(false);
+            }
+        }
+        service.state = Some(state.clone());
+        content.record_transition(SERVICE, id, current, state, reason);
+        Ok(true)
+    }
+
+    fn set_enddate_for_service(&self, id: i64) -> Result<usize, Error> {
+        self.lock().service(id)?.end = Some(now());
+        Ok(1)
+    }
+
+    fn insert_task(&self, task: &Task, deployment_id: i64) -> Result<i64, Error> {
+        Ok(self.lock().insert_task(task, deployment_id))
+    }
+
+    /// Tasks carry their deployment and service as they are now, like the ones from the database.
+    fn get_tasks_by_deployment(&self, id: i64) -> Result<Vec<Task>, Error> {
+        let mut content = self.lock();
+        let deployment = content.deployment(id)?.clone();
+        let content = &*content;
+        Ok(content
+            .tasks
+            .iter()
+            .filter(|t| t.deployment.as_ref().and_then(|d| d.id) == Some(id))
+            .map(|t| {
+                let mut task = t.clone();
+                task.deployment = Some(deployment.clone());
+                task.service = t
+                    .service
+                    .as_ref()
+                    .and_then(|s| s.id)
+                    .and_then(|service| content.services.iter().find(|s| s.id == Some(service)))
+                    .cloned();
+                task
+            })
+            .collect())
+    }
+
+    fn set_startdate_for_task(&self, id: i64) -> Result<usize, Error> {
+        self.lock().task(id)?.start = Some(now());
+        Ok(1)
+    }
+
+    fn set_outcome_for_task(&self, id: i64, success: bool) -> Result<usize, Error> {
+        let mut content = self.lock();
+        let task = content.task(id)?;
+        task.end = Some(now());
+        task.executed = true;
+        task.success = Some(success);
+        Ok(1)
+    }
+
+    fn set_hits_for_task(&self, id: i64, hits: i64) -> Result<usize, Error> {
+        self.lock().task(id)?.hits = hits;
+        Ok(1)
+    }
+
+    fn get_all_nodes(&self) -> Vec<Node> {
+        self.lock().nodes.clone()
+    }
+
+    /// Like the database, nodes reserved by `owner` come first, but in the order they were added.
+    fn get_idle_nodes(&self, owner: &str) -> Result<Vec<Node>, Error> {
+        let owned = self
+            .get_active_reservations()?
+            .into_iter()
+            .filter(|r| r.owner.eq(owner))
+            .flat_map(|r| r.nodes)
+            .collect::<Vec<String>>();
+        let content = self.lock();
+        let mut available = Vec::new();
+        let mut reserved = Vec::new();
+        for node in &content.nodes {
+            match content.node_states.get(&node.id).map(|entry| &entry.state) {
+                Some(NodeState::Available) => available.push(node.clone()),
+                Some(NodeState::Reserved) if owned.contains(&node.id) => {
+                    reserved.push(node.clone());
+                }
+                _ => {}
+            }
+        }
+        reserved.append(&mut available);
+        Ok(reserved)
+    }
+
+    fn get_node_states(&self) -> Result<HashMap<String, NodeStateEntry>, Error> {
+        Ok(self.lock().node_states.clone())
+    }
+
+    fn set_state_for_node(
+        &self,
+        node: &str,
+        state: &NodeState,
+        reason: Option<&str>,
+    ) -> Result<bool, Error> {
+        let mut content = self.lock();
+        let current = content.node_states.get(node).map(|e| e.state.clone());
+        if current.as_ref() == Some(state) {
+            return Ok(false);
+        }
+        content.node_states.insert(
+            String::from(node),
+            NodeStateEntry::new(state.clone(), reason.map(String::from)),
+        );
+        content.node_transitions.push(NodeStateTransition {
+            node: String::from(node),
+            from: current,
+            to: state.clone(),
+            timestamp: now(),
+            reason: reason.map(String::from),
+        });
+        Ok(true)
+    }
+
+    fn set_expected_return_for_node(
+        &self,
+        node: &str,
+        expected_return: Option<&NaiveDateTime>,
+    ) -> Result<usize, Error> {
+        Ok(match self.lock().node_states.get_mut(node) {
+            Some(entry) => {
+                entry.expected_return = expected_return.copied();
+                1
+            }
+            None => 0,
+        })
+    }
+
+    fn set_reason_for_node(&self, node: &str, reason: &str) -> Result<usize, Error> {
+        Ok(match self.lock().node_states.get_mut(node) {
+            Some(entry) => {
+                entry.reason = Some(String::from(reason));
+                1
+            }
+            None => 0,
+        })
+    }
+
+    fn get_state_transitions_of_node(&self, node: &str) -> Result<Vec<NodeStateTransition>, Error> {
+        Ok(self
+            .lock()
+            .node_transitions
+            .iter()
+            .filter(|t| t.node.eq(node))
+            .cloned()
+            .collect())
+    }
+
+    fn insert_reservation(&self, reservation: &Reservation) -> Result<Option<i64>, Error> {
+        let mut content = self.lock();
+        let conflicting =
+            content.get_overlapping_reservations(&reservation.start, &reservation.end);
+        if reservation
+            .nodes
+            .iter()
+            .any(|node| conflicting.iter().any(|r| r.contains_node(node)))
+        {
+            return Ok(None);
+        }
+        let id = content.reservations.len() as i64 + 1;
+        let mut stored = reservation.clone();
+        stored.id = Some(id);
+        content.reservations.push(stored);
+        Ok(Some(id))
+    }
+
+    fn get_reservations(&self) -> Result<Vec<Reservation>, Error> {
+        Ok(self.lock().reservations.clone())
+    }
+
+    fn get_active_reservations(&self) -> Result<Vec<Reservation>, Error> {
+        let now = now();
+        Ok(self
+            .lock()
+            .reservations
+            .iter()
+            .filter(|r| r.released.is_none() && r.start <= now && r.end > now)
+            .cloned()
+            .collect())
+    }
+
+    fn get_upcoming_reservations(&self) -> Result<Vec<Reservation>, Error> {
+        let now = now();
+        Ok(self
+            .lock()
+            .reservations
+            .iter()
+            .filter(|r| r.released.is_none() && r.end > now)
+            .cloned()
+            .collect())
+    }
+
+    fn get_overlapping_reservations(
+        &self,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Vec<Reservation>, Error> {
+        Ok(self.lock().get_overlapping_reservations(start, end))
+    }
+
+    fn get_reservation_by_id(&self, id: i64) -> Result<Reservation, Error> {
+        Ok(self.lock().reservation(id)?.clone())
+    }
+
+    fn set_enddate_for_reservation(&self, id: i64, end: &NaiveDateTime) -> Result<usize, Error> {
+        self.lock().reservation(id)?.end = *end;
+        Ok(1)
+    }
+
+    fn set_released_for_reservation(&self, id: i64) -> Result<usize, Error> {
+        self.lock().reservation(id)?.released = Some(now());
+        Ok(1)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::audit::record;
+use crate::database::{
+    delete_user, get_user_by_token_hash, get_users, insert_user, set_token_hash_for_user,
+};
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use structs::user::User;
+use structs::user_row::UserRow;
+use structs::utils::{print_information, print_message};
+
+const TOKEN_LENGTH: usize = 40;
+
+fn generate_token() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn authenticate(token: &str) -> Option<User> {
+    get_user_by_token_hash(&hash_token(token)).ok()
+}
+
+pub fn add_user(name: &str, admin: bool) -> bool {
+    let token = generate_token();
+    let status = insert_user(&User::new(name, admin), &hash_token(&token)).is_ok();
+    print_message(&format!("add user {}", name), status);
+    if status {
+        print_token(&token);
+    }
+    record("add user", &format!("user {}", name), None, status)
+}
+
+pub fn renew_token(name: &str) -> bool {
+    let token = generate_token();
+    let status = matches!(set_token_hash_for_user(name, &hash_token(&token)), Ok(1));
+    print_message(&format!("renew token of {}", name), status);
+    if status {
+        print_token(&token);
+    }
+    record("renew token", &format!("user {}", name), None, status)
+}
+
+pub fn remove_user(name: &str) -> bool {
+    let status = matches!(delete_user(name), Ok(1));
+    print_message(&format!("remove user {}", name), status);
+    record("remove user", &format!("user {}", name), None, status)
+}
+
+fn print_token(token: &str) {
+    print_information(&format!("token is {}", token));
+    print_information("the token is only shown once, pass it to the client as CLUSTER_TOKEN");
+}
+
+pub fn list_users() {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(
+        [Cell::new("name"), Cell::new("admin"), Cell::new("created")].to_vec(),
+    ));
+
+    match get_users() {
+        Ok(users) => {
+            for user in users {
+                table.add_row(Row::new(UserRow::new(user).get_cells()));
+            }
+        }
+        Err(_) => eprintln!("Problem with reading users from database"),
+    }
+
+    table.printstd()
+}
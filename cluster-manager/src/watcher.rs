@@ -1,48 +1,440 @@
-use crate::database::{get_running_deployments, get_services_by_deployment};
-use crate::logs_manager::get_logs_of_deployment;
+use crate::audit::as_actor;
+use crate::database::Error;
+use crate::fault_injector::{get_fault_injection, run_fault_task};
+use crate::logs_manager::{get_host_log_files, get_message_of_line, snapshot_results};
 use crate::manager::stop_deployment;
+use crate::node_manager::{is_up, refresh_node_states};
+use crate::remote_commands::{get_remote_command, run_command_task};
+use crate::storage::Storage;
+use chrono::{NaiveDateTime, Utc};
+use linemux::MuxedEvents;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use structs::deployment::Deployment;
-use structs::task::Type::StopIfTrue;
-use structs::task::{Task, Type};
+use structs::remote_command::Trigger;
+use structs::service::Service;
+use structs::state::State;
+use structs::task::Task;
+use structs::task::Type::{InjectFault, RunCommand, SnapshotResults, StopIfTrue};
+use tokio::task::{spawn_blocking, JoinHandle};
 use tokio::time::{sleep, Duration};
 
-pub async fn watch() {
+/// The actor of everything the watcher changes on its own.
+const WATCHER: &str = "watcher";
+
+struct StopCondition {
+    task: i64,
+    message: String,
+    occurrence: i64,
+    hits: i64,
+}
+
+pub async fn watch(storage: Arc<dyn Storage>) {
+    let watched = Arc::new(Mutex::new(HashSet::new()));
     loop {
-        let deployments = get_running_deployments().unwrap();
-        for mut deployment in deployments {
-            deployment.services = get_services_by_deployment(deployment.id.unwrap()).unwrap();
-            if !deployment.tasks.is_empty() {
-                for task in &deployment.tasks {
-                    if task.during_deployment {
-                        match task.task_type {
-                            StopIfTrue => {
-                                if log_task_fulfilled(&task, &deployment).await {
-                                    stop_deployment(deployment.id.unwrap(), false);
-                                }
-                            }
-                            Type::NoOp
-                            | Type::PurgeLocalStorage
-                            | Type::DeleteLocalStorage
-                            | Type::GetResults => {}
-                        }
+        let round = storage.clone();
+        let deployments = match spawn_blocking(move || check_deployments(&*round)).await {
+            Ok(Ok(deployments)) => deployments,
+            Ok(Err(e)) => {
+                // e.g. the database is busy, the next round tries again
+                eprintln!("could not check the running deployments: {}", e);
+                sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("could not check the running deployments: {}", e);
+                sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+        watched
+            .lock()
+            .unwrap()
+            .retain(|id| deployments.iter().any(|d| d.id == Some(*id)));
+        for deployment in deployments {
+            let id = deployment.id.unwrap();
+            if watched.lock().unwrap().insert(id) {
+                let storage = storage.clone();
+                let watched = watched.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = watch_deployment_tasks(storage, deployment).await {
+                        eprintln!("could not watch deployment {}: {}", id, e);
+                        // e.g. a log file does not exist yet, the next round tries again
+                        watched.lock().unwrap().remove(&id);
                     }
-                }
+                });
             }
         }
-        sleep(Duration::from_secs(60)).await;
+        sleep(Duration::from_secs(10)).await;
+    }
+}
+
+/// Returns the running deployments with their services, after marking booted services and
+/// refreshing the node states. Pings, files and the database block, so this runs off the runtime.
+fn check_deployments(storage: &dyn Storage) -> Result<Vec<Deployment>, Error> {
+    let mut deployments = storage.get_running_deployments()?;
+    for deployment in &mut deployments {
+        deployment.services = storage.get_services_by_deployment(deployment.id.unwrap())?;
+        if deployment.state == Some(State::Booting) {
+            check_booted_services(storage, deployment);
+        }
     }
+    refresh_node_states(storage);
+    Ok(deployments)
 }
 
-async fn log_task_fulfilled(task: &Task, deployment: &Deployment) -> bool {
-    if let Ok((message, occurrence)) = serde_json::from_str::<(String, i64)>(&task.parameters) {
-        let mut hits = 0;
-        let logs = get_logs_of_deployment(deployment, false);
-        for (_node, _filename, log) in logs {
-            if message.eq(&log) {
-                hits += 1;
+/// Marks every service whose node answers to pings as running, the deployment follows once all
+/// of its services are up.
+fn check_booted_services(storage: &dyn Storage, deployment: &Deployment) {
+    let mut all_running = true;
+    for service in &deployment.services {
+        if service.state != Some(State::Booting) {
+            all_running &= service.state == Some(State::Running);
+            continue;
+        }
+        match &service.ipv4_address {
+            Some(address) if is_up(address) => {
+                storage
+                    .set_state_for_service(service.id.unwrap(), &State::Running, Some("node is up"))
+                    .unwrap_or_default();
             }
+            _ => all_running = false,
         }
-        return hits >= occurrence;
     }
-    false
+    if all_running {
+        storage
+            .set_state_for_deployment(
+                deployment.id.unwrap(),
+                &State::Running,
+                Some("all services are up"),
+            )
+            .unwrap_or_default();
+    }
+}
+
+fn get_stop_conditions(deployment: &Deployment) -> Vec<StopCondition> {
+    deployment
+        .tasks
+        .iter()
+        .filter(|t| t.during_deployment && t.task_type.eq(&StopIfTrue) && t.id.is_some())
+        .filter_map(|t| {
+            serde_json::from_str::<(String, i64)>(&t.parameters)
+                .ok()
+                .map(|(message, occurrence)| StopCondition {
+                    task: t.id.unwrap(),
+                    message,
+                    occurrence,
+                    hits: t.hits,
+                })
+        })
+        .collect()
+}
+
+/// Returns the commands and faults which have not run yet, together with what triggers them.
+fn get_scheduled_tasks(deployment: &Deployment) -> Vec<(Task, Trigger)> {
+    deployment
+        .tasks
+        .iter()
+        .filter(|t| t.during_deployment && t.id.is_some() && t.start.is_none())
+        .filter_map(|t| {
+            let trigger = match t.task_type {
+                RunCommand => get_remote_command(t).map(|c| c.trigger),
+                InjectFault => get_fault_injection(t).map(|f| Trigger::After(f.at)),
+                _ => None,
+            };
+            trigger.map(|trigger| (t.clone(), trigger))
+        })
+        .collect()
+}
+
+fn is_due(trigger: &Trigger, messages: &[String], running_since: Option<NaiveDateTime>) -> bool {
+    match trigger {
+        Trigger::After(seconds) => running_since.map_or(false, |since| {
+            Utc::now().naive_utc() >= since + chrono::Duration::seconds(*seconds)
+        }),
+        Trigger::LogEvent(message) => messages.contains(message),
+        Trigger::OnStop => false,
+    }
+}
+
+/// Returns the id of the snapshot task and its interval in seconds.
+fn get_snapshot_task(deployment: &Deployment) -> Option<(i64, u64)> {
+    deployment
+        .tasks
+        .iter()
+        .filter(|t| t.task_type.eq(&SnapshotResults) && t.id.is_some())
+        .find_map(|t| {
+            t.parameters
+                .parse::<u64>()
+                .ok()
+                .filter(|interval| *interval > 0)
+                .map(|interval| (t.id.unwrap(), interval))
+        })
+}
+
+fn run_scheduled_task(storage: &dyn Storage, task: &Task, services: &[Service]) -> bool {
+    match task.task_type {
+        InjectFault => run_fault_task(storage, task, services),
+        _ => run_command_task(storage, task, services),
+    }
+}
+
+/// Follows the log files of a running deployment, runs its commands and faults when they are due,
+/// snapshots its results and stops it as soon as one of its stop conditions is fulfilled. Offsets,
+/// hits and executed tasks are stored, a restarted server continues where the previous one stopped.
+async fn watch_deployment_tasks(
+    storage: Arc<dyn Storage>,
+    deployment: Deployment,
+) -> std::io::Result<()> {
+    let id = deployment.id.unwrap();
+    let mut conditions = get_stop_conditions(&deployment);
+    let mut scheduled = get_scheduled_tasks(&deployment);
+    let snapshots = get_snapshot_task(&deployment);
+    if conditions.is_empty() && scheduled.is_empty() && snapshots.is_none() {
+        return Ok(());
+    }
+    let (files, mut offsets) = {
+        let storage = storage.clone();
+        let deployment = deployment.clone();
+        spawn_blocking(move || {
+            let files = get_host_log_files(&deployment);
+            (files, storage.get_log_offsets(id).unwrap_or_default())
+        })
+        .await?
+    };
+    let mut events = MuxedEvents::new()?;
+    for file in &files {
+        events.add_file(file).await?;
+    }
+    let mut running_since = None;
+    let mut last_check = Instant::now();
+    let mut last_snapshot: Option<Instant> = None;
+    let mut snapshot: Option<JoinHandle<bool>> = None;
+    loop {
+        let round = {
+            let storage = storage.clone();
+            let files = files.clone();
+            spawn_blocking(move || {
+                let mut messages = Vec::new();
+                for file in &files {
+                    messages.extend(read_new_lines(&*storage, id, file, &mut offsets));
+                }
+                count_hits(&*storage, &messages, &mut conditions);
+                if running_since.is_none() {
+                    running_since = storage.get_running_since(id).unwrap_or(None);
+                }
+                (messages, offsets, conditions, running_since)
+            })
+        };
+        let messages;
+        (messages, offsets, conditions, running_since) = round.await?;
+        if let Some(condition) = conditions.iter().find(|c| c.hits >= c.occurrence) {
+            let reason = format!("stop condition fired: {}", condition.message);
+            spawn_blocking(move || {
+                storage
+                    .set_state_for_deployment(id, &State::CollectingResults, Some(&reason))
+                    .unwrap_or_default();
+                as_actor(WATCHER, || stop_deployment(&*storage, id, false))
+            });
+            return Ok(());
+        }
+        scheduled.retain(|(task, trigger)| {
+            if !is_due(trigger, &messages, running_since) {
+                return true;
+            }
+            let task = task.clone();
+            let services = deployment.services.clone();
+            let storage = storage.clone();
+            spawn_blocking(move || {
+                as_actor(WATCHER, || run_scheduled_task(&*storage, &task, &services))
+            });
+            false
+        });
+        if let Some((task, interval)) = &snapshots {
+            let due = last_snapshot.map_or(true, |last| last.elapsed().as_secs() >= *interval);
+            let idle = snapshot.as_ref().map_or(true, JoinHandle::is_finished);
+            if running_since.is_some() && due && idle {
+                let first = last_snapshot.is_none();
+                let task = *task;
+                let storage = storage.clone();
+                let services = deployment.services.clone();
+                snapshot = Some(spawn_blocking(move || {
+                    if first {
+                        storage.set_startdate_for_task(task).unwrap_or_default();
+                    }
+                    snapshot_results(id, &services)
+                }));
+                last_snapshot = Some(Instant::now());
+            }
+        }
+        if conditions.is_empty() && scheduled.is_empty() && snapshots.is_none() {
+            return Ok(());
+        }
+        if last_check.elapsed().as_secs() >= 10 {
+            let storage = storage.clone();
+            if spawn_blocking(move || storage.get_deployment_by_id(id, true).is_err()).await? {
+                return Ok(());
+            }
+            last_check = Instant::now();
+        }
+        tokio::select! {
+            event = events.next_event() => {
+                if !matches!(event, Ok(Some(_))) {
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+            _ = sleep(Duration::from_secs(10)) => {}
+        }
+    }
+}
+
+/// Returns the messages of the complete lines appended to `file` since the last call.
+fn read_new_lines(
+    storage: &dyn Storage,
+    deployment: i64,
+    file: &str,
+    offsets: &mut HashMap<String, u64>,
+) -> Vec<String> {
+    let mut messages = Vec::new();
+    let handle = match File::open(file) {
+        Ok(handle) => handle,
+        Err(_) => return messages,
+    };
+    let length = handle.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut offset = *offsets.get(file).unwrap_or(&0);
+    if length < offset {
+        // the file was truncated or replaced
+        offset = 0;
+    }
+    if length == offset {
+        return messages;
+    }
+    let mut reader = BufReader::new(handle);
+    if reader.seek(SeekFrom::Start(offset)).is_err() {
+        return messages;
+    }
+    let mut line = Vec::new();
+    while let Ok(read) = reader.read_until(b'\n', &mut line) {
+        if read == 0 || !line.ends_with(b"\n") {
+            break;
+        }
+        offset += read as u64;
+        if let Some(message) = get_message_of_line(String::from_utf8_lossy(&line).trim_end()) {
+            messages.push(message);
+        }
+        line.clear();
+    }
+    offsets.insert(String::from(file), offset);
+    storage
+        .set_log_offset(deployment, file, offset)
+        .unwrap_or_default();
+    messages
+}
+
+fn count_hits(storage: &dyn Storage, messages: &[String], conditions: &mut [StopCondition]) {
+    for condition in conditions.iter_mut() {
+        let hits = messages.iter().filter(|m| condition.message.eq(*m)).count() as i64;
+        if hits > 0 {
+            condition.hits += hits;
+            storage
+                .set_hits_for_task(condition.task, condition.hits)
+                .unwrap_or_default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    fn insert_deployment(storage: &dyn Storage, message: &str, occurrence: i64) -> Deployment {
+        let mut deployment = Deployment::new("test", "alice");
+        deployment.tasks.push(Task::new(
+            None,
+            None,
+            StopIfTrue,
+            serde_json::to_string(&(message, occurrence)).unwrap(),
+            true,
+        ));
+        let id = storage.insert_deployment(&deployment).unwrap();
+        storage
+            .get_running_deployments()
+            .unwrap()
+            .into_iter()
+            .find(|d| d.id == Some(id))
+            .unwrap()
+    }
+
+    fn get_messages(messages: &[&str]) -> Vec<String> {
+        messages.iter().map(|m| String::from(*m)).collect()
+    }
+
+    #[test]
+    fn counts_and_stores_hits_of_stop_conditions() {
+        let storage = MemoryStorage::default();
+        let deployment = insert_deployment(&storage, "benchmark done", 2);
+        let mut conditions = get_stop_conditions(&deployment);
+        count_hits(
+            &storage,
+            &get_messages(&["benchmark done", "started"]),
+            &mut conditions,
+        );
+        assert_eq!(conditions[0].hits, 1);
+        assert!(conditions[0].hits < conditions[0].occurrence);
+        count_hits(
+            &storage,
+            &get_messages(&["benchmark done"]),
+            &mut conditions,
+        );
+        assert_eq!(conditions[0].hits, conditions[0].occurrence);
+        let tasks = storage
+            .get_tasks_by_deployment(deployment.id.unwrap())
+            .unwrap();
+        assert_eq!(tasks[0].hits, 2);
+    }
+
+    #[test]
+    fn continues_with_the_stored_hits() {
+        let storage = MemoryStorage::default();
+        let deployment = insert_deployment(&storage, "benchmark done", 3);
+        let task = deployment.tasks[0].id.unwrap();
+        storage.set_hits_for_task(task, 2).unwrap();
+        let deployment = storage.get_running_deployments().unwrap().remove(0);
+        let mut conditions = get_stop_conditions(&deployment);
+        count_hits(
+            &storage,
+            &get_messages(&["benchmark done"]),
+            &mut conditions,
+        );
+        assert_eq!(conditions[0].hits, 3);
+    }
+
+    #[test]
+    fn is_due_once_the_deployment_ran_long_enough() {
+        let now = Utc::now().naive_utc();
+        let trigger = Trigger::After(60);
+        assert!(!is_due(&trigger, &[], None));
+        assert!(!is_due(&trigger, &[], Some(now)));
+        assert!(is_due(
+            &trigger,
+            &[],
+            Some(now - chrono::Duration::seconds(61))
+        ));
+    }
+
+    #[test]
+    fn is_due_when_the_message_was_logged() {
+        let trigger = Trigger::LogEvent(String::from("ready"));
+        assert!(!is_due(&trigger, &get_messages(&["starting"]), None));
+        assert!(is_due(
+            &trigger,
+            &get_messages(&["starting", "ready"]),
+            None
+        ));
+        assert!(!is_due(&Trigger::OnStop, &get_messages(&["ready"]), None));
+    }
 }
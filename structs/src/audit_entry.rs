@@ -0,0 +1,38 @@
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+/// Something a user or the cluster-manager itself changed on the testbed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub timestamp: NaiveDateTime,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub node: Option<String>,
+    pub success: bool,
+}
+
+impl AuditEntry {
+    /// Reads the columns timestamp, actor, action, target, node and success in this order.
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        AuditEntry {
+            timestamp: row.get(0).unwrap(),
+            actor: row.get(1).unwrap(),
+            action: row.get(2).unwrap(),
+            target: row.get(3).unwrap(),
+            node: row.get(4).unwrap(),
+            success: row.get(5).unwrap(),
+        }
+    }
+}
+
+/// Restricts the audit log to the entries matching every given field.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AuditFilter {
+    pub user: Option<String>,
+    pub node: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
@@ -0,0 +1,41 @@
+use crate::audit_entry::AuditEntry;
+use crate::utils::{get_cell_content_of_date, get_cell_content_of_option};
+use chrono::NaiveDateTime;
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditRow {
+    pub timestamp: Option<NaiveDateTime>,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub node: Option<String>,
+    pub success: bool,
+}
+
+impl AuditRow {
+    #[must_use]
+    pub fn new(entry: AuditEntry) -> Self {
+        AuditRow {
+            timestamp: Some(entry.timestamp),
+            actor: entry.actor,
+            action: entry.action,
+            target: entry.target,
+            node: entry.node,
+            success: entry.success,
+        }
+    }
+
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(get_cell_content_of_date(&self.timestamp));
+        cells.push(Cell::new(&self.actor));
+        cells.push(Cell::new(&self.action));
+        cells.push(Cell::new(&self.target));
+        cells.push(get_cell_content_of_option(&self.node));
+        cells.push(Cell::new(if self.success { "ok" } else { "failed" }));
+        cells
+    }
+}
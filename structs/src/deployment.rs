@@ -1,6 +1,11 @@
+use crate::fault::FaultInjection;
+use crate::remote_command::RemoteCommand;
 use crate::service::Service;
+use crate::state::{get_state_of_row, State};
 use crate::task::Task;
-use crate::task::Type::StopIfTrue;
+use crate::task::Type;
+use crate::task::Type::{InjectFault, RunCommand, SnapshotResults, StopIfTrue};
+use crate::utils::get_random_name;
 use chrono::{NaiveDateTime, Utc};
 use rusqlite::Row;
 use serde::{Deserialize, Serialize};
@@ -15,30 +20,55 @@ pub struct Deployment {
     pub start: NaiveDateTime,
     pub end: Option<NaiveDateTime>,
     pub tasks: Vec<Task>,
+    pub failure: Option<String>,
+    pub state: Option<State>,
+    pub rerun_of: Option<i64>,
+    /// how the storage of nodes running `on_device` images is cleaned up after the deployment
+    pub local_storage: Option<Type>,
 }
 
 impl Deployment {
     #[must_use]
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, owner: &str) -> Self {
         Deployment {
             id: None,
             name: String::from(name),
             services: Vec::new(),
-            owner: String::from("aresch"),
+            owner: String::from(owner),
             start: Utc::now().naive_local(),
             end: None,
             tasks: Vec::new(),
+            failure: None,
+            state: None,
+            rerun_of: None,
+            local_storage: None,
         }
     }
 
     #[must_use]
-    pub fn from_yaml(name: &str, yaml: &Yaml) -> Self {
+    pub fn for_image(image: &str, node: Option<String>, owner: &str) -> Self {
+        let mut deployment = Deployment::new(get_random_name().as_str(), owner);
+        let mut service = Service::new(
+            get_random_name().as_str(),
+            image,
+            get_random_name().as_str(),
+        );
+        service.preferred_node = node;
+        deployment.services.push(service);
+        deployment
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if a service, a command, a fault or the local storage cleanup of the
+    /// deployment is invalid
+    pub fn from_yaml(name: &str, owner: &str, yaml: &Yaml) -> Result<Self, String> {
         let mut services: Vec<Service> = Vec::new();
         for (n, data) in yaml["services"].as_hash().unwrap().iter() {
             services.push(Service::from_yaml(
                 n.as_str().unwrap(),
                 data.as_hash().unwrap(),
-            ));
+            )?);
         }
         let mut tasks = Vec::new();
         if let Some (tasks_yaml) = yaml["stop"].as_hash() {
@@ -60,15 +90,55 @@ impl Deployment {
                 }
             }
         }
-        Deployment {
+        if let Some(commands) = yaml["commands"].as_vec() {
+            for command in commands {
+                let command = RemoteCommand::from_yaml(command)?;
+                tasks.push(Task::new(
+                    None,
+                    None,
+                    RunCommand,
+                    serde_json::to_string(&command).unwrap(),
+                    command.is_during_deployment(),
+                ));
+            }
+        }
+        if let Some(faults) = yaml["faults"].as_vec() {
+            for fault in faults {
+                let fault = FaultInjection::from_yaml(fault)?;
+                tasks.push(Task::new(
+                    None,
+                    None,
+                    InjectFault,
+                    serde_json::to_string(&fault).unwrap(),
+                    true,
+                ));
+            }
+        }
+        if let Some(interval) = yaml["snapshots"].as_i64() {
+            tasks.push(Task::new(
+                None,
+                None,
+                SnapshotResults,
+                interval.to_string(),
+                true,
+            ));
+        }
+        Ok(Deployment {
             id: None,
             name: String::from(name),
             services,
-            owner: String::from("aresch"),
+            owner: String::from(owner),
             start: Utc::now().naive_local(),
             end: None,
             tasks,
-        }
+            failure: None,
+            state: None,
+            rerun_of: None,
+            local_storage: yaml["local-storage"]
+                .as_str()
+                .map(Type::parse_local_storage_cleanup)
+                .transpose()?,
+        })
     }
 
     #[must_use]
@@ -81,6 +151,10 @@ impl Deployment {
             start: row.get(3).unwrap(),
             end: row.get(4).unwrap(),
             tasks: Vec::new(),
+            failure: row.get(5).unwrap_or(None),
+            state: get_state_of_row(row, 6),
+            rerun_of: row.get(8).unwrap_or(None),
+            local_storage: None,
         }
     }
 
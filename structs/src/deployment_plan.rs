@@ -0,0 +1,27 @@
+use crate::plan_row::PlanRow;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeploymentPlan {
+    pub name: String,
+    pub owner: String,
+    pub replicas: Vec<PlanRow>,
+    pub problems: Vec<String>,
+}
+
+impl DeploymentPlan {
+    #[must_use]
+    pub fn new(name: &str, owner: &str) -> Self {
+        DeploymentPlan {
+            name: String::from(name),
+            owner: String::from(owner),
+            replicas: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_feasible(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
@@ -1,5 +1,5 @@
 use crate::deployment::Deployment;
-use crate::utils::get_cell_content_of_date;
+use crate::utils::{get_cell_content_of_date, get_cell_content_of_option};
 use chrono::NaiveDateTime;
 use prettytable::Cell;
 use serde::{Deserialize, Serialize};
@@ -12,6 +12,9 @@ pub struct DeploymentRow {
     pub end: Option<NaiveDateTime>,
     pub owner: String,
     pub services: Option<usize>,
+    pub failure: Option<String>,
+    pub state: Option<String>,
+    pub rerun_of: Option<i64>,
 }
 
 impl DeploymentRow {
@@ -24,6 +27,9 @@ impl DeploymentRow {
             end: deployment.end,
             owner: deployment.owner,
             services,
+            failure: deployment.failure,
+            state: deployment.state.map(|s| String::from(s.get_name())),
+            rerun_of: deployment.rerun_of,
         }
     }
 
@@ -39,6 +45,12 @@ impl DeploymentRow {
             Some(number) => cells.push(Cell::new(&number.to_string())),
             None => cells.push(Cell::new("\u{2014}")),
         }
+        cells.push(get_cell_content_of_option(&self.state));
+        cells.push(get_cell_content_of_option(&self.failure));
+        match self.rerun_of {
+            Some(id) => cells.push(Cell::new(&id.to_string())),
+            None => cells.push(Cell::new("\u{2014}")),
+        }
         cells
     }
 }
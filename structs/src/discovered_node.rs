@@ -0,0 +1,26 @@
+use crate::utils::get_cell_content_of_option;
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+/// A device which asked for an address or a boot file but is not part of the cluster yet.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DiscoveredNode {
+    pub mac_address: Option<String>,
+    pub ipv4_address: Option<String>,
+    pub serial_number: Option<String>,
+    pub raspberry_pi: bool,
+    pub last_seen: Option<String>,
+}
+
+impl DiscoveredNode {
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(get_cell_content_of_option(&self.mac_address));
+        cells.push(get_cell_content_of_option(&self.ipv4_address));
+        cells.push(get_cell_content_of_option(&self.serial_number));
+        cells.push(Cell::new(if self.raspberry_pi { "yes" } else { "no" }));
+        cells.push(get_cell_content_of_option(&self.last_seen));
+        cells
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Fault {
+    PowerCycle,
+    PowerOff,
+    /// name of the process which is killed with SIGKILL
+    KillProcess(String),
+    /// impairs the default interface of the node with `tc netem` for `duration` seconds
+    Network {
+        delay: Option<String>,
+        loss: Option<String>,
+        rate: Option<String>,
+        duration: i64,
+    },
+}
+
+/// A failure which is injected into every replica of a service, `at` seconds after all services of
+/// the deployment are running.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FaultInjection {
+    pub service: String,
+    pub at: i64,
+    pub fault: Fault,
+}
+
+impl FaultInjection {
+    /// # Errors
+    ///
+    /// Will return `Err` if the service, the time or the fault is missing or invalid
+    pub fn from_yaml(yaml: &Yaml) -> Result<FaultInjection, String> {
+        let service = yaml["service"]
+            .as_str()
+            .ok_or("fault needs a service to inject into")?;
+        let at = yaml["at"].as_i64().ok_or("fault needs a time (at)")?;
+        if at < 0 {
+            return Err(format!(
+                "fault for {} can not happen before the start",
+                service
+            ));
+        }
+        let fault = if let Some(power) = yaml["power"].as_str() {
+            match power {
+                "cycle" => Fault::PowerCycle,
+                "off" => Fault::PowerOff,
+                _ => {
+                    return Err(format!(
+                        "power {} is not supported, use cycle or off",
+                        power
+                    ))
+                }
+            }
+        } else if let Some(process) = yaml["kill"].as_str() {
+            Fault::KillProcess(String::from(process))
+        } else if !yaml["network"].is_badvalue() {
+            let network = &yaml["network"];
+            let duration = network["duration"]
+                .as_i64()
+                .ok_or("network fault needs a duration")?;
+            if duration <= 0 {
+                return Err(String::from("network fault needs a positive duration"));
+            }
+            let fault = Fault::Network {
+                delay: parse_netem_value(network, "delay", 2, &DELAY_UNITS)?,
+                loss: parse_netem_value(network, "loss", 1, &LOSS_UNITS)?,
+                rate: parse_netem_value(network, "rate", 1, &RATE_UNITS)?,
+                duration,
+            };
+            if fault.get_netem_parameters().is_empty() {
+                return Err(String::from(
+                    "network fault needs at least one of delay, loss or rate",
+                ));
+            }
+            fault
+        } else {
+            return Err(format!(
+                "fault for {} needs one of power, kill or network",
+                service
+            ));
+        };
+        Ok(FaultInjection {
+            service: String::from(service),
+            at,
+            fault,
+        })
+    }
+}
+
+const DELAY_UNITS: [&str; 3] = ["us", "ms", "s"];
+const LOSS_UNITS: [&str; 1] = ["%"];
+const RATE_UNITS: [&str; 10] = [
+    "kbit", "mbit", "gbit", "tbit", "bit", "kbps", "mbps", "gbps", "tbps", "bps",
+];
+
+/// Reads a value for `tc netem`, every part of it has to be a number followed by one of `units`.
+/// The value ends up in a shell on the node, so nothing else may pass.
+fn parse_netem_value(
+    network: &Yaml,
+    name: &str,
+    parts: usize,
+    units: &[&str],
+) -> Result<Option<String>, String> {
+    let value = match network[name].as_str() {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let words = value.split_whitespace().collect::<Vec<&str>>();
+    let valid = !words.is_empty()
+        && words.len() <= parts
+        && words.iter().all(|word| {
+            units.iter().any(|unit| {
+                word.strip_suffix(unit).map_or(false, |number| {
+                    !number.is_empty()
+                        && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+                        && number.parse::<f64>().is_ok()
+                })
+            })
+        });
+    if valid {
+        Ok(Some(words.join(" ")))
+    } else {
+        Err(format!(
+            "network fault has the invalid {} {}, use a number followed by one of {}",
+            name,
+            value,
+            units.join(", ")
+        ))
+    }
+}
+
+impl Fault {
+    /// Returns the arguments for `tc qdisc ... netem`, empty for all other faults.
+    #[must_use]
+    pub fn get_netem_parameters(&self) -> String {
+        match self {
+            Fault::Network {
+                delay, loss, rate, ..
+            } => {
+                let mut parameters = Vec::new();
+                for (name, value) in &[("delay", delay), ("loss", loss), ("rate", rate)] {
+                    if let Some(value) = value {
+                        parameters.push(format!("{} {}", name, value));
+                    }
+                }
+                parameters.join(" ")
+            }
+            _ => String::new(),
+        }
+    }
+}
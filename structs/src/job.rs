@@ -0,0 +1,58 @@
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+/// A line of output of a job, `status` is `None` for plain information.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobStep {
+    pub id: i64,
+    pub timestamp: NaiveDateTime,
+    pub message: String,
+    pub status: Option<bool>,
+}
+
+/// Work the server runs in the background on behalf of a user, e.g. a deployment.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub owner: String,
+    pub deployment: Option<i64>,
+    pub created: NaiveDateTime,
+    pub finished: Option<NaiveDateTime>,
+    pub success: Option<bool>,
+    pub steps: Vec<JobStep>,
+}
+
+impl JobStep {
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        JobStep {
+            id: row.get(0).unwrap(),
+            timestamp: row.get(2).unwrap(),
+            message: row.get(3).unwrap(),
+            status: row.get(4).unwrap(),
+        }
+    }
+}
+
+impl Job {
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        Job {
+            id: row.get(0).unwrap(),
+            kind: row.get(1).unwrap(),
+            owner: row.get(2).unwrap(),
+            deployment: row.get(3).unwrap(),
+            created: row.get(4).unwrap(),
+            finished: row.get(5).unwrap(),
+            success: row.get(6).unwrap(),
+            steps: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+}
@@ -1,24 +1,48 @@
 pub mod architecture;
 pub mod arm_preamble;
+pub mod audit_entry;
+pub mod audit_row;
 pub mod bootconfig;
 pub mod configuration;
 pub mod deployment;
+pub mod deployment_plan;
 pub mod deployment_row;
+pub mod discovered_node;
+pub mod fault;
 pub mod image;
 pub mod image_row;
 pub mod imagefile;
+pub mod job;
 pub mod logsource;
+pub mod manifest;
 pub mod mountpoint;
 pub mod node;
+pub mod node_definition;
 pub mod node_row;
+pub mod node_state;
+pub mod node_state_transition;
+pub mod node_state_transition_row;
 pub mod partition;
+pub mod plan_row;
+pub mod placement;
 pub mod post_provisioner;
 pub mod power_action;
 pub mod power_action_set;
 pub mod preamble;
+pub mod remote_command;
+pub mod rerun_request;
 pub mod provisioner;
+pub mod reservation;
+pub mod reservation_request;
+pub mod reservation_row;
 pub mod service;
 pub mod service_row;
+pub mod state;
+pub mod state_transition;
+pub mod state_transition_row;
 pub mod task;
+pub mod timeline_event;
+pub mod user;
+pub mod user_row;
 pub mod utils;
 pub mod x86_preamble;
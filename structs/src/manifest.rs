@@ -0,0 +1,121 @@
+use crate::architecture::Architecture;
+use crate::deployment::Deployment;
+use crate::node::Node;
+use crate::service::Service;
+use crate::state_transition::StateTransition;
+use crate::task::{Task, Type};
+use crate::timeline_event::TimelineEvent;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Everything needed to tell what exactly ran in a deployment, stored as `manifest.json` next to
+/// its results.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub cluster_manager_version: String,
+    pub id: i64,
+    pub name: String,
+    pub owner: String,
+    pub rerun_of: Option<i64>,
+    /// the deployment as it was submitted
+    pub spec: Option<Value>,
+    pub deployed: NaiveDateTime,
+    pub stopped: NaiveDateTime,
+    pub services: Vec<ServiceManifest>,
+    pub tasks: Vec<TaskManifest>,
+    /// state changes of the deployment and its services, e.g. when they booted or why they stopped
+    pub transitions: Vec<StateTransition>,
+    /// what was done to the deployment while it ran, e.g. injected faults
+    pub timeline: Vec<TimelineEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceManifest {
+    pub id: Option<i64>,
+    pub name: String,
+    pub hostname: String,
+    pub image: String,
+    pub image_checksum: Option<String>,
+    pub ipv4_address: Option<String>,
+    pub node: Option<NodeManifest>,
+    pub start: NaiveDateTime,
+    pub end: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeManifest {
+    pub id: String,
+    pub architecture: Architecture,
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskManifest {
+    pub task_type: Type,
+    pub service: Option<String>,
+    pub parameters: String,
+    pub during_deployment: bool,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    pub executed: bool,
+    pub success: Option<bool>,
+}
+
+impl Manifest {
+    #[must_use]
+    pub fn new(deployment: &Deployment, version: &str, stopped: NaiveDateTime) -> Self {
+        Manifest {
+            cluster_manager_version: String::from(version),
+            id: deployment.id.unwrap(),
+            name: deployment.name.clone(),
+            owner: deployment.owner.clone(),
+            rerun_of: deployment.rerun_of,
+            spec: None,
+            deployed: deployment.start,
+            stopped,
+            services: Vec::new(),
+            tasks: Vec::new(),
+            transitions: Vec::new(),
+            timeline: Vec::new(),
+        }
+    }
+}
+
+impl ServiceManifest {
+    #[must_use]
+    pub fn new(service: &Service, node: Option<&Node>) -> Self {
+        ServiceManifest {
+            id: service.id,
+            name: service.name.clone(),
+            hostname: service.hostname.clone(),
+            image: service.image.clone(),
+            image_checksum: service.image_checksum.clone(),
+            ipv4_address: service.ipv4_address.clone(),
+            node: node.map(|n| NodeManifest {
+                id: n.id.clone(),
+                architecture: n.architecture.clone(),
+                labels: n.labels.clone(),
+            }),
+            start: service.start,
+            end: service.end,
+        }
+    }
+}
+
+impl TaskManifest {
+    #[must_use]
+    pub fn new(task: &Task) -> Self {
+        TaskManifest {
+            task_type: task.task_type,
+            service: task.service.as_ref().map(|s| s.name.clone()),
+            parameters: task.parameters.clone(),
+            during_deployment: task.during_deployment,
+            start: task.start,
+            end: task.end,
+            executed: task.executed,
+            success: task.success,
+        }
+    }
+}
@@ -1,8 +1,7 @@
 use crate::architecture::Architecture;
 use crate::logsource::LogSource;
-use config::Value;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Node {
@@ -15,62 +14,23 @@ pub struct Node {
     pub log_inputs: Vec<LogSource>,
     pub architecture: Architecture,
     pub pxe: bool,
+    pub labels: BTreeMap<String, String>,
 }
 
 impl Node {
     #[must_use]
-    pub fn from_config(
-        id: String,
-        hash: &HashMap<String, Value>,
-        log_inputs: Vec<LogSource>,
-    ) -> Self {
-        Node {
-            id,
-            name: hash.get("name").unwrap().to_owned().into_str().unwrap(),
-            tftp_prefix: hash
-                .get("tftp-prefix")
-                .unwrap()
-                .to_owned()
-                .into_str()
-                .unwrap(),
-            mac_address: hash
-                .get("mac-address")
-                .unwrap()
-                .to_owned()
-                .into_str()
-                .unwrap(),
-            ipv4_address: hash
-                .get("ipv4-address")
-                .unwrap()
-                .to_owned()
-                .into_str()
-                .unwrap(),
-            serial_number: hash
-                .get("serial-number")
-                .unwrap()
-                .to_owned()
-                .into_str()
-                .unwrap(),
-            log_inputs,
-            architecture: Architecture::parse(
-                &hash
-                    .get("architecture")
-                    .unwrap()
-                    .to_owned()
-                    .into_str()
-                    .unwrap(),
-            )
-            .unwrap(),
-            pxe: if hash.contains_key("pxe") {
-                hash.get("pxe")
-                    .unwrap()
-                    .to_owned()
-                    .into_bool()
-                    .unwrap_or(false)
-            } else {
-                false
-            },
-        }
+    pub fn matches_labels(&self, selector: &BTreeMap<String, String>) -> bool {
+        selector
+            .iter()
+            .all(|(key, value)| self.labels.get(key).map_or(false, |v| v.eq(value)))
+    }
+
+    #[must_use]
+    pub fn count_matching_labels(&self, preferences: &BTreeMap<String, String>) -> usize {
+        preferences
+            .iter()
+            .filter(|(key, value)| self.labels.get(*key).map_or(false, |v| v.eq(*value)))
+            .count()
     }
 }
 
@@ -0,0 +1,100 @@
+use crate::architecture::Architecture;
+use crate::logsource::LogSource;
+use crate::node::Node;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A node as it is written in config.yml or sent to the server. Fields every node needs are
+/// optional as well, so their absence can be reported instead of failing to parse.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct NodeDefinition {
+    pub name: Option<String>,
+    pub tftp_prefix: Option<String>,
+    pub mac_address: Option<String>,
+    pub ipv4_address: Option<String>,
+    pub serial_number: Option<String>,
+    pub architecture: Option<String>,
+    #[serde(default)]
+    pub pxe: bool,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub log_inputs: LogInputs,
+    #[serde(default)]
+    pub power: PowerCommands,
+    pub default_os: Option<String>,
+    pub default_user: Option<String>,
+    pub storage_device: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LogInputs {
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub serial: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PowerCommands {
+    pub on: Option<String>,
+    pub off: Option<String>,
+    pub reboot: Option<String>,
+}
+
+impl NodeDefinition {
+    /// Returns the keys of the fields every node needs but this one does not have.
+    #[must_use]
+    pub fn get_missing_fields(&self) -> Vec<&'static str> {
+        [
+            ("name", &self.name),
+            ("tftp-prefix", &self.tftp_prefix),
+            ("mac-address", &self.mac_address),
+            ("ipv4-address", &self.ipv4_address),
+            ("serial-number", &self.serial_number),
+            ("architecture", &self.architecture),
+        ]
+        .iter()
+        .filter(|(_, value)| value.is_none())
+        .map(|(key, _)| *key)
+        .collect()
+    }
+
+    #[must_use]
+    pub fn get_log_sources(&self) -> Vec<LogSource> {
+        let hosts = self.log_inputs.hosts.iter().cloned().map(LogSource::host);
+        let serial = self
+            .log_inputs
+            .serial
+            .iter()
+            .cloned()
+            .map(LogSource::serial);
+        hosts.chain(serial).collect()
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if a required field is missing or the architecture is unknown
+    pub fn to_node(&self, id: &str) -> Result<Node, String> {
+        let missing = self.get_missing_fields();
+        if !missing.is_empty() {
+            return Err(format!("{} misses {}", id, missing.join(", ")));
+        }
+        let architecture = self.architecture.clone().unwrap_or_default();
+        let field = |value: &Option<String>| value.clone().unwrap_or_default();
+        Ok(Node {
+            id: String::from(id),
+            name: field(&self.name),
+            tftp_prefix: field(&self.tftp_prefix),
+            mac_address: field(&self.mac_address),
+            serial_number: field(&self.serial_number),
+            ipv4_address: field(&self.ipv4_address),
+            log_inputs: self.get_log_sources(),
+            architecture: Architecture::parse(&architecture)
+                .map_err(|_| format!("{} has the unknown architecture {}", id, architecture))?,
+            pxe: self.pxe,
+            labels: self.labels.clone(),
+        })
+    }
+}
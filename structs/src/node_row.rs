@@ -1,7 +1,12 @@
 use crate::node::Node;
-use crate::utils::get_cell_content_of_string;
+use crate::node_state::NodeStateEntry;
+use crate::utils::{
+    get_cell_content_of_date, get_cell_content_of_option, get_cell_content_of_string,
+};
+use chrono::NaiveDateTime;
 use prettytable::Cell;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NodeRow {
@@ -13,7 +18,11 @@ pub struct NodeRow {
     pub status: Option<bool>,
     pub hostname: Option<String>,
     pub ipv4_address: Option<String>,
-    pub usable: bool,
+    pub labels: BTreeMap<String, String>,
+    pub state: Option<String>,
+    /// why the node is in its state, e.g. because it did not boot
+    pub reason: Option<String>,
+    pub expected_return: Option<NaiveDateTime>,
 }
 
 impl NodeRow {
@@ -23,8 +32,16 @@ impl NodeRow {
         status: Option<bool>,
         hostname: Option<String>,
         ipv4_address: Option<String>,
-        usable: bool,
+        state: Option<NodeStateEntry>,
     ) -> Self {
+        let (state, reason, expected_return) = match state {
+            Some(entry) => (
+                Some(String::from(entry.state.get_name())),
+                entry.reason,
+                entry.expected_return,
+            ),
+            None => (None, None, None),
+        };
         NodeRow {
             id: node.id,
             name: node.name,
@@ -34,7 +51,10 @@ impl NodeRow {
             status,
             hostname,
             ipv4_address,
-            usable,
+            labels: node.labels,
+            state,
+            reason,
+            expected_return,
         }
     }
 
@@ -56,7 +76,21 @@ impl NodeRow {
                 value.as_ref().unwrap_or(&String::from("\u{2014}")),
             ));
         }
-        cells.push(Cell::new(if self.usable { "yes" } else { "no" }));
+        if self.labels.is_empty() {
+            cells.push(Cell::new("\u{2014}"));
+        } else {
+            cells.push(get_cell_content_of_string(
+                &self
+                    .labels
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ));
+        }
+        cells.push(get_cell_content_of_option(&self.state));
+        cells.push(get_cell_content_of_option(&self.reason));
+        cells.push(get_cell_content_of_date(&self.expected_return));
         cells
     }
 }
@@ -0,0 +1,78 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Names of all node states, e.g. for the possible values of a command line argument.
+pub const NODE_STATES: [&str; 6] = [
+    "available",
+    "busy",
+    "reserved",
+    "maintenance",
+    "failed",
+    "unknown",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum NodeState {
+    Available,
+    Busy,
+    Reserved,
+    Maintenance,
+    Failed,
+    Unknown,
+}
+
+impl NodeState {
+    #[must_use]
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            NodeState::Available => "available",
+            NodeState::Busy => "busy",
+            NodeState::Reserved => "reserved",
+            NodeState::Maintenance => "maintenance",
+            NodeState::Failed => "failed",
+            NodeState::Unknown => "unknown",
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if `name` is not a known node state
+    pub fn parse(name: &str) -> Result<NodeState, &'static str> {
+        match name {
+            "available" => Ok(NodeState::Available),
+            "busy" => Ok(NodeState::Busy),
+            "reserved" => Ok(NodeState::Reserved),
+            "maintenance" => Ok(NodeState::Maintenance),
+            "failed" => Ok(NodeState::Failed),
+            "unknown" => Ok(NodeState::Unknown),
+            _ => Err("not a valid node state"),
+        }
+    }
+
+    /// Nodes in these states are only released by a user, the health check leaves them alone.
+    #[must_use]
+    pub fn is_held(&self) -> bool {
+        matches!(self, NodeState::Maintenance | NodeState::Failed)
+    }
+}
+
+/// The stored state of a node.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeStateEntry {
+    pub state: NodeState,
+    /// why the node is in its state, e.g. the note of a node in maintenance
+    pub reason: Option<String>,
+    /// when a node in maintenance is expected to be usable again
+    pub expected_return: Option<NaiveDateTime>,
+}
+
+impl NodeStateEntry {
+    #[must_use]
+    pub fn new(state: NodeState, reason: Option<String>) -> Self {
+        NodeStateEntry {
+            state,
+            reason,
+            expected_return: None,
+        }
+    }
+}
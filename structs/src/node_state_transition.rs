@@ -0,0 +1,28 @@
+use crate::node_state::NodeState;
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeStateTransition {
+    pub node: String,
+    pub from: Option<NodeState>,
+    pub to: NodeState,
+    pub timestamp: NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+impl NodeStateTransition {
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        let from: Option<String> = row.get(2).unwrap();
+        let to: String = row.get(3).unwrap();
+        NodeStateTransition {
+            node: row.get(1).unwrap(),
+            from: from.and_then(|s| NodeState::parse(&s).ok()),
+            to: NodeState::parse(&to).unwrap_or(NodeState::Unknown),
+            timestamp: row.get(4).unwrap(),
+            reason: row.get(5).unwrap(),
+        }
+    }
+}
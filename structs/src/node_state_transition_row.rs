@@ -0,0 +1,37 @@
+use crate::node_state_transition::NodeStateTransition;
+use crate::utils::{get_cell_content_of_date, get_cell_content_of_option};
+use chrono::NaiveDateTime;
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeStateTransitionRow {
+    pub node: String,
+    pub from: Option<String>,
+    pub to: String,
+    pub timestamp: Option<NaiveDateTime>,
+    pub reason: Option<String>,
+}
+
+impl NodeStateTransitionRow {
+    #[must_use]
+    pub fn new(transition: NodeStateTransition) -> Self {
+        NodeStateTransitionRow {
+            node: transition.node,
+            from: transition.from.map(|s| String::from(s.get_name())),
+            to: String::from(transition.to.get_name()),
+            timestamp: Some(transition.timestamp),
+            reason: transition.reason,
+        }
+    }
+
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(get_cell_content_of_date(&self.timestamp));
+        cells.push(get_cell_content_of_option(&self.from));
+        cells.push(Cell::new(&self.to));
+        cells.push(get_cell_content_of_option(&self.reason));
+        cells
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Placement {
+    Spread { label: String },
+    AntiAffinity { service: String, label: String },
+    Pack { label: String },
+}
+
+impl Placement {
+    #[must_use]
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Placement::Spread { .. } => "spread",
+            Placement::AntiAffinity { .. } => "anti-affinity",
+            Placement::Pack { .. } => "pack",
+        }
+    }
+
+    #[must_use]
+    pub fn get_label(&self) -> &str {
+        match self {
+            Placement::Spread { label }
+            | Placement::AntiAffinity { label, .. }
+            | Placement::Pack { label } => label,
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the policy is unknown or a required key is missing
+    pub fn from_yaml(hash: &Hash) -> Result<Placement, String> {
+        let get = |key: &str| {
+            hash.get(&Yaml::from_str(key))
+                .and_then(Yaml::as_str)
+                .map(String::from)
+        };
+        let label = get("label").ok_or("placement needs a label, e.g. rack")?;
+        match get("policy").as_deref() {
+            Some("spread") => Ok(Placement::Spread { label }),
+            Some("anti-affinity") => match get("service") {
+                Some(service) => Ok(Placement::AntiAffinity { service, label }),
+                None => Err(String::from("anti-affinity needs a service")),
+            },
+            Some("pack") => Ok(Placement::Pack { label }),
+            Some(policy) => Err(format!("placement policy {} is not supported", policy)),
+            None => Err(String::from("placement needs a policy")),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::utils::{get_cell_content_of_option, get_cell_content_of_string, vec_to_string};
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlanRow {
+    pub service: String,
+    pub hostname: String,
+    pub node: String,
+    pub images: Vec<String>,
+    pub replaced_nfsroot: Option<String>,
+    pub rationale: String,
+}
+
+impl PlanRow {
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(Cell::new(&self.service));
+        cells.push(get_cell_content_of_string(&self.hostname));
+        cells.push(Cell::new(&self.node));
+        cells.push(Cell::new(&vec_to_string(&self.images, false)));
+        cells.push(get_cell_content_of_option(&self.replaced_nfsroot));
+        cells.push(get_cell_content_of_string(&self.rationale));
+        cells
+    }
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// seconds after all services of the deployment are running
+    After(i64),
+    /// the first time a node of the deployment logs this message
+    LogEvent(String),
+    /// before the results of the deployment are collected
+    OnStop,
+}
+
+/// A shell command which is run over ssh on every replica of a service.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteCommand {
+    pub service: String,
+    pub command: String,
+    pub trigger: Trigger,
+}
+
+impl RemoteCommand {
+    /// # Errors
+    ///
+    /// Will return `Err` if the service, the command or the trigger is missing
+    pub fn from_yaml(yaml: &Yaml) -> Result<RemoteCommand, String> {
+        let service = yaml["service"]
+            .as_str()
+            .ok_or("command needs a service to run on")?;
+        let command = yaml["run"]
+            .as_str()
+            .ok_or("command needs something to run")?;
+        let trigger = if let Some(seconds) = yaml["after"].as_i64() {
+            Trigger::After(seconds)
+        } else if let Some(message) = yaml["on-log"].as_str() {
+            Trigger::LogEvent(String::from(message))
+        } else if yaml["on-stop"].as_bool().unwrap_or(false) {
+            Trigger::OnStop
+        } else {
+            return Err(format!(
+                "command {} needs one of after, on-log or on-stop",
+                command
+            ));
+        };
+        Ok(RemoteCommand {
+            service: String::from(service),
+            command: String::from(command),
+            trigger,
+        })
+    }
+
+    /// Commands triggered on stop run while the deployment is stopped, all others are watched
+    /// while it runs.
+    #[must_use]
+    pub fn is_during_deployment(&self) -> bool {
+        self.trigger.ne(&Trigger::OnStop)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::deployment::Deployment;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Changes to apply to a stored deployment before it is deployed again.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RerunRequest {
+    pub name: Option<String>,
+    /// image per service name
+    pub images: BTreeMap<String, String>,
+    /// preferred node per service name
+    pub nodes: BTreeMap<String, String>,
+}
+
+impl RerunRequest {
+    /// Turns the stored deployment `original` into a new run owned by `owner`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an override names a service the deployment does not have
+    pub fn apply(
+        &self,
+        original: i64,
+        owner: &str,
+        deployment: &mut Deployment,
+    ) -> Result<(), String> {
+        deployment.id = None;
+        deployment.owner = String::from(owner);
+        deployment.start = Utc::now().naive_local();
+        deployment.end = None;
+        deployment.failure = None;
+        deployment.state = None;
+        deployment.rerun_of = Some(original);
+        if let Some(name) = &self.name {
+            deployment.name = name.clone();
+        }
+        for (service, image) in &self.images {
+            match deployment.services.iter_mut().find(|s| s.name.eq(service)) {
+                Some(s) => {
+                    s.image = image.clone();
+                    s.architecture = None;
+                }
+                None => return Err(format!("deployment has no service {}", service)),
+            }
+        }
+        for (service, node) in &self.nodes {
+            match deployment.services.iter_mut().find(|s| s.name.eq(service)) {
+                Some(s) => s.preferred_node = Some(node.clone()),
+                None => return Err(format!("deployment has no service {}", service)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits an override like `web=debian-11` into service name and value.
+///
+/// # Errors
+///
+/// Will return `Err` if `assignment` does not contain a `=`
+pub fn parse_override(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((service, value)) if !service.is_empty() && !value.is_empty() => {
+            Ok((String::from(service), String::from(value)))
+        }
+        _ => Err(format!("{} is not of the form service=value", assignment)),
+    }
+}
@@ -0,0 +1,54 @@
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reservation {
+    pub id: Option<i64>,
+    pub owner: String,
+    pub nodes: Vec<String>,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub released: Option<NaiveDateTime>,
+}
+
+impl Reservation {
+    #[must_use]
+    pub fn new(owner: &str, nodes: Vec<String>, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        Reservation {
+            id: None,
+            owner: String::from(owner),
+            nodes,
+            start,
+            end,
+            released: None,
+        }
+    }
+
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        Reservation {
+            id: row.get(0).unwrap(),
+            owner: row.get(1).unwrap(),
+            nodes: Vec::new(),
+            start: row.get(2).unwrap(),
+            end: row.get(3).unwrap(),
+            released: row.get(4).unwrap(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_active_at(&self, time: &NaiveDateTime) -> bool {
+        self.released.is_none() && self.start.le(time) && self.end.gt(time)
+    }
+
+    #[must_use]
+    pub fn overlaps(&self, start: &NaiveDateTime, end: &NaiveDateTime) -> bool {
+        self.released.is_none() && self.start.lt(end) && self.end.gt(start)
+    }
+
+    #[must_use]
+    pub fn contains_node(&self, id: &str) -> bool {
+        self.nodes.iter().any(|n| n.eq(id))
+    }
+}
@@ -0,0 +1,211 @@
+use crate::architecture::Architecture;
+use crate::utils::{get_current_user, parse_local_datetime};
+use chrono::{Duration, NaiveDateTime, Utc};
+use clap::{App, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReservationRequest {
+    pub owner: String,
+    pub nodes: Vec<String>,
+    pub count: Option<usize>,
+    pub architecture: Option<Architecture>,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl ReservationRequest {
+    #[must_use]
+    pub fn for_nodes(
+        owner: &str,
+        nodes: Vec<String>,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Self {
+        ReservationRequest {
+            owner: String::from(owner),
+            nodes,
+            count: None,
+            architecture: None,
+            start,
+            end,
+        }
+    }
+
+    #[must_use]
+    pub fn for_count(
+        owner: &str,
+        count: usize,
+        architecture: Option<Architecture>,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Self {
+        ReservationRequest {
+            owner: String::from(owner),
+            nodes: Vec::new(),
+            count: Some(count),
+            architecture,
+            start,
+            end,
+        }
+    }
+}
+
+/// The reservation subcommand of the cluster-manager and the client.
+#[must_use]
+pub fn add_reservation_subcommand() -> App<'static, 'static> {
+    App::new("reservation")
+        .about("run commands to manage your node reservations")
+        .subcommand(
+            App::new("list").about("list reservations").arg(
+                Arg::with_name("all")
+                    .short("a")
+                    .long("all")
+                    .help("show all reservations, past and released included")
+                    .takes_value(false),
+            ),
+        )
+        .subcommand(
+            App::new("add")
+                .about("reserve nodes for a time window")
+                .arg(
+                    Arg::with_name("node")
+                        .short("n")
+                        .long("node")
+                        .help("id of a node which should be reserved")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .short("c")
+                        .long("count")
+                        .help("number of nodes which should be reserved")
+                        .conflicts_with("node")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("architecture")
+                        .long("architecture")
+                        .help("architecture of the nodes which should be reserved")
+                        .requires("count")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("start of the reservation (YYYY-MM-DD HH:MM), defaults to now")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .help("end of the reservation (YYYY-MM-DD HH:MM)")
+                        .required_unless("hours")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("hours")
+                        .long("hours")
+                        .help("duration of the reservation in hours")
+                        .conflicts_with("until")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("extend")
+                .about("extend the reservation with the given id")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the reservation which should be extended")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .help("new end of the reservation (YYYY-MM-DD HH:MM)")
+                        .required_unless("hours")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("hours")
+                        .long("hours")
+                        .help("hours which should be added to the reservation")
+                        .conflicts_with("until")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("release")
+                .about("release the reservation with the given id")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("id of the reservation which should be released")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+}
+
+/// Builds the request of the current user from the arguments of reservation add.
+///
+/// # Errors
+///
+/// Will return `Err` if an argument is invalid or neither nodes nor a count are given
+pub fn parse_reservation_arguments(matches: &ArgMatches) -> Result<ReservationRequest, String> {
+    let start = match matches.value_of("from") {
+        Some(from) => parse_local_datetime(from)?,
+        None => Utc::now().naive_utc(),
+    };
+    let end = parse_end_argument(matches, &start)?;
+    if let Some(count) = matches.value_of("count") {
+        let count = count
+            .parse::<usize>()
+            .map_err(|_| String::from("Please provide a valid count"))?;
+        let architecture = match matches.value_of("architecture") {
+            Some(name) => Some(Architecture::parse(name)?),
+            None => None,
+        };
+        return Ok(ReservationRequest::for_count(
+            &get_current_user(),
+            count,
+            architecture,
+            start,
+            end,
+        ));
+    }
+    match matches.values_of("node") {
+        Some(nodes) => Ok(ReservationRequest::for_nodes(
+            &get_current_user(),
+            nodes.map(std::string::ToString::to_string).collect(),
+            start,
+            end,
+        )),
+        None => Err(String::from("Please provide nodes or a count")),
+    }
+}
+
+/// Returns the end given by `--until` or by `--hours` after `start`.
+///
+/// # Errors
+///
+/// Will return `Err` if the end is invalid or missing
+pub fn parse_end_argument(
+    matches: &ArgMatches,
+    start: &NaiveDateTime,
+) -> Result<NaiveDateTime, String> {
+    if let Some(hours) = matches.value_of("hours") {
+        return match hours.parse::<i64>() {
+            Ok(hours) => Ok(*start + Duration::hours(hours)),
+            Err(_) => Err(String::from("Please provide a valid number of hours")),
+        };
+    }
+    match matches.value_of("until") {
+        Some(until) => parse_local_datetime(until),
+        None => Err(String::from("Please provide an end of the reservation")),
+    }
+}
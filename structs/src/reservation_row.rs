@@ -0,0 +1,41 @@
+use crate::reservation::Reservation;
+use crate::utils::{get_cell_content_of_date, vec_to_string};
+use chrono::NaiveDateTime;
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReservationRow {
+    pub id: i64,
+    pub owner: String,
+    pub nodes: Vec<String>,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    pub released: Option<NaiveDateTime>,
+}
+
+impl ReservationRow {
+    #[must_use]
+    pub fn new(reservation: Reservation) -> Self {
+        ReservationRow {
+            id: reservation.id.unwrap(),
+            owner: reservation.owner,
+            nodes: reservation.nodes,
+            start: Some(reservation.start),
+            end: Some(reservation.end),
+            released: reservation.released,
+        }
+    }
+
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(Cell::new(&self.id.to_string()));
+        cells.push(Cell::new(&self.owner));
+        cells.push(Cell::new(&vec_to_string(&self.nodes, false)));
+        cells.push(get_cell_content_of_date(&self.start));
+        cells.push(get_cell_content_of_date(&self.end));
+        cells.push(get_cell_content_of_date(&self.released));
+        cells
+    }
+}
@@ -1,9 +1,11 @@
 use crate::architecture::Architecture;
+use crate::placement::Placement;
+use crate::state::{get_state_of_row, State};
 use crate::utils::get_random_name;
 use chrono::{NaiveDateTime, Utc};
 use rusqlite::Row;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use yaml_rust::yaml::Hash;
 use yaml_rust::Yaml;
@@ -22,6 +24,11 @@ pub struct Service {
     pub end: Option<NaiveDateTime>,
     pub node: Option<String>,
     pub architecture: Option<Architecture>,
+    pub node_selector: BTreeMap<String, String>,
+    pub preferences: BTreeMap<String, String>,
+    pub placement: Option<Placement>,
+    pub state: Option<State>,
+    pub image_checksum: Option<String>,
 }
 
 impl Service {
@@ -40,11 +47,18 @@ impl Service {
             end: None,
             node: None,
             architecture: None,
+            node_selector: BTreeMap::new(),
+            preferences: BTreeMap::new(),
+            placement: None,
+            state: None,
+            image_checksum: None,
         }
     }
 
-    #[must_use]
-    pub fn from_yaml(name: &str, hash: &Hash) -> Self {
+    /// # Errors
+    ///
+    /// Will return `Err` if the placement of the service is invalid
+    pub fn from_yaml(name: &str, hash: &Hash) -> Result<Self, String> {
         let image = String::from(
             hash.get(&Yaml::from_str("image"))
                 .unwrap()
@@ -67,7 +81,14 @@ impl Service {
             Some(ip) => Some(ip.as_str().unwrap().to_string()),
             None => None,
         };
-        Service {
+        let placement = match hash.get(&Yaml::from_str("placement")) {
+            Some(p) => match p.as_hash() {
+                Some(p) => Some(Placement::from_yaml(p).map_err(|e| format!("{}: {}", name, e))?),
+                None => return Err(format!("{}: placement needs a label and a policy", name)),
+            },
+            None => None,
+        };
+        Ok(Service {
             id: None,
             name: String::from(name),
             image,
@@ -80,25 +101,42 @@ impl Service {
             end: None,
             node: None,
             architecture: None,
-        }
+            node_selector: get_labels_from_yaml(hash, "node-selector"),
+            preferences: get_labels_from_yaml(hash, "preferences"),
+            placement,
+            state: None,
+            image_checksum: None,
+        })
     }
 
     #[must_use]
     pub fn from_row(row: &Row) -> Self {
-        let arch: String = row.get(9).unwrap();
+        Service::from_row_at(row, 0)
+    }
+
+    /// Reads a service whose columns start at `offset`, e.g. because it was joined to another
+    /// table.
+    #[must_use]
+    pub fn from_row_at(row: &Row, offset: usize) -> Self {
+        let arch: String = row.get(offset + 9).unwrap();
         Service {
-            id: row.get(0).unwrap(),
-            name: row.get(1).unwrap(),
-            image: row.get(2).unwrap(),
-            deployment: row.get(3).unwrap(),
-            node: row.get(4).unwrap(),
-            start: row.get(5).unwrap(),
-            end: row.get(6).unwrap(),
-            ipv4_address: row.get(7).unwrap(),
-            hostname: row.get(8).unwrap(),
+            id: row.get(offset).unwrap(),
+            name: row.get(offset + 1).unwrap(),
+            image: row.get(offset + 2).unwrap(),
+            deployment: row.get(offset + 3).unwrap(),
+            node: row.get(offset + 4).unwrap(),
+            start: row.get(offset + 5).unwrap(),
+            end: row.get(offset + 6).unwrap(),
+            ipv4_address: row.get(offset + 7).unwrap(),
+            hostname: row.get(offset + 8).unwrap(),
             replicas: 1,
             preferred_node: None,
             architecture: Some(Architecture::parse(&arch).unwrap_or(Architecture::ARM64)),
+            node_selector: BTreeMap::new(),
+            preferences: BTreeMap::new(),
+            placement: None,
+            state: get_state_of_row(row, offset + 10),
+            image_checksum: row.get(offset + 11).unwrap_or(None),
         }
     }
 
@@ -123,3 +161,21 @@ impl Service {
         vec
     }
 }
+
+fn get_labels_from_yaml(hash: &Hash, key: &str) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    if let Some(Yaml::Hash(entries)) = hash.get(&Yaml::from_str(key)) {
+        for (label, value) in entries {
+            let value = match value {
+                Yaml::String(s) | Yaml::Real(s) => s.clone(),
+                Yaml::Integer(i) => i.to_string(),
+                Yaml::Boolean(b) => b.to_string(),
+                _ => continue,
+            };
+            if let Some(label) = label.as_str() {
+                labels.insert(label.to_string(), value);
+            }
+        }
+    }
+    labels
+}
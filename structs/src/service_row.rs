@@ -16,6 +16,7 @@ pub struct ServiceRow {
     pub start: Option<NaiveDateTime>,
     pub end: Option<NaiveDateTime>,
     pub replicas: i64,
+    pub state: Option<String>,
 }
 
 impl ServiceRow {
@@ -32,6 +33,7 @@ impl ServiceRow {
             start: Some(service.start),
             end: service.end,
             replicas: service.replicas,
+            state: service.state.as_ref().map(|s| String::from(s.get_name())),
         }
     }
 
@@ -48,6 +50,7 @@ impl ServiceRow {
         cells.push(get_cell_content_of_date(&self.start));
         cells.push(get_cell_content_of_date(&self.end));
         cells.push(Cell::new(&self.replicas.to_string()));
+        cells.push(get_cell_content_of_option(&self.state));
         cells
     }
 }
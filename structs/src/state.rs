@@ -0,0 +1,68 @@
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+/// Names of all states, e.g. for the possible values of a command line filter.
+pub const STATES: [&str; 6] = [
+    "provisioning",
+    "booting",
+    "running",
+    "collecting-results",
+    "finished",
+    "failed",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum State {
+    Provisioning,
+    Booting,
+    Running,
+    CollectingResults,
+    Finished,
+    Failed,
+}
+
+impl State {
+    #[must_use]
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            State::Provisioning => "provisioning",
+            State::Booting => "booting",
+            State::Running => "running",
+            State::CollectingResults => "collecting-results",
+            State::Finished => "finished",
+            State::Failed => "failed",
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if `name` is not a known state
+    pub fn parse(name: &str) -> Result<State, &'static str> {
+        match name {
+            "provisioning" => Ok(State::Provisioning),
+            "booting" => Ok(State::Booting),
+            "running" => Ok(State::Running),
+            "collecting-results" => Ok(State::CollectingResults),
+            "finished" => Ok(State::Finished),
+            "failed" => Ok(State::Failed),
+            _ => Err("not a valid state"),
+        }
+    }
+
+    #[must_use]
+    pub fn is_final(&self) -> bool {
+        matches!(self, State::Finished | State::Failed)
+    }
+
+    #[must_use]
+    pub fn can_transition_to(&self, next: &State) -> bool {
+        !self.is_final() && self.ne(next)
+    }
+}
+
+/// Reads the state stored in column `index`, rows from before states were tracked have none.
+#[must_use]
+pub fn get_state_of_row(row: &Row, index: usize) -> Option<State> {
+    let name: Option<String> = row.get(index).unwrap_or(None);
+    name.and_then(|n| State::parse(&n).ok())
+}
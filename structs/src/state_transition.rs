@@ -0,0 +1,30 @@
+use crate::state::State;
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StateTransition {
+    pub entity: String,
+    pub entity_id: i64,
+    pub from: Option<State>,
+    pub to: State,
+    pub timestamp: NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+impl StateTransition {
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        let from: Option<String> = row.get(3).unwrap();
+        let to: String = row.get(4).unwrap();
+        StateTransition {
+            entity: row.get(1).unwrap(),
+            entity_id: row.get(2).unwrap(),
+            from: from.and_then(|s| State::parse(&s).ok()),
+            to: State::parse(&to).unwrap_or(State::Failed),
+            timestamp: row.get(5).unwrap(),
+            reason: row.get(6).unwrap(),
+        }
+    }
+}
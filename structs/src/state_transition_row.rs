@@ -0,0 +1,40 @@
+use crate::state_transition::StateTransition;
+use crate::utils::{get_cell_content_of_date, get_cell_content_of_option};
+use chrono::NaiveDateTime;
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateTransitionRow {
+    pub entity: String,
+    pub entity_id: i64,
+    pub from: Option<String>,
+    pub to: String,
+    pub timestamp: Option<NaiveDateTime>,
+    pub reason: Option<String>,
+}
+
+impl StateTransitionRow {
+    #[must_use]
+    pub fn new(transition: StateTransition) -> Self {
+        StateTransitionRow {
+            entity: transition.entity,
+            entity_id: transition.entity_id,
+            from: transition.from.map(|s| String::from(s.get_name())),
+            to: String::from(transition.to.get_name()),
+            timestamp: Some(transition.timestamp),
+            reason: transition.reason,
+        }
+    }
+
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(get_cell_content_of_date(&self.timestamp));
+        cells.push(Cell::new(&format!("{} {}", self.entity, self.entity_id)));
+        cells.push(get_cell_content_of_option(&self.from));
+        cells.push(Cell::new(&self.to));
+        cells.push(get_cell_content_of_option(&self.reason));
+        cells
+    }
+}
@@ -11,10 +11,14 @@ pub enum Type {
     DeleteLocalStorage = 2,
     StopIfTrue = 3,
     GetResults = 4,
+    RunCommand = 5,
+    InjectFault = 6,
+    SnapshotResults = 7,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Task {
+    pub id: Option<i64>,
     pub deployment: Option<Deployment>,
     pub service: Option<Service>,
     pub task_type: Type,
@@ -22,6 +26,11 @@ pub struct Task {
     pub start: Option<NaiveDateTime>,
     pub end: Option<NaiveDateTime>,
     pub during_deployment: bool,
+    /// how often the log message of a stop condition has been seen so far
+    pub hits: i64,
+    pub executed: bool,
+    /// `None` as long as the task has not been executed
+    pub success: Option<bool>,
 }
 
 impl Task {
@@ -34,6 +43,7 @@ impl Task {
         during_deployment: bool,
     ) -> Self {
         Task {
+            id: None,
             deployment,
             service,
             task_type,
@@ -41,12 +51,16 @@ impl Task {
             during_deployment,
             start: None,
             end: None,
+            hits: 0,
+            executed: false,
+            success: None,
         }
     }
 
     #[must_use]
     pub fn from_row(deployment: Deployment, service: Option<Service>, row: &Row) -> Self {
         Task {
+            id: row.get(0).unwrap(),
             deployment: Some(deployment),
             service,
             task_type: from_db_to_type(row.get(3).unwrap()),
@@ -54,6 +68,27 @@ impl Task {
             during_deployment: row.get(5).unwrap(),
             start: row.get(6).unwrap(),
             end: row.get(7).unwrap(),
+            hits: row.get(8).unwrap_or(0),
+            executed: row.get(9).unwrap_or(false),
+            success: row.get(10).unwrap_or(None),
+        }
+    }
+}
+
+impl Type {
+    /// Parses the `local-storage` setting of a deployment.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `name` is neither `purge` nor `delete`
+    pub fn parse_local_storage_cleanup(name: &str) -> Result<Type, String> {
+        match name {
+            "purge" => Ok(Type::PurgeLocalStorage),
+            "delete" => Ok(Type::DeleteLocalStorage),
+            _ => Err(format!(
+                "local-storage {} is not supported, use purge or delete",
+                name
+            )),
         }
     }
 }
@@ -64,6 +99,9 @@ fn from_db_to_type(id: usize) -> Type {
         2 => Type::DeleteLocalStorage,
         3 => Type::StopIfTrue,
         4 => Type::GetResults,
+        5 => Type::RunCommand,
+        6 => Type::InjectFault,
+        7 => Type::SnapshotResults,
         _ => Type::NoOp,
     }
 }
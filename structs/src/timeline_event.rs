@@ -0,0 +1,24 @@
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+/// Something that was done to a running deployment, e.g. an injected fault.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimelineEvent {
+    pub deployment: i64,
+    pub service: Option<i64>,
+    pub timestamp: NaiveDateTime,
+    pub message: String,
+}
+
+impl TimelineEvent {
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        TimelineEvent {
+            deployment: row.get(1).unwrap(),
+            service: row.get(2).unwrap(),
+            timestamp: row.get(3).unwrap(),
+            message: row.get(4).unwrap(),
+        }
+    }
+}
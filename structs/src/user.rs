@@ -0,0 +1,35 @@
+use chrono::NaiveDateTime;
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct User {
+    pub name: String,
+    pub admin: bool,
+    pub created: Option<NaiveDateTime>,
+}
+
+impl User {
+    #[must_use]
+    pub fn new(name: &str, admin: bool) -> Self {
+        User {
+            name: String::from(name),
+            admin,
+            created: None,
+        }
+    }
+
+    #[must_use]
+    pub fn from_row(row: &Row) -> Self {
+        User {
+            name: row.get(0).unwrap(),
+            admin: row.get(2).unwrap(),
+            created: row.get(3).unwrap(),
+        }
+    }
+
+    #[must_use]
+    pub fn may_manage(&self, owner: &str) -> bool {
+        self.admin || self.name.eq(owner)
+    }
+}
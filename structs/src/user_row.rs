@@ -0,0 +1,32 @@
+use crate::user::User;
+use crate::utils::get_cell_content_of_date;
+use chrono::NaiveDateTime;
+use prettytable::Cell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserRow {
+    pub name: String,
+    pub admin: bool,
+    pub created: Option<NaiveDateTime>,
+}
+
+impl UserRow {
+    #[must_use]
+    pub fn new(user: User) -> Self {
+        UserRow {
+            name: user.name,
+            admin: user.admin,
+            created: user.created,
+        }
+    }
+
+    #[must_use]
+    pub fn get_cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<prettytable::Cell> = Vec::new();
+        cells.push(Cell::new(&self.name));
+        cells.push(Cell::new(if self.admin { "yes" } else { "no" }));
+        cells.push(get_cell_content_of_date(&self.created));
+        cells
+    }
+}
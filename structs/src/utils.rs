@@ -1,12 +1,15 @@
-use chrono::NaiveDateTime;
+use chrono::{Local, NaiveDateTime, TimeZone};
 use colored::Colorize;
 use names::Generator;
 use prettytable::Cell;
+use std::cell::RefCell;
+use std::env;
 use std::fs::OpenOptions;
 use std::io;
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use std::process::Command;
+use std::sync::Arc;
 use std::{format, fs, panic, print, println, str, usize};
 use string_builder::Builder;
 
@@ -73,6 +76,26 @@ pub fn get_random_name() -> String {
     generator.next().unwrap()
 }
 
+#[must_use]
+pub fn get_current_user() -> String {
+    env::var("SUDO_USER")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
+/// # Errors
+///
+/// Will return `Err` if `value` is not formatted as `%Y-%m-%d %H:%M`
+pub fn parse_local_datetime(value: &str) -> Result<NaiveDateTime, String> {
+    match NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M") {
+        Ok(datetime) => match Local.from_local_datetime(&datetime).single() {
+            Some(local) => Ok(local.naive_utc()),
+            None => Err(format!("{} is ambiguous in the local timezone", value)),
+        },
+        Err(_) => Err(format!("{} is not formatted as YYYY-MM-DD HH:MM", value)),
+    }
+}
+
 #[must_use]
 pub fn get_ok_or_error(result: bool) -> String {
     if result {
@@ -85,12 +108,51 @@ pub fn print_with_offset(str: &str) {
     println!("[      ]: {}", str)
 }
 
+pub type StepSink = Arc<dyn Fn(&str, Option<bool>) + Send + Sync>;
+
+thread_local! {
+    static STEP_SINK: RefCell<Option<StepSink>> = RefCell::new(None);
+}
+
+/// Forwards everything `print_message` and `print_information` print on the current thread to
+/// `sink` as well, `None` stops forwarding.
+pub fn set_step_sink(sink: Option<StepSink>) {
+    STEP_SINK.with(|s| *s.borrow_mut() = sink);
+}
+
+/// Returns where the current thread forwards its messages to, so threads it spawns can do the same.
+#[must_use]
+pub fn get_step_sink() -> Option<StepSink> {
+    STEP_SINK.with(|s| s.borrow().clone())
+}
+
+/// Runs `work` on the current thread while forwarding its messages to `sink`.
+pub fn with_step_sink<F, R>(sink: Option<StepSink>, work: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let previous = STEP_SINK.with(|s| s.replace(sink));
+    let result = work();
+    set_step_sink(previous);
+    result
+}
+
+fn forward_step(message: &str, status: Option<bool>) {
+    STEP_SINK.with(|s| {
+        if let Some(sink) = s.borrow().as_ref() {
+            sink(message, status);
+        }
+    });
+}
+
 pub fn print_information(str: &str) {
     println!("{}", format!("[{}]: {}", " INFO ".cyan(), str));
+    forward_step(str, None);
 }
 
 pub fn print_message(message: &str, status: bool) {
     println!("{}: {}", get_ok_or_error(status), message);
+    forward_step(message, Some(status));
 }
 
 /// # Errors